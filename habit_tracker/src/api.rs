@@ -0,0 +1,326 @@
+use crate::habit::{CheckIn, Frequency, Habit};
+use crate::stats::{self, Summary};
+use crate::storage::Storage;
+use crate::todo::Todo;
+use chrono::{NaiveDate, NaiveTime};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io;
+use std::path::Path;
+use tiny_http::{Header, Method, Request, Response, Server};
+
+pub const DEFAULT_ADDR: &str = "127.0.0.1:8766";
+
+/// Where the API listens.
+pub enum Listen<'a> {
+    Tcp(&'a str),
+    #[cfg(unix)]
+    Unix(&'a Path),
+}
+
+/// Serves the local API until the process is stopped. Every request reads
+/// the data afresh and writes changes straight back, so a running UI picks
+/// them up like any other change on disk, and sees the UI's own edits.
+///
+/// - `GET /habits[?date=D]`: habits with their state on `D` (default today)
+/// - `POST /habits/check`, `POST /habits/uncheck`: body
+///   `{"habit": ..., "category": ..., "date": ...}`, category and date optional
+/// - `GET /todos`, `POST /todos`: body `{"description": ..., "priority": "A"}`
+/// - `GET /stats[?date=D]`: the day's summary
+pub fn serve(listen: Listen, storage: &mut dyn Storage) -> io::Result<()> {
+    let server = match listen {
+        Listen::Tcp(addr) => {
+            let server = Server::http(addr).map_err(io::Error::other)?;
+            println!("API listening on http://{}", addr);
+            server
+        }
+        #[cfg(unix)]
+        Listen::Unix(path) => {
+            use std::os::unix::fs::FileTypeExt;
+            // Left behind by an earlier run; anything else is not ours to
+            // delete.
+            match std::fs::symlink_metadata(path) {
+                Ok(metadata) if metadata.file_type().is_socket() => std::fs::remove_file(path)?,
+                Ok(_) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::AlreadyExists,
+                        format!("{} exists and is not a socket", path.display()),
+                    ))
+                }
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e),
+            }
+            let server = Server::http_unix(path).map_err(io::Error::other)?;
+            println!("API listening on {}", path.display());
+            server
+        }
+    };
+    for request in server.incoming_requests() {
+        respond(request, storage);
+    }
+    Ok(())
+}
+
+fn respond(mut request: Request, storage: &mut dyn Storage) {
+    let (status, body) = match handle(&mut request, storage) {
+        Ok((status, body)) => (status, body),
+        Err(ApiError(status, message)) => {
+            (status, serde_json::json!({ "error": message }).to_string())
+        }
+    };
+    let header = Header::from_bytes("Content-Type", "application/json").expect("valid header");
+    let response = Response::from_string(body)
+        .with_status_code(status)
+        .with_header(header);
+    if let Err(err) = request.respond(response) {
+        eprintln!("Could not respond: {}", err);
+    }
+}
+
+struct ApiError(u16, String);
+
+impl From<io::Error> for ApiError {
+    fn from(err: io::Error) -> Self {
+        ApiError(500, err.to_string())
+    }
+}
+
+impl From<serde_json::Error> for ApiError {
+    fn from(err: serde_json::Error) -> Self {
+        ApiError(400, err.to_string())
+    }
+}
+
+#[derive(Serialize)]
+struct HabitView<'a> {
+    name: &'a str,
+    category: &'a str,
+    frequency: Frequency,
+    done: bool,
+    streak: u32,
+    completed_dates: &'a [NaiveDate],
+    reminders: &'a [NaiveTime],
+    paused: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    category_color: Option<&'a str>,
+    check_ins: &'a BTreeMap<NaiveDate, CheckIn>,
+}
+
+#[derive(Deserialize)]
+struct CheckRequest {
+    habit: String,
+    category: Option<String>,
+    date: Option<NaiveDate>,
+}
+
+#[derive(Deserialize)]
+struct TodoRequest {
+    description: String,
+    priority: Option<char>,
+}
+
+fn handle(request: &mut Request, storage: &mut dyn Storage) -> Result<(u16, String), ApiError> {
+    let url = request.url().to_string();
+    let (path, query) = url.split_once('?').unwrap_or((&url, ""));
+    let today = crate::clock::today();
+    let date = match query_param(query, "date") {
+        Some(date) => NaiveDate::parse_from_str(date, "%Y-%m-%d")
+            .map_err(|e| ApiError(400, format!("bad date: {}", e)))?,
+        None => today,
+    };
+
+    let method = request.method().clone();
+    match (method, path.trim_end_matches('/')) {
+        (Method::Get, "/habits") => {
+            let habits = storage.load_habits()?;
+            let colors = storage.load_category_colors()?;
+            let views: Vec<HabitView> = habits
+                .iter()
+                .map(|h| HabitView {
+                    name: &h.name,
+                    category: &h.category,
+                    frequency: h.frequency,
+                    done: h.is_completed(date),
+                    streak: stats::live_streak(h, date),
+                    completed_dates: h.completed_dates(),
+                    reminders: &h.reminders,
+                    paused: h.paused,
+                    category_color: colors.get(&h.category).map(String::as_str),
+                    check_ins: h.check_ins(),
+                })
+                .collect();
+            Ok((200, serde_json::to_string(&views)?))
+        }
+        (Method::Post, action @ ("/habits/check" | "/habits/uncheck")) => {
+            let body: CheckRequest = serde_json::from_reader(request.as_reader())?;
+            let mut habits = storage.load_habits()?;
+            let index = find_habit(&habits, &body.habit, body.category.as_deref())?;
+            let done = action == "/habits/check";
+            let date = body.date.unwrap_or(today);
+            let check_in = done.then(|| crate::clock::check_in_for(date)).flatten();
+            habits[index].set_completed(date, done, check_in);
+            if !storage.record_check_in(&habits[index], date)? {
+                storage.save_habits(&habits)?;
+            }
+            let summary = stats::summarize(&storage.load_habits()?, &storage.load_todos()?, date);
+            Ok((200, serde_json::to_string(&summary)?))
+        }
+        (Method::Get, "/todos") => Ok((200, serde_json::to_string(&storage.load_todos()?)?)),
+        (Method::Post, "/todos") => {
+            let body: TodoRequest = serde_json::from_reader(request.as_reader())?;
+            if body.description.trim().is_empty() {
+                return Err(ApiError(400, "description is empty".to_string()));
+            }
+            let mut todos = storage.load_todos()?;
+            if todos.iter().any(|t| t.description == body.description) {
+                return Err(ApiError(
+                    409,
+                    "a todo with this description exists".to_string(),
+                ));
+            }
+            let mut todo = Todo::new(body.description);
+            todo.priority = body.priority.map(|p| p.to_ascii_uppercase());
+            todos.push(todo.clone());
+            storage.save_todos(&todos)?;
+            Ok((201, serde_json::to_string(&todo)?))
+        }
+        (Method::Get, "/stats") => {
+            let summary: Summary =
+                stats::summarize(&storage.load_habits()?, &storage.load_todos()?, date);
+            Ok((200, serde_json::to_string(&summary)?))
+        }
+        _ => Err(ApiError(404, format!("no such endpoint: {}", path))),
+    }
+}
+
+/// The index of the habit called `name`, which must be unambiguous when no
+/// category is given.
+fn find_habit(habits: &[Habit], name: &str, category: Option<&str>) -> Result<usize, ApiError> {
+    let matches: Vec<usize> = (0..habits.len())
+        .filter(|&i| habits[i].name == name && category.is_none_or(|c| habits[i].category == c))
+        .collect();
+    match matches.as_slice() {
+        [index] => Ok(*index),
+        [] => Err(ApiError(404, format!("no habit named {}", name))),
+        _ => Err(ApiError(
+            400,
+            format!("{} exists in several categories; give one", name),
+        )),
+    }
+}
+
+fn query_param<'a>(query: &'a str, name: &str) -> Option<&'a str> {
+    query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::JsonStorage;
+    use serde_json::Value;
+    use std::fs;
+    use std::path::PathBuf;
+    use std::thread;
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("habit_tracker-api-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn habit(name: &str, category: &str) -> Habit {
+        Habit::new(name.into(), category.into(), Frequency::Daily)
+    }
+
+    /// Serves `count` requests from a store in `dir` on a free port.
+    fn start(dir: &Path, count: usize) -> (String, thread::JoinHandle<()>) {
+        let server = Server::http("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", server.server_addr());
+        let mut storage = JsonStorage::new(dir.join("habits.json"), dir.join("todos.json"));
+        let handle = thread::spawn(move || {
+            for request in server.incoming_requests().take(count) {
+                respond(request, &mut storage);
+            }
+        });
+        (url, handle)
+    }
+
+    fn status_and_body(result: Result<ureq::Response, ureq::Error>) -> (u16, Value) {
+        let response = match result {
+            Ok(response) => response,
+            Err(ureq::Error::Status(_, response)) => response,
+            Err(err) => panic!("{}", err),
+        };
+        (response.status(), response.into_json().unwrap())
+    }
+
+    #[test]
+    fn checks_habits_and_adds_todos_over_http() {
+        let dir = scratch_dir("round-trip");
+        let mut storage = JsonStorage::new(dir.join("habits.json"), dir.join("todos.json"));
+        storage
+            .save_habits(&[habit("Run", "Health"), habit("Read", "Work")])
+            .unwrap();
+        let (url, server) = start(&dir, 5);
+
+        let (status, summary) = status_and_body(
+            ureq::post(&format!("{}/habits/check", url))
+                .send_json(serde_json::json!({ "habit": "Run", "date": "2024-05-01" })),
+        );
+        assert_eq!(status, 200);
+        assert_eq!(summary["done"], 1);
+        assert_eq!(summary["total"], 2);
+
+        let (_, habits) =
+            status_and_body(ureq::get(&format!("{}/habits?date=2024-05-01", url)).call());
+        assert_eq!(habits[0]["name"], "Run");
+        assert_eq!(habits[0]["done"], true);
+        assert_eq!(habits[1]["done"], false);
+
+        let (status, error) = status_and_body(
+            ureq::post(&format!("{}/habits/check", url))
+                .send_json(serde_json::json!({ "habit": "Swim" })),
+        );
+        assert_eq!(status, 404);
+        assert_eq!(error["error"], "no habit named Swim");
+
+        let (status, todo) = status_and_body(
+            ureq::post(&format!("{}/todos", url))
+                .send_json(serde_json::json!({ "description": "Call mum", "priority": "b" })),
+        );
+        assert_eq!(status, 201);
+        assert_eq!(todo["priority"], "B");
+        let (_, todos) = status_and_body(ureq::get(&format!("{}/todos", url)).call());
+        assert_eq!(todos[0]["description"], "Call mum");
+
+        server.join().unwrap();
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn a_name_in_several_categories_needs_one() {
+        let habits = [habit("Read", "Work"), habit("Read", "Fun")];
+        let err = find_habit(&habits, "Read", None).err().unwrap();
+        assert_eq!(err.0, 400);
+        assert_eq!(find_habit(&habits, "Read", Some("Fun")).ok(), Some(1));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn never_replaces_a_file_that_is_not_a_socket() {
+        let dir = scratch_dir("socket");
+        let path = dir.join("habits.json");
+        fs::write(&path, "[]").unwrap();
+        let mut storage = JsonStorage::new(dir.join("h.json"), dir.join("t.json"));
+        let err = serve(Listen::Unix(&path), &mut storage).unwrap_err();
+        assert!(err.to_string().contains("is not a socket"));
+        assert_eq!(fs::read_to_string(&path).unwrap(), "[]");
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,642 @@
+use crate::api::{self, Listen};
+use crate::clock;
+use crate::config::{Backend, Config};
+use crate::crypto;
+use crate::daemon::{self, NotifySend};
+use crate::eventlog::{self, EventLogStorage};
+use crate::habit::{self, Habit};
+use crate::habit_csv::{self, Conflict, Layout};
+use crate::ical;
+use crate::importers::{self, Imported};
+use crate::prompt::{self, Style};
+use crate::remote;
+use crate::report::{self, Format, Period};
+use crate::server;
+use crate::stats;
+use crate::storage;
+use crate::sync;
+use crate::theme;
+use crate::todo;
+use crate::ui::Icons;
+use chrono::{NaiveDate, NaiveTime};
+use std::error::Error;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+const USAGE: &str = "\
+Usage: habit_tracker [COMMAND]
+
+Without a command the terminal UI is started.
+
+Commands:
+  export todotxt [FILE]   Write todos in todo.txt format (stdout by default)
+  import todotxt [FILE]   Add todos from a todo.txt file (stdin by default)
+  export csv [--wide] [FILE]
+                          Write habit history as CSV, one row per check-in
+                          or (with --wide) one row per day
+  import csv [--on-conflict merge|overwrite|skip] [FILE]
+                          Add habit history from either CSV layout
+  import loop PATH        Add habits from a Loop Habit Tracker backup (a .db
+                          file or an unzipped CSV export directory)
+  import habitica FILE    Add dailies, habits and to-dos from a Habitica export
+  export ics [FILE]       Write habits and todos as an iCalendar file
+  report [week|month|year] [--html] [--date YYYY-MM-DD] [FILE]
+                          Write a review of the period in Markdown or HTML
+  remind HABIT [CATEGORY] TIME...
+                          Set the times of day (HH:MM) to be reminded of a
+                          habit not done yet; `off` instead of times removes
+                          them
+  pause HABIT [CATEGORY]  Stop reminders for a habit
+  resume HABIT [CATEGORY] Start them again
+  color CATEGORY [COLOR]  Show a category in COLOR (a name, #rrggbb or
+                          0-255), or in the theme's colors again
+  daemon                  Show desktop notifications for reminders and todos
+                          due soon, until stopped
+  prompt [--format FMT] [--style STYLE]
+                          Print a one-line summary of today for shell
+                          prompts and status bars. FMT may use {done},
+                          {total}, {percent}, {streak}, {todos} and
+                          {overdue}; STYLE is text, json, waybar, i3,
+                          polybar or tmux
+  sync                    Sync habits with the configured server and, with
+                          `git_sync` set or no server configured, commit the
+                          data files, merge the remote's changes and push
+  sync init [URL]         Make the data directory a git repository, with
+                          URL as the remote to sync with
+  api [--addr ADDR | --socket PATH]
+                          Serve a JSON API for scripts and status bars on
+                          127.0.0.1:8766 or a Unix socket
+  replica export [FILE]   Write this copy's replicated habit state
+  replica merge FILE      Merge in the state exported from another copy
+  serve [--addr ADDR] [--dir DIR]
+                          Run a sync server (default 127.0.0.1:8765, storing
+                          data in ./server-data)
+  encrypt                 Encrypt the data files with a new passphrase
+  decrypt                 Decrypt the data files and turn encryption off
+  unlock                  Remember the passphrase's key until `lock` or
+                          logout, so commands don't ask for it
+  lock                    Forget the remembered key
+  history HABIT [CATEGORY]
+                          Show every recorded change to a habit (log backend)
+  compact                 Fold the event log into its snapshot (log backend)
+  migrate FROM TO         Copy all data between storage backends (json,
+                          sqlite, log)
+  help                    Show this message";
+
+pub fn run(args: &[String], config: &Config) -> Result<(), Box<dyn Error>> {
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    match args.as_slice() {
+        ["export", "todotxt", rest @ ..] => export_todotxt(config, rest.first().copied()),
+        ["import", "todotxt", rest @ ..] => import_todotxt(config, rest.first().copied()),
+        ["export", "csv", rest @ ..] => export_csv(config, rest.to_vec()),
+        ["import", "csv", rest @ ..] => import_csv(config, rest.to_vec()),
+        ["import", "loop", path] => import_from(config, importers::loop_backup(Path::new(path))?),
+        ["import", "habitica", rest @ ..] => import_from(
+            config,
+            importers::habitica(&read_input(single_path(rest)?)?)?,
+        ),
+        ["export", "ics", rest @ ..] => export_ics(config, single_path(rest)?),
+        ["report", rest @ ..] => report(config, rest.to_vec()),
+        ["remind", name, rest @ ..] => remind(config, name, rest),
+        ["pause", name, rest @ ..] => set_paused(config, name, single_path(rest)?, true),
+        ["resume", name, rest @ ..] => set_paused(config, name, single_path(rest)?, false),
+        ["color", category, rest @ ..] => color_category(config, category, single_path(rest)?),
+        ["daemon"] => {
+            let storage = storage::open(config)?;
+            daemon::run(&config.daemon, storage.as_ref(), &mut NotifySend)?;
+            Ok(())
+        }
+        ["prompt", rest @ ..] => prompt(config, rest.to_vec()),
+        ["sync"] => sync(config),
+        ["api", rest @ ..] => api(config, rest.to_vec()),
+        ["replica", "export", rest @ ..] => export_replica(config, single_path(rest)?),
+        ["replica", "merge", path] => merge_replica(config, path),
+        ["serve", rest @ ..] => serve(rest.to_vec()),
+        ["sync", "init", rest @ ..] => sync_init(config, single_path(rest)?),
+        // Invoked by git, as registered by `sync init`.
+        ["merge-driver", base, ours, theirs, rest @ ..] => Ok(sync::merge_driver(
+            Path::new(base),
+            Path::new(ours),
+            Path::new(theirs),
+            Path::new(rest.first().unwrap_or(ours)),
+        )?),
+        ["encrypt"] => encrypt(config),
+        ["decrypt"] => decrypt(),
+        ["unlock"] => unlock(),
+        ["lock"] => {
+            crypto::forget_key(Path::new("."))?;
+            Ok(())
+        }
+        ["history", name] => history(config, name, None),
+        ["history", name, category] => history(config, name, Some(category)),
+        ["compact"] => compact(config),
+        ["migrate", from, to] => migrate(from.parse()?, to.parse()?),
+        ["help"] | ["--help"] | ["-h"] => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+        _ => {
+            eprintln!("{}", USAGE);
+            Err(format!("unknown command: {}", args.join(" ")).into())
+        }
+    }
+}
+
+fn export_todotxt(config: &Config, path: Option<&str>) -> Result<(), Box<dyn Error>> {
+    let todos = storage::open(config)?.load_todos()?;
+    write_output(path, &todo::to_todotxt(&todos))?;
+    Ok(())
+}
+
+fn import_todotxt(config: &Config, path: Option<&str>) -> Result<(), Box<dyn Error>> {
+    let imported = todo::parse_todotxt(&read_input(path)?);
+    let mut storage = storage::open(config)?;
+    let mut todos = storage.load_todos()?;
+    let total = imported.len();
+    let mut added = 0;
+    for new_todo in imported {
+        // Todos are identified by their description everywhere else too.
+        if !todos.iter().any(|t| t.description == new_todo.description) {
+            todos.push(new_todo);
+            added += 1;
+        }
+    }
+    storage.save_todos(&todos)?;
+    println!(
+        "Imported {} todos ({} already present)",
+        added,
+        total - added
+    );
+    Ok(())
+}
+
+fn export_csv(config: &Config, mut args: Vec<&str>) -> Result<(), Box<dyn Error>> {
+    let layout = if take_flag(&mut args, "--wide") {
+        Layout::Wide
+    } else {
+        Layout::Long
+    };
+    let habits = storage::open(config)?.load_habits()?;
+    write_output(single_path(&args)?, &habit_csv::export(&habits, layout)?)?;
+    Ok(())
+}
+
+fn import_csv(config: &Config, mut args: Vec<&str>) -> Result<(), Box<dyn Error>> {
+    let conflict = match take_option(&mut args, "--on-conflict")? {
+        Some(mode) => mode.parse()?,
+        None => Conflict::Merge,
+    };
+    let contents = read_input(single_path(&args)?)?;
+    let mut storage = storage::open(config)?;
+    let mut habits = storage.load_habits()?;
+    let summary = habit_csv::import(&mut habits, &contents, conflict)?;
+    storage.save_habits(&habits)?;
+    for row in &summary.bad_rows {
+        eprintln!("Ignored {}", row);
+    }
+    println!("Imported: {}", summary);
+    Ok(())
+}
+
+fn import_from(config: &Config, imported: Imported) -> Result<(), Box<dyn Error>> {
+    let mut storage = storage::open(config)?;
+    let mut habits = storage.load_habits()?;
+    let mut todos = storage.load_todos()?;
+    let unmapped = imported.unmapped.clone();
+    let (new_habits, new_todos) = imported.merge_into(&mut habits, &mut todos);
+    storage.save_habits(&habits)?;
+    storage.save_todos(&todos)?;
+    println!(
+        "Imported {} new habits and {} new todos",
+        new_habits, new_todos
+    );
+    if !unmapped.is_empty() {
+        println!("Not carried over exactly:");
+        for note in unmapped {
+            println!("  {}", note);
+        }
+    }
+    Ok(())
+}
+
+fn export_ics(config: &Config, path: Option<&str>) -> Result<(), Box<dyn Error>> {
+    let storage = storage::open(config)?;
+    let today = clock::today();
+    let calendar = ical::export(&storage.load_habits()?, &storage.load_todos()?, today);
+    write_output(path, &calendar)?;
+    Ok(())
+}
+
+fn report(config: &Config, mut args: Vec<&str>) -> Result<(), Box<dyn Error>> {
+    let format = if take_flag(&mut args, "--html") {
+        Format::Html
+    } else {
+        Format::Markdown
+    };
+    let today = clock::today();
+    let date = match take_option(&mut args, "--date")? {
+        Some(date) => NaiveDate::parse_from_str(date, "%Y-%m-%d")?,
+        None => today,
+    };
+    let period = match args.first().map(|arg| arg.parse::<Period>()) {
+        Some(Ok(period)) => {
+            args.remove(0);
+            period
+        }
+        _ => Period::Week,
+    };
+    let storage = storage::open(config)?;
+    let report = report::render(
+        &storage.load_habits()?,
+        &storage.load_todos()?,
+        period,
+        date,
+        today,
+        format,
+    );
+    write_output(single_path(&args)?, &report)?;
+    Ok(())
+}
+
+fn remind(config: &Config, name: &str, args: &[&str]) -> Result<(), Box<dyn Error>> {
+    let is_time = |arg: &&str| *arg == "off" || parse_time(arg).is_ok();
+    let (category, times) = match args.split_first() {
+        Some((category, times)) if !is_time(category) => (Some(*category), times),
+        _ => (None, args),
+    };
+    let reminders = match times {
+        [] => return Err("give the reminder times, as HH:MM, or `off`".into()),
+        ["off"] => Vec::new(),
+        times => {
+            let mut reminders = times
+                .iter()
+                .map(|time| parse_time(time))
+                .collect::<Result<Vec<_>, _>>()?;
+            reminders.sort_unstable();
+            reminders.dedup();
+            reminders
+        }
+    };
+    update_habit(config, name, category, |habit| habit.reminders = reminders)
+}
+
+fn parse_time(time: &str) -> Result<NaiveTime, String> {
+    NaiveTime::parse_from_str(time, "%H:%M").map_err(|_| format!("not a time (HH:MM): {}", time))
+}
+
+fn set_paused(
+    config: &Config,
+    name: &str,
+    category: Option<&str>,
+    paused: bool,
+) -> Result<(), Box<dyn Error>> {
+    update_habit(config, name, category, |habit| habit.paused = paused)
+}
+
+fn color_category(
+    config: &Config,
+    category: &str,
+    color: Option<&str>,
+) -> Result<(), Box<dyn Error>> {
+    if let Some(color) = color {
+        theme::parse_color(color)?;
+    }
+    let mut storage = storage::open(config)?;
+    if !storage
+        .load_habits()?
+        .iter()
+        .any(|h| h.category == category)
+    {
+        return Err(format!("no category named {}", category).into());
+    }
+    let mut colors = storage.load_category_colors()?;
+    habit::set_category_color(&mut colors, category, color);
+    storage.save_category_colors(&colors)?;
+    Ok(())
+}
+
+/// Applies `change` to the habit called `name`, which must be unambiguous
+/// when no category is given, and saves it.
+fn update_habit(
+    config: &Config,
+    name: &str,
+    category: Option<&str>,
+    change: impl FnOnce(&mut Habit),
+) -> Result<(), Box<dyn Error>> {
+    let mut storage = storage::open(config)?;
+    let mut habits = storage.load_habits()?;
+    let mut matches = habits
+        .iter_mut()
+        .filter(|h| h.name == name && category.is_none_or(|c| h.category == c));
+    let habit = match (matches.next(), matches.next()) {
+        (Some(habit), None) => habit,
+        (None, _) => return Err(format!("no habit named {}", name).into()),
+        (Some(_), Some(_)) => {
+            return Err(format!("{} exists in several categories; give one", name).into())
+        }
+    };
+    change(habit);
+    storage.save_habits(&habits)?;
+    Ok(())
+}
+
+/// Kept to what a shell prompt can afford on every command: no sync, no
+/// lock and never a passphrase prompt.
+fn prompt(config: &Config, mut args: Vec<&str>) -> Result<(), Box<dyn Error>> {
+    let default_format = match config.icons {
+        Icons::Emoji => prompt::DEFAULT_FORMAT,
+        Icons::Ascii => prompt::ASCII_FORMAT,
+    };
+    let format = take_option(&mut args, "--format")?
+        .or(config.prompt_format.as_deref())
+        .unwrap_or(default_format);
+    let style = match take_option(&mut args, "--style")? {
+        Some(style) => style.parse()?,
+        None => Style::Text,
+    };
+    single_path(&args)?.map_or(Ok(()), |arg| Err(format!("unexpected argument: {}", arg)))?;
+    if !crypto::is_unlocked(Path::new(".")) {
+        match config.icons {
+            Icons::Emoji => println!("🔒"),
+            Icons::Ascii => println!("locked"),
+        }
+        return Ok(());
+    }
+    let storage = storage::open(config)?;
+    let today = clock::today();
+    let summary = stats::summarize(&storage.load_habits()?, &storage.load_todos()?, today);
+    let text = prompt::format(&summary, format)?;
+    println!("{}", prompt::render(&summary, &text, style));
+    Ok(())
+}
+
+fn sync(config: &Config) -> Result<(), Box<dyn Error>> {
+    let mut storage = storage::open(config)?;
+    if let Some(url) = &config.sync_server {
+        let mut habits = storage.load_habits()?;
+        let mut colors = storage.load_category_colors()?;
+        let state_file = Path::new(remote::STATE_FILE);
+        match remote::sync(
+            &mut habits,
+            &mut colors,
+            state_file,
+            url,
+            config.sync_user(),
+        )? {
+            remote::Outcome::Synced => println!("Synced with {}", url),
+            remote::Outcome::Offline(pending) => {
+                println!("{} unreachable; {} changes queued", url, pending)
+            }
+        }
+        storage.save_habits(&habits)?;
+        storage.save_category_colors(&colors)?;
+    }
+    if config.git_sync || config.sync_server.is_none() {
+        let files = storage.watched_files();
+        sync::sync(Path::new("."), &files, "Sync from the command line")?;
+        println!("Synced with git");
+    }
+    Ok(())
+}
+
+fn api(config: &Config, mut args: Vec<&str>) -> Result<(), Box<dyn Error>> {
+    let addr = take_option(&mut args, "--addr")?;
+    let socket = take_option(&mut args, "--socket")?;
+    if !args.is_empty() {
+        return Err(format!("unexpected arguments: {}", args.join(" ")).into());
+    }
+    let listen = match (addr, socket) {
+        (Some(_), Some(_)) => return Err("give either --addr or --socket".into()),
+        #[cfg(unix)]
+        (None, Some(path)) => Listen::Unix(Path::new(path)),
+        #[cfg(not(unix))]
+        (None, Some(_)) => return Err("Unix sockets are not available here".into()),
+        (addr, None) => Listen::Tcp(addr.unwrap_or(api::DEFAULT_ADDR)),
+    };
+    let mut storage = storage::open(config)?;
+    api::serve(listen, storage.as_mut())?;
+    Ok(())
+}
+
+fn export_replica(config: &Config, path: Option<&str>) -> Result<(), Box<dyn Error>> {
+    let mut storage = storage::open(config)?;
+    let mut habits = storage.load_habits()?;
+    let colors = storage.load_category_colors()?;
+    let replica = remote::export_replica(&mut habits, &colors, Path::new(remote::STATE_FILE))?;
+    // New habits were given ids, which have to be kept to match them later.
+    storage.save_habits(&habits)?;
+    write_output(path, &serde_json::to_string_pretty(&replica)?)?;
+    Ok(())
+}
+
+fn merge_replica(config: &Config, path: &str) -> Result<(), Box<dyn Error>> {
+    let other = serde_json::from_str(&fs::read_to_string(path)?)?;
+    let mut storage = storage::open(config)?;
+    let mut habits = storage.load_habits()?;
+    let mut colors = storage.load_category_colors()?;
+    remote::merge_replica(
+        &mut habits,
+        &mut colors,
+        Path::new(remote::STATE_FILE),
+        &other,
+    )?;
+    storage.save_habits(&habits)?;
+    storage.save_category_colors(&colors)?;
+    println!("Merged {}", path);
+    Ok(())
+}
+
+fn serve(mut args: Vec<&str>) -> Result<(), Box<dyn Error>> {
+    let addr = take_option(&mut args, "--addr")?.unwrap_or(server::DEFAULT_ADDR);
+    let dir = take_option(&mut args, "--dir")?.unwrap_or("server-data");
+    if !args.is_empty() {
+        return Err(format!("unexpected arguments: {}", args.join(" ")).into());
+    }
+    server::serve(addr, Path::new(dir))?;
+    Ok(())
+}
+
+fn sync_init(config: &Config, url: Option<&str>) -> Result<(), Box<dyn Error>> {
+    let dir = Path::new(".");
+    sync::init(dir, url)?;
+    let files = storage::open(config)?.watched_files();
+    sync::commit(dir, &files, "Start tracking habit data")?;
+    if sync::pull(dir)? {
+        println!("Merged data from the remote");
+    }
+    println!("Sync set up");
+    Ok(())
+}
+
+fn encrypt(config: &Config) -> Result<(), Box<dyn Error>> {
+    let dir = Path::new(".");
+    if config.backend != Backend::Json {
+        return Err("encryption is only supported with the json backend".into());
+    }
+    if config.todotxt_file.is_some() {
+        return Err("a todo.txt file can't be encrypted; unset todotxt_file first".into());
+    }
+    if crypto::is_enabled(dir) {
+        return Err("the data is already encrypted".into());
+    }
+    let passphrase = match std::env::var(crypto::PASSPHRASE_VAR) {
+        Ok(passphrase) => passphrase,
+        Err(_) => {
+            let passphrase = crypto::prompt("New passphrase: ")?;
+            if crypto::prompt("Repeat passphrase: ")? != passphrase {
+                return Err("the passphrases differ".into());
+            }
+            passphrase
+        }
+    };
+    if passphrase.is_empty() {
+        return Err("the passphrase is empty".into());
+    }
+    let cipher = crypto::enable(dir, &passphrase)?;
+    let count = storage::convert_encryption(None, Some(&cipher))?;
+    println!("Encrypted {} files, including backups", count);
+    Ok(())
+}
+
+fn decrypt() -> Result<(), Box<dyn Error>> {
+    let dir = Path::new(".");
+    let cipher = crypto::unlock(dir)?.ok_or("the data is not encrypted")?;
+    let count = storage::convert_encryption(Some(&cipher), None)?;
+    crypto::disable(dir)?;
+    println!("Decrypted {} files, including backups", count);
+    Ok(())
+}
+
+fn unlock() -> Result<(), Box<dyn Error>> {
+    let dir = Path::new(".");
+    let cipher = crypto::unlock(dir)?.ok_or("the data is not encrypted")?;
+    let path = crypto::cache_key(dir, &cipher)?;
+    println!("Key kept in {} until `habit_tracker lock`", path.display());
+    Ok(())
+}
+
+fn event_log(config: &Config) -> Result<EventLogStorage, Box<dyn Error>> {
+    if config.backend != Backend::Log {
+        return Err("the event log is only kept with `backend = \"log\"`".into());
+    }
+    Ok(EventLogStorage::new(Path::new(".")))
+}
+
+fn history(config: &Config, name: &str, category: Option<&str>) -> Result<(), Box<dyn Error>> {
+    let entries = event_log(config)?.entries()?;
+    let history = eventlog::history(&entries, name, category);
+    if history.is_empty() {
+        println!(
+            "No recorded changes to {} since the log was last compacted",
+            name
+        );
+    }
+    for entry in history {
+        let (habit, category) = entry.event.habit().unwrap_or_default();
+        println!(
+            "{}  {:<10} {} ({}): {}",
+            entry.time.format("%Y-%m-%d %H:%M:%S"),
+            entry.actor,
+            habit,
+            category,
+            entry.event
+        );
+    }
+    Ok(())
+}
+
+fn compact(config: &Config) -> Result<(), Box<dyn Error>> {
+    let count = event_log(config)?.compact()?;
+    println!("Folded {} events into {}", count, eventlog::SNAPSHOT_FILE);
+    Ok(())
+}
+
+fn migrate(from: Backend, to: Backend) -> Result<(), Box<dyn Error>> {
+    if from == to {
+        return Err("source and target backends are the same".into());
+    }
+    let source = storage::open_backend(from)?;
+    let habits = source.load_habits()?;
+    let todos = source.load_todos()?;
+    let colors = source.load_category_colors()?;
+
+    let mut target = storage::open_backend(to)?;
+    target.save_habits(&habits)?;
+    target.save_todos(&todos)?;
+    target.save_category_colors(&colors)?;
+    println!(
+        "Copied {} habits and {} todos from {:?} to {:?}",
+        habits.len(),
+        todos.len(),
+        from,
+        to
+    );
+    Ok(())
+}
+
+/// Removes `name` from `args`, returning whether it was there.
+fn take_flag(args: &mut Vec<&str>, name: &str) -> bool {
+    let len = args.len();
+    args.retain(|arg| *arg != name);
+    args.len() != len
+}
+
+/// Removes `name VALUE` from `args`, returning the value.
+fn take_option<'a>(args: &mut Vec<&'a str>, name: &str) -> Result<Option<&'a str>, String> {
+    let Some(index) = args.iter().position(|arg| *arg == name) else {
+        return Ok(None);
+    };
+    if index + 1 >= args.len() {
+        return Err(format!("{} needs a value", name));
+    }
+    let value = args.remove(index + 1);
+    args.remove(index);
+    Ok(Some(value))
+}
+
+/// The optional file argument left after options were taken out.
+fn single_path<'a>(args: &[&'a str]) -> Result<Option<&'a str>, String> {
+    match args {
+        [] => Ok(None),
+        [path] => Ok(Some(path)),
+        _ => Err(format!("unexpected arguments: {}", args.join(" "))),
+    }
+}
+
+/// Reads the whole of `path`, or stdin when no path (or `-`) is given.
+fn read_input(path: Option<&str>) -> io::Result<String> {
+    match path {
+        Some(path) if path != "-" => fs::read_to_string(path),
+        _ => {
+            let mut contents = String::new();
+            io::stdin().read_to_string(&mut contents)?;
+            Ok(contents)
+        }
+    }
+}
+
+/// Writes `contents` to `path`, or stdout when no path (or `-`) is given.
+fn write_output(path: Option<&str>, contents: &str) -> io::Result<()> {
+    match path {
+        Some(path) if path != "-" => fs::write(path, contents),
+        _ => io::stdout().write_all(contents.as_bytes()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn refuses_to_encrypt_while_todos_are_in_a_todotxt_file() {
+        let config = Config {
+            todotxt_file: Some(PathBuf::from("todo.txt")),
+            ..Config::default()
+        };
+        let err = encrypt(&config).unwrap_err();
+        assert!(err.to_string().contains("todotxt_file"), "{}", err);
+        assert!(!crypto::is_enabled(Path::new(".")));
+    }
+}
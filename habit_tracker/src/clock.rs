@@ -0,0 +1,272 @@
+use crate::habit::CheckIn;
+use chrono::{DateTime, Duration, Local, NaiveDate, NaiveDateTime, NaiveTime, Utc, Weekday};
+use chrono_tz::Tz;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::OnceLock;
+
+/// Set once from the config at startup; every "today" goes through here.
+static DAY_START_HOUR: AtomicU32 = AtomicU32::new(0);
+/// Days after Monday that weeks start on.
+static WEEK_START: AtomicU32 = AtomicU32::new(0);
+static HOME_ZONE: OnceLock<Zone> = OnceLock::new();
+static LOCAL_ZONE: OnceLock<Zone> = OnceLock::new();
+
+/// A time zone from the tz database, or the system's own when it has no
+/// name there (such as a copied `/etc/localtime`), whose rules chrono reads.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Zone {
+    Named(Tz),
+    System,
+}
+
+const SYSTEM_ZONE: &str = "localtime";
+
+impl Zone {
+    /// A tz database name such as `Europe/Berlin`.
+    pub fn named(name: &str) -> Result<Self, String> {
+        match name {
+            SYSTEM_ZONE => Ok(Zone::System),
+            _ => name
+                .parse()
+                .map(Zone::Named)
+                .map_err(|_| format!("unknown time zone: {}", name)),
+        }
+    }
+
+    /// The zone the system clock is in.
+    fn local() -> Self {
+        let tz_var = std::env::var("TZ").ok();
+        let name = match tz_var.as_deref().map(|tz| tz.trim_start_matches(':')) {
+            Some(name) if !name.is_empty() => Some(name.to_string()),
+            _ => iana_time_zone::get_timezone().ok(),
+        };
+        name.and_then(|name| name.parse().ok())
+            .map_or(Zone::System, Zone::Named)
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            Zone::Named(tz) => tz.name(),
+            Zone::System => SYSTEM_ZONE,
+        }
+    }
+
+    /// The wall clock time in the zone at `time`.
+    pub fn local_time(&self, time: DateTime<Utc>) -> NaiveDateTime {
+        match self {
+            Zone::Named(tz) => time.with_timezone(tz).naive_local(),
+            Zone::System => time.with_timezone(&Local).naive_local(),
+        }
+    }
+}
+
+/// Makes days start at `hour` o'clock instead of midnight, so a habit
+/// checked at 00:30 still counts for the evening before.
+pub fn set_day_start_hour(hour: u32) {
+    DAY_START_HOUR.store(hour, Ordering::Relaxed);
+}
+
+pub fn day_start_hour() -> u32 {
+    DAY_START_HOUR.load(Ordering::Relaxed)
+}
+
+pub fn set_week_start(day: Weekday) {
+    WEEK_START.store(day.num_days_from_monday(), Ordering::Relaxed);
+}
+
+pub fn week_start() -> Weekday {
+    Weekday::try_from(WEEK_START.load(Ordering::Relaxed) as u8).unwrap_or(Weekday::Mon)
+}
+
+/// The day `time` counts for when days start at `day_start_hour`.
+pub fn date_of(time: NaiveDateTime, day_start_hour: u32) -> NaiveDate {
+    (time - Duration::hours(day_start_hour as i64)).date()
+}
+
+/// When the time of day `time` comes round on the day `date`: times before
+/// the day-start hour fall on the next calendar day.
+pub fn instant(date: NaiveDate, time: NaiveTime, day_start_hour: u32) -> NaiveDateTime {
+    let start = NaiveTime::from_hms_opt(day_start_hour, 0, 0).unwrap_or_default();
+    if time < start {
+        date.succ_opt().unwrap_or(date).and_time(time)
+    } else {
+        date.and_time(time)
+    }
+}
+
+/// Makes dates follow `zone` wherever the computer is, so travelling does
+/// not move check-ins to another day. Only the first call has effect.
+pub fn set_home_zone(zone: Zone) {
+    let _ = HOME_ZONE.set(zone);
+}
+
+/// The zone the system clock is in, looked up once.
+pub fn local_zone() -> &'static Zone {
+    LOCAL_ZONE.get_or_init(Zone::local)
+}
+
+/// The wall clock time at `at` that dates go by: in `home` if there is
+/// one, else in `zone`.
+pub fn wall_clock(at: DateTime<Utc>, zone: &Zone, home: Option<&Zone>) -> NaiveDateTime {
+    home.unwrap_or(zone).local_time(at)
+}
+
+/// The day a check-in at `at`, made in `zone`, counts for.
+pub fn logical_date(
+    at: DateTime<Utc>,
+    zone: &Zone,
+    home: Option<&Zone>,
+    day_start_hour: u32,
+) -> NaiveDate {
+    date_of(wall_clock(at, zone, home), day_start_hour)
+}
+
+/// A check-in made now, for marking `date` done: only if it is today, as the
+/// time a past day is filled in says nothing about when it was done.
+pub fn check_in_for(date: NaiveDate) -> Option<CheckIn> {
+    (date == today()).then(check_in_now)
+}
+
+/// A check-in made now, in the local zone.
+fn check_in_now() -> CheckIn {
+    CheckIn {
+        at: Utc::now(),
+        zone: local_zone().name().to_string(),
+    }
+}
+
+/// The wall clock time now, in the home zone if one is set.
+pub fn now() -> NaiveDateTime {
+    wall_clock(Utc::now(), local_zone(), HOME_ZONE.get())
+}
+
+pub fn today() -> NaiveDate {
+    logical_date(Utc::now(), local_zone(), HOME_ZONE.get(), day_start_hour())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn utc(time: &str) -> DateTime<Utc> {
+        NaiveDateTime::parse_from_str(time, "%Y-%m-%d %H:%M")
+            .unwrap()
+            .and_utc()
+    }
+
+    fn date(date: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(date, "%Y-%m-%d").unwrap()
+    }
+
+    fn new_york() -> Zone {
+        Zone::named("America/New_York").unwrap()
+    }
+
+    fn local(time: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(time, "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    fn time(time: &str) -> NaiveTime {
+        NaiveTime::parse_from_str(time, "%H:%M").unwrap()
+    }
+
+    #[test]
+    fn days_turn_over_at_the_day_start_hour() {
+        assert_eq!(date_of(local("2024-05-02 03:59"), 4), date("2024-05-01"));
+        assert_eq!(date_of(local("2024-05-02 04:00"), 4), date("2024-05-02"));
+        assert_eq!(date_of(local("2024-05-01 23:59"), 0), date("2024-05-01"));
+        assert_eq!(date_of(local("2024-05-02 00:00"), 0), date("2024-05-02"));
+        // Across the end of the year.
+        assert_eq!(date_of(local("2025-01-01 03:59"), 4), date("2024-12-31"));
+    }
+
+    #[test]
+    fn times_before_the_day_start_hour_fall_on_the_next_calendar_day() {
+        let day = date("2024-12-31");
+        assert_eq!(instant(day, time("03:59"), 4), local("2025-01-01 03:59"));
+        assert_eq!(instant(day, time("04:00"), 4), local("2024-12-31 04:00"));
+        assert_eq!(instant(day, time("00:00"), 0), local("2024-12-31 00:00"));
+        // Each time of the day counts for the day it was made for.
+        for t in ["00:00", "03:59", "04:00", "12:00", "23:59"] {
+            assert_eq!(date_of(instant(day, time(t), 4), 4), day, "{}", t);
+        }
+    }
+
+    #[test]
+    fn a_time_in_the_spring_forward_gap_still_comes_round() {
+        let zone = new_york();
+        let Zone::Named(tz) = zone else {
+            unreachable!()
+        };
+        // 02:30 on 2024-03-10 never shows on New York clocks.
+        let at = instant(date("2024-03-09"), time("02:30"), 4);
+        assert_eq!(at, local("2024-03-10 02:30"));
+        assert!(tz.from_local_datetime(&at).single().is_none());
+        // It lies between the readings either side of the jump, so a window
+        // spanning the jump contains it, and it counts for the day before.
+        let before = zone.local_time(utc("2024-03-10 06:59"));
+        let after = zone.local_time(utc("2024-03-10 07:00"));
+        assert_eq!(
+            (before, after),
+            (local("2024-03-10 01:59"), local("2024-03-10 03:00"))
+        );
+        assert!(before < at && at <= after);
+        assert_eq!(date_of(at, 4), date("2024-03-09"));
+    }
+
+    #[test]
+    fn the_repeated_hour_at_fall_back_stays_on_one_day() {
+        let zone = new_york();
+        // 01:30 EDT and 01:30 EST on 2024-11-03, with days starting at 2:00.
+        for at in ["2024-11-03 05:30", "2024-11-03 06:30"] {
+            assert_eq!(logical_date(utc(at), &zone, None, 2), date("2024-11-02"));
+        }
+        // 02:00 EST is an hour and a half after the second 01:30.
+        assert_eq!(
+            logical_date(utc("2024-11-03 07:00"), &zone, None, 2),
+            date("2024-11-03")
+        );
+    }
+
+    #[test]
+    fn the_skipped_hour_at_spring_forward() {
+        let zone = new_york();
+        // 01:59 EST, then a minute later 03:00 EDT; 02:00 never happens.
+        assert_eq!(
+            logical_date(utc("2024-03-10 06:59"), &zone, None, 2),
+            date("2024-03-09")
+        );
+        assert_eq!(
+            logical_date(utc("2024-03-10 07:00"), &zone, None, 2),
+            date("2024-03-10")
+        );
+        // Midnight-to-midnight days are untouched: 23:30 EST the day before.
+        assert_eq!(
+            logical_date(utc("2024-03-10 04:30"), &zone, None, 0),
+            date("2024-03-09")
+        );
+    }
+
+    #[test]
+    fn the_home_zone_wins_over_the_zone_checked_in_from() {
+        let tokyo = Zone::named("Asia/Tokyo").unwrap();
+        let home = new_york();
+        // 10:00 on July 2nd in Tokyo is still 21:00 on July 1st at home.
+        let at = utc("2024-07-02 01:00");
+        assert_eq!(logical_date(at, &tokyo, None, 0), date("2024-07-02"));
+        assert_eq!(logical_date(at, &tokyo, Some(&home), 0), date("2024-07-01"));
+    }
+
+    #[test]
+    fn unknown_zones_are_rejected() {
+        assert_eq!(
+            Zone::named("Mars/Olympus").unwrap_err(),
+            "unknown time zone: Mars/Olympus"
+        );
+        assert_eq!(
+            Zone::named("Europe/Berlin").unwrap().name(),
+            "Europe/Berlin"
+        );
+    }
+}
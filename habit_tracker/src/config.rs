@@ -0,0 +1,275 @@
+use crate::clock::Zone;
+use crate::hooks::EventKind;
+use crate::keymap::{self, Action, Keymap, Keys};
+use crate::theme::{self, Colors, Theme};
+use crate::ui::{Icons, Tab};
+use chrono::format::{Item, StrftimeItems};
+use chrono::{NaiveTime, Weekday};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+const CONFIG_FILE: &str = "config.toml";
+
+#[derive(Debug, Default, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    /// `habits.json` and `todos.json`
+    #[default]
+    Json,
+    /// `habits.db`
+    Sqlite,
+    /// `events.jsonl`, a log of every change, and `snapshot.json`
+    Log,
+}
+
+impl FromStr for Backend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(Backend::Json),
+            "sqlite" => Ok(Backend::Sqlite),
+            "log" => Ok(Backend::Log),
+            _ => Err(format!("unknown storage backend: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Directory holding the data files; by default the one the program is
+    /// started in. A leading `~/` means the home directory.
+    pub data_dir: Option<PathBuf>,
+    pub backend: Backend,
+    /// When set, todos are read from and written to this todo.txt file
+    /// instead of `todos.json`.
+    pub todotxt_file: Option<PathBuf>,
+    /// Commit the data files to a git repository in the data directory
+    /// after each session, pulling before and pushing after if it has a
+    /// remote.
+    pub git_sync: bool,
+    /// Base URL of a `habit_tracker serve` instance to sync habits with at
+    /// the start and end of each session.
+    pub sync_server: Option<String>,
+    /// Whose habits to sync on the server; defaults to "default".
+    pub sync_user: Option<String>,
+    /// Hour (0-23) at which a new day starts; check-ins before it count for
+    /// the day before.
+    pub day_start_hour: u32,
+    /// Time zone whose clock decides which day a check-in counts for, such
+    /// as "Europe/Berlin"; by default the zone the computer is in.
+    pub home_zone: Option<String>,
+    /// Day weeks start on, for weekly habits, the frequency graph and
+    /// reports; Monday if unset.
+    pub week_start: Option<Weekday>,
+    /// Tab shown at startup.
+    pub default_tab: Tab,
+    /// How dates are shown in the UI, in `strftime` syntax; `%Y-%m-%d` if
+    /// unset.
+    pub date_format: Option<String>,
+    pub icons: Icons,
+    /// The built-in keys: `default` or `vim`.
+    pub keymap: keymap::Preset,
+    /// Keys for each action, replacing the preset's ones, from `[keys]`.
+    pub keys: BTreeMap<Action, Keys>,
+    /// The built-in colors: `dark`, `light`, `solarized` or `high_contrast`.
+    pub theme: theme::Preset,
+    /// Colors replacing the theme's, from `[colors]`.
+    pub colors: Colors,
+    /// Format of `habit_tracker prompt` when no `--format` is given.
+    pub prompt_format: Option<String>,
+    pub hooks: Hooks,
+    pub webhooks: Vec<Webhook>,
+    pub daemon: Daemon,
+}
+
+/// Shell commands run when things happen in the UI, from `[hooks]`.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct Hooks {
+    pub habit_completed: Option<String>,
+    pub streak_milestone: Option<String>,
+    pub todo_done: Option<String>,
+    pub day_completed: Option<String>,
+    /// Hooks still running after this many seconds are killed.
+    pub timeout_secs: u64,
+    /// Streak lengths that fire `streak_milestone`.
+    pub milestones: Vec<u32>,
+}
+
+/// Settings of `habit_tracker daemon`, from `[daemon]`.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct Daemon {
+    /// When to remind of todos due that day or the next.
+    pub todo_time: NaiveTime,
+    /// How often to check for due reminders.
+    pub interval_secs: u64,
+}
+
+impl Default for Daemon {
+    fn default() -> Self {
+        Daemon {
+            todo_time: NaiveTime::from_hms_opt(9, 0, 0).unwrap_or_default(),
+            interval_secs: 30,
+        }
+    }
+}
+
+/// An HTTP endpoint to post events to, from a `[[webhooks]]` entry.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Webhook {
+    pub url: String,
+    #[serde(default = "default_webhook_events")]
+    pub events: Vec<EventKind>,
+    /// Streak lengths that fire `streak_milestone`.
+    #[serde(default = "default_webhook_milestones")]
+    pub milestones: Vec<u32>,
+    /// JSON body with `{field}` placeholders for the event's fields and
+    /// `{message}`; by default the event itself with a `text` message.
+    pub body: Option<String>,
+}
+
+fn default_webhook_events() -> Vec<EventKind> {
+    vec![EventKind::StreakMilestone, EventKind::DayCompleted]
+}
+
+fn default_webhook_milestones() -> Vec<u32> {
+    vec![30, 100, 365]
+}
+
+impl Default for Hooks {
+    fn default() -> Self {
+        Hooks {
+            habit_completed: None,
+            streak_milestone: None,
+            todo_done: None,
+            day_completed: None,
+            timeout_secs: 10,
+            milestones: vec![7, 30, 100, 365],
+        }
+    }
+}
+
+impl Config {
+    pub fn sync_user(&self) -> &str {
+        self.sync_user.as_deref().unwrap_or("default")
+    }
+
+    pub fn data_dir(&self) -> Option<PathBuf> {
+        let dir = self.data_dir.as_ref()?;
+        match (dir.strip_prefix("~"), dirs::home_dir()) {
+            (Ok(rest), Some(home)) => Some(home.join(rest)),
+            _ => Some(dir.clone()),
+        }
+    }
+
+    pub fn date_format(&self) -> &str {
+        self.date_format.as_deref().unwrap_or("%Y-%m-%d")
+    }
+
+    pub fn keymap(&self) -> Result<Keymap, String> {
+        Keymap::new(self.keymap, &self.keys)
+    }
+
+    pub fn theme(&self) -> Theme {
+        Theme::new(self.theme, &self.colors)
+    }
+}
+
+pub fn config_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("habit_tracker").join(CONFIG_FILE))
+}
+
+pub fn load() -> io::Result<Config> {
+    let path = match config_path() {
+        Some(path) if path.exists() => path,
+        _ => return Ok(Config::default()),
+    };
+    parse(&fs::read_to_string(&path)?).map_err(|message| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}: {}", path.display(), message),
+        )
+    })
+}
+
+/// Reads and checks a config file's contents.
+fn parse(contents: &str) -> Result<Config, String> {
+    let config: Config = toml::from_str(contents).map_err(|e| e.to_string())?;
+    if config.day_start_hour > 23 {
+        return Err(format!(
+            "day_start_hour must be between 0 and 23, not {}",
+            config.day_start_hour
+        ));
+    }
+    if let Some(zone) = &config.home_zone {
+        Zone::named(zone).map_err(|e| format!("home_zone: {}", e))?;
+    }
+    if StrftimeItems::new(config.date_format()).any(|item| item == Item::Error) {
+        return Err(format!(
+            "date_format `{}` is not a valid strftime format",
+            config.date_format()
+        ));
+    }
+    config.keymap().map_err(|e| format!("[keys]: {}", e))?;
+    Ok(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_a_valid_config() {
+        let config = parse(
+            "day_start_hour = 4\nhome_zone = \"Europe/Berlin\"\ndate_format = \"%d.%m.%Y\"\nkeymap = \"vim\"\n\n[keys]\nquit = \"ctrl-q\"\n",
+        )
+        .unwrap();
+        assert_eq!(config.day_start_hour, 4);
+        assert_eq!(config.home_zone.as_deref(), Some("Europe/Berlin"));
+        assert_eq!(config.date_format(), "%d.%m.%Y");
+    }
+
+    #[test]
+    fn empty_config_is_the_default() {
+        let config = parse("").unwrap();
+        assert_eq!(config.day_start_hour, 0);
+        assert_eq!(config.date_format(), "%Y-%m-%d");
+    }
+
+    #[test]
+    fn rejects_day_start_hour_past_23() {
+        let err = parse("day_start_hour = 24\n").unwrap_err();
+        assert!(err.contains("day_start_hour"), "{}", err);
+    }
+
+    #[test]
+    fn rejects_unknown_home_zone() {
+        let err = parse("home_zone = \"Mars/Olympus\"\n").unwrap_err();
+        assert!(err.starts_with("home_zone:"), "{}", err);
+    }
+
+    #[test]
+    fn rejects_bad_date_format() {
+        let err = parse("date_format = \"%Q\"\n").unwrap_err();
+        assert!(err.contains("date_format"), "{}", err);
+    }
+
+    #[test]
+    fn rejects_a_key_bound_twice() {
+        let err = parse("[keys]\nquit = \"a\"\nadd = \"a\"\n").unwrap_err();
+        assert!(err.starts_with("[keys]:"), "{}", err);
+    }
+
+    #[test]
+    fn rejects_unknown_fields() {
+        assert!(parse("day_start = 4\n").is_err());
+    }
+}
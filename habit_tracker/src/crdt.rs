@@ -0,0 +1,396 @@
+use crate::habit::{CategoryColors, CheckIn, Frequency, Habit};
+use chrono::{DateTime, NaiveDate, NaiveTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+// Every type here merges by a join: merging is commutative, associative and
+// idempotent, so replicas that have seen the same edits end up identical no
+// matter in which order or how often they exchanged them.
+
+/// A last-writer-wins register: of two writes the later one wins, and the
+/// larger value breaks ties so every replica picks the same one.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Lww<T> {
+    pub value: T,
+    pub time: DateTime<Utc>,
+}
+
+impl<T: Clone + Serialize> Lww<T> {
+    pub fn new(value: T, time: DateTime<Utc>) -> Self {
+        Lww { value, time }
+    }
+
+    fn merge(current: &mut Option<Lww<T>>, other: &Option<Lww<T>>) {
+        let Some(other) = other else { return };
+        let newer = match current {
+            None => true,
+            Some(current) => {
+                (other.time, sort_key(&other.value)) > (current.time, sort_key(&current.value))
+            }
+        };
+        if newer {
+            *current = Some(other.clone());
+        }
+    }
+}
+
+fn sort_key<T: Serialize>(value: &T) -> String {
+    serde_json::to_string(value).unwrap_or_default()
+}
+
+/// An observed-remove set in which a concurrent add beats a remove. Each add
+/// is tagged uniquely and a remove only cancels the tags it has seen, so a
+/// date checked on one device while unchecked on another stays checked.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AddWinsSet<T: Ord> {
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    adds: BTreeMap<T, BTreeSet<String>>,
+    #[serde(skip_serializing_if = "BTreeSet::is_empty")]
+    removed: BTreeSet<String>,
+}
+
+impl<T: Ord + Clone> AddWinsSet<T> {
+    pub fn insert(&mut self, value: T) {
+        let tag = uuid::Uuid::new_v4().to_string();
+        self.adds.entry(value).or_default().insert(tag);
+    }
+
+    /// Removes `value` as seen in `observed`, returning the delta to merge.
+    pub fn remove_observed(observed: &Self, value: &T) -> Self {
+        AddWinsSet {
+            adds: BTreeMap::new(),
+            removed: observed.adds.get(value).cloned().unwrap_or_default(),
+        }
+    }
+
+    pub fn contains(&self, value: &T) -> bool {
+        self.adds.contains_key(value)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.adds.keys()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.adds.is_empty() && self.removed.is_empty()
+    }
+
+    pub fn merge(&mut self, other: &Self) {
+        self.removed.extend(other.removed.iter().cloned());
+        for (value, tags) in &other.adds {
+            self.adds
+                .entry(value.clone())
+                .or_default()
+                .extend(tags.iter().cloned());
+        }
+        // Removed tags are remembered in `removed`, so they can be dropped
+        // from `adds`; a value is present while any of its tags survives.
+        let removed = &self.removed;
+        self.adds.retain(|_, tags| {
+            tags.retain(|tag| !removed.contains(tag));
+            !tags.is_empty()
+        });
+    }
+}
+
+/// A habit's replicated state. Every field is merged on its own. The same
+/// shape describes a change, with only the edited fields set.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Record {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<Lww<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub category: Option<Lww<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frequency: Option<Lww<Frequency>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reminders: Option<Lww<Vec<NaiveTime>>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub paused: Option<Lww<bool>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deleted: Option<Lww<bool>>,
+    #[serde(skip_serializing_if = "AddWinsSet::is_empty")]
+    pub completions: AddWinsSet<NaiveDate>,
+    /// When and where each date was checked, for those checked live.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub check_ins: BTreeMap<NaiveDate, Lww<CheckIn>>,
+}
+
+impl Record {
+    pub fn merge(&mut self, other: &Record) {
+        Lww::merge(&mut self.name, &other.name);
+        Lww::merge(&mut self.category, &other.category);
+        Lww::merge(&mut self.frequency, &other.frequency);
+        Lww::merge(&mut self.reminders, &other.reminders);
+        Lww::merge(&mut self.paused, &other.paused);
+        Lww::merge(&mut self.deleted, &other.deleted);
+        self.completions.merge(&other.completions);
+        for (date, check_in) in &other.check_ins {
+            let mut current = self.check_ins.remove(date);
+            Lww::merge(&mut current, &Some(check_in.clone()));
+            self.check_ins.extend(current.map(|c| (*date, c)));
+        }
+    }
+
+    pub fn is_deleted(&self) -> bool {
+        self.deleted.as_ref().is_some_and(|d| d.value)
+    }
+
+    pub fn name(&self) -> String {
+        value(&self.name)
+    }
+
+    pub fn category(&self) -> String {
+        value(&self.category)
+    }
+
+    pub fn reminders(&self) -> Vec<NaiveTime> {
+        value(&self.reminders)
+    }
+
+    pub fn is_paused(&self) -> bool {
+        value(&self.paused)
+    }
+
+    pub fn to_habit(&self, id: &str) -> Habit {
+        let mut habit = Habit::new(self.name(), self.category(), value(&self.frequency));
+        habit.id = id.to_string();
+        habit.reminders = self.reminders();
+        habit.paused = self.is_paused();
+        for &date in self.completions.iter() {
+            match self.check_ins.get(&date) {
+                Some(check_in) => habit.check_in(date, check_in.value.clone()),
+                None => habit.mark_completed(date),
+            }
+        }
+        habit
+    }
+}
+
+fn value<T: Clone + Default>(field: &Option<Lww<T>>) -> T {
+    field.as_ref().map(|f| f.value.clone()).unwrap_or_default()
+}
+
+/// All habits of one user, by id.
+pub type Records = BTreeMap<String, Record>;
+
+pub fn merge(records: &mut Records, other: &Records) {
+    for (id, record) in other {
+        records.entry(id.clone()).or_default().merge(record);
+    }
+}
+
+/// Everything one user syncs: their habits, and the color of each category,
+/// kept once per category. The same shape describes a change.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Replica {
+    pub habits: Records,
+    /// A removed color stays as `None`, so the removal syncs too.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub category_colors: BTreeMap<String, Lww<Option<String>>>,
+}
+
+impl Replica {
+    pub fn merge(&mut self, other: &Replica) {
+        merge(&mut self.habits, &other.habits);
+        for (category, color) in &other.category_colors {
+            let mut current = self.category_colors.remove(category);
+            Lww::merge(&mut current, &Some(color.clone()));
+            self.category_colors
+                .extend(current.map(|c| (category.clone(), c)));
+        }
+    }
+
+    /// The colors currently set.
+    pub fn colors(&self) -> CategoryColors {
+        self.category_colors
+            .iter()
+            .filter_map(|(category, color)| Some((category.clone(), color.value.clone()?)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn at(second: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 5, 1, 12, 0, second).unwrap()
+    }
+
+    fn day(d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 5, d).unwrap()
+    }
+
+    fn merged<T: Clone>(mut a: T, b: &T, merge: impl Fn(&mut T, &T)) -> T {
+        merge(&mut a, b);
+        a
+    }
+
+    fn lww(value: &str, second: u32) -> Option<Lww<String>> {
+        Some(Lww::new(value.to_string(), at(second)))
+    }
+
+    #[test]
+    fn a_new_register_holds_the_write() {
+        let register = Lww::new("Run".to_string(), at(1));
+        assert_eq!((register.value.as_str(), register.time), ("Run", at(1)));
+        // Merged into an empty field, it is taken as is.
+        assert_eq!(
+            merged(None, &Some(register.clone()), Lww::merge),
+            Some(register)
+        );
+    }
+
+    #[test]
+    fn the_later_write_wins_in_either_order() {
+        let (old, new) = (lww("Run", 1), lww("Jog", 2));
+        assert_eq!(merged(old.clone(), &new, Lww::merge), new);
+        assert_eq!(merged(new.clone(), &old, Lww::merge), new);
+        assert_eq!(merged(new.clone(), &new, Lww::merge), new);
+        assert_eq!(merged(None, &old, Lww::merge), old);
+        assert_eq!(merged(old.clone(), &None, Lww::merge), old);
+    }
+
+    #[test]
+    fn writes_at_the_same_time_pick_the_same_value_everywhere() {
+        let (a, b) = (lww("Jog", 1), lww("Run", 1));
+        assert_eq!(merged(a.clone(), &b, Lww::merge), b);
+        assert_eq!(merged(b.clone(), &a, Lww::merge), b);
+    }
+
+    #[test]
+    fn sets_merge_commutatively_and_idempotently() {
+        let (mut a, mut b) = (AddWinsSet::default(), AddWinsSet::default());
+        a.insert(day(1));
+        a.insert(day(2));
+        b.insert(day(2));
+        b.insert(day(3));
+        let ab = merged(a.clone(), &b, AddWinsSet::merge);
+        assert_eq!(ab, merged(b.clone(), &a, AddWinsSet::merge));
+        assert_eq!(ab, merged(ab.clone(), &a, AddWinsSet::merge));
+        assert_eq!(
+            ab.iter().copied().collect::<Vec<_>>(),
+            [day(1), day(2), day(3)]
+        );
+    }
+
+    #[test]
+    fn an_observed_remove_takes_effect_everywhere() {
+        let mut a = AddWinsSet::default();
+        a.insert(day(1));
+        let b = a.clone();
+        a.merge(&AddWinsSet::remove_observed(&a, &day(1)));
+        assert!(!a.contains(&day(1)));
+        assert!(!merged(b.clone(), &a, AddWinsSet::merge).contains(&day(1)));
+        assert!(!merged(a.clone(), &b, AddWinsSet::merge).contains(&day(1)));
+    }
+
+    #[test]
+    fn a_concurrent_add_beats_a_remove() {
+        let mut a = AddWinsSet::default();
+        a.insert(day(1));
+        let mut b = a.clone();
+        // A unchecks the day while B, not having seen that, checks it again.
+        a.merge(&AddWinsSet::remove_observed(&a, &day(1)));
+        b.insert(day(1));
+        let ab = merged(a.clone(), &b, AddWinsSet::merge);
+        assert!(ab.contains(&day(1)));
+        assert_eq!(ab, merged(b, &a, AddWinsSet::merge));
+    }
+
+    fn record(name: &str, second: u32, done: &[u32]) -> Record {
+        let mut record = Record {
+            name: lww(name, second),
+            ..Record::default()
+        };
+        for d in done {
+            record.completions.insert(day(*d));
+        }
+        record
+    }
+
+    #[test]
+    fn records_merge_field_by_field_in_any_order() {
+        let mut a = record("Run", 1, &[1]);
+        a.frequency = Some(Lww::new(Frequency::Weekly, at(1)));
+        let check_in = CheckIn {
+            at: at(5),
+            zone: "Europe/Berlin".into(),
+        };
+        a.check_ins.insert(day(1), Lww::new(check_in, at(5)));
+        let mut b = record("Jog", 2, &[2]);
+        b.paused = Some(Lww::new(true, at(3)));
+        let c = record("Sprint", 0, &[3]);
+
+        let ab = merged(a.clone(), &b, Record::merge);
+        assert_eq!(ab, merged(b.clone(), &a, Record::merge));
+        assert_eq!(ab, merged(ab.clone(), &b, Record::merge));
+        assert_eq!(
+            merged(ab.clone(), &c, Record::merge),
+            merged(
+                a.clone(),
+                &merged(b.clone(), &c, Record::merge),
+                Record::merge
+            )
+        );
+
+        let habit = ab.to_habit("id");
+        assert_eq!(habit.name, "Jog");
+        assert_eq!(habit.frequency, Frequency::Weekly);
+        assert!(habit.paused);
+        assert_eq!(habit.completed_dates(), [day(1), day(2)]);
+        assert_eq!(habit.check_in_on(day(1)).map(|c| c.at), Some(at(5)));
+    }
+
+    #[test]
+    fn merging_record_maps_adds_missing_habits() {
+        let mut records = Records::new();
+        records.insert("a".into(), record("Run", 1, &[1]));
+        let mut other = Records::new();
+        other.insert("a".into(), record("Run", 1, &[2]));
+        other.insert("b".into(), record("Read", 1, &[]));
+        merge(&mut records, &other);
+        assert_eq!(records.len(), 2);
+        assert_eq!(
+            records["a"].completions.iter().copied().collect::<Vec<_>>(),
+            [day(1), day(2)]
+        );
+    }
+
+    fn colored(colors: &[(&str, Option<&str>, u32)]) -> Replica {
+        let mut replica = Replica::default();
+        for (category, color, second) in colors {
+            replica.category_colors.insert(
+                category.to_string(),
+                Lww::new(color.map(str::to_string), at(*second)),
+            );
+        }
+        replica
+    }
+
+    #[test]
+    fn each_category_ends_up_with_one_color_in_any_order() {
+        let a = colored(&[("Health", Some("green"), 1), ("Work", Some("blue"), 3)]);
+        let b = colored(&[("Health", Some("red"), 2), ("Work", None, 2)]);
+        let ab = merged(a.clone(), &b, Replica::merge);
+        assert_eq!(ab, merged(b.clone(), &a, Replica::merge));
+        assert_eq!(ab, merged(ab.clone(), &a, Replica::merge));
+        assert_eq!(
+            ab.colors(),
+            CategoryColors::from([
+                ("Health".to_string(), "red".to_string()),
+                ("Work".to_string(), "blue".to_string()),
+            ])
+        );
+
+        // A later removal wins over an earlier color.
+        let removed = colored(&[("Work", None, 4)]);
+        let colors = merged(ab, &removed, Replica::merge).colors();
+        assert_eq!(colors.keys().collect::<Vec<_>>(), ["Health"]);
+    }
+}
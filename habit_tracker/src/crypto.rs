@@ -0,0 +1,364 @@
+use argon2::Argon2;
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use crossterm::terminal;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// Holds the KDF salt and a token to check passphrases against. Its presence
+/// marks the data directory as encrypted.
+pub const KEY_FILE: &str = "encryption.json";
+/// Checked before the keyring and the prompt, for scripts and CLI use.
+pub const PASSPHRASE_VAR: &str = "HABIT_TRACKER_PASSPHRASE";
+
+/// Encrypted files start with this, followed by the nonce and ciphertext.
+const MAGIC: &[u8] = b"HTENC1\n";
+const NONCE_LEN: usize = 24;
+const CHECK_TOKEN: &[u8] = b"habit_tracker";
+const PROMPT_ATTEMPTS: usize = 3;
+
+#[derive(Serialize, Deserialize)]
+struct KeyInfo {
+    /// Argon2id with the crate's default cost parameters.
+    kdf: String,
+    salt: String,
+    /// `CHECK_TOKEN` encrypted with the key, to tell a wrong passphrase
+    /// from a damaged data file.
+    check: String,
+}
+
+/// XChaCha20-Poly1305 with a key derived from the passphrase.
+#[derive(Clone)]
+pub struct Cipher {
+    key: [u8; 32],
+}
+
+impl Cipher {
+    fn derive(passphrase: &str, salt: &[u8]) -> io::Result<Self> {
+        let mut key = [0u8; 32];
+        Argon2::default()
+            .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+            .map_err(|e| io::Error::other(e.to_string()))?;
+        Ok(Cipher { key })
+    }
+
+    pub fn encrypt(&self, plaintext: &[u8]) -> Vec<u8> {
+        let cipher = XChaCha20Poly1305::new(&self.key.into());
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = cipher
+            .encrypt(&nonce, plaintext)
+            .expect("encryption does not fail for in-memory data");
+        [MAGIC, nonce.as_slice(), &ciphertext].concat()
+    }
+
+    /// Decrypts data written by `encrypt`. Data that is not encrypted is
+    /// refused, so a plain file slipped into an encrypted directory is not
+    /// taken for the user's own.
+    pub fn decrypt(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+        let Some(rest) = data.strip_prefix(MAGIC) else {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "not encrypted, though encryption is on; `habit_tracker decrypt` \
+                 finishes an interrupted `habit_tracker encrypt`",
+            ));
+        };
+        if rest.len() < NONCE_LEN {
+            return Err(invalid("encrypted data is truncated"));
+        }
+        let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+        XChaCha20Poly1305::new(&self.key.into())
+            .decrypt(XNonce::from_slice(nonce), ciphertext)
+            .map_err(|_| invalid("encrypted data is damaged or was written with another key"))
+    }
+
+    /// Like `decrypt`, but passes plain data through: only for converting
+    /// files, which may be left half done.
+    pub fn decrypt_or_plain(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+        if is_encrypted(data) {
+            self.decrypt(data)
+        } else {
+            Ok(data.to_vec())
+        }
+    }
+}
+
+pub fn is_encrypted(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+pub fn is_enabled(dir: &Path) -> bool {
+    dir.join(KEY_FILE).exists()
+}
+
+/// The key for an encrypted data directory, or `None` if it is not
+/// encrypted. The passphrase is taken from `HABIT_TRACKER_PASSPHRASE`, then
+/// from a key cached by `unlock`, and otherwise asked for on the terminal.
+pub fn unlock(dir: &Path) -> io::Result<Option<Cipher>> {
+    let path = dir.join(KEY_FILE);
+    if !path.exists() {
+        return Ok(None);
+    }
+    let info: KeyInfo = serde_json::from_str(&fs::read_to_string(&path)?)
+        .map_err(|e| invalid(&format!("{}: {}", path.display(), e)))?;
+    let salt = from_hex(&info.salt)?;
+    let check = from_hex(&info.check)?;
+    let verify = |cipher: Cipher| match cipher.decrypt(&check) {
+        Ok(token) if token == CHECK_TOKEN => Some(cipher),
+        _ => None,
+    };
+
+    if let Ok(passphrase) = std::env::var(PASSPHRASE_VAR) {
+        return verify(Cipher::derive(&passphrase, &salt)?)
+            .map(Some)
+            .ok_or_else(|| wrong_passphrase(&format!("{} is not the passphrase", PASSPHRASE_VAR)));
+    }
+    if let Some(cipher) = cached_key(&salt).and_then(verify) {
+        return Ok(Some(cipher));
+    }
+    for _ in 0..PROMPT_ATTEMPTS {
+        let passphrase = prompt("Passphrase: ").map_err(|e| match e.kind() {
+            io::ErrorKind::Interrupted => e,
+            _ => wrong_passphrase(&format!(
+                "the data is encrypted; set {} or run `habit_tracker unlock` in a terminal",
+                PASSPHRASE_VAR
+            )),
+        })?;
+        if let Some(cipher) = verify(Cipher::derive(&passphrase, &salt)?) {
+            return Ok(Some(cipher));
+        }
+        eprintln!("Wrong passphrase");
+    }
+    Err(wrong_passphrase("no valid passphrase given"))
+}
+
+/// Whether `unlock` would get the key without asking, for commands that
+/// must never wait on the terminal.
+pub fn is_unlocked(dir: &Path) -> bool {
+    !is_enabled(dir)
+        || std::env::var_os(PASSPHRASE_VAR).is_some()
+        || salt_of(dir)
+            .ok()
+            .and_then(|salt| cached_key(&salt))
+            .is_some()
+}
+
+/// Sets up encryption for `dir` with a new passphrase and returns its key.
+pub fn enable(dir: &Path, passphrase: &str) -> io::Result<Cipher> {
+    let mut salt = [0u8; 16];
+    OsRng.fill_bytes(&mut salt);
+    let cipher = Cipher::derive(passphrase, &salt)?;
+    let info = KeyInfo {
+        kdf: "argon2id".to_string(),
+        salt: to_hex(&salt),
+        check: to_hex(&cipher.encrypt(CHECK_TOKEN)),
+    };
+    fs::write(dir.join(KEY_FILE), serde_json::to_string_pretty(&info)?)?;
+    Ok(cipher)
+}
+
+pub fn disable(dir: &Path) -> io::Result<()> {
+    forget_key(dir)?;
+    fs::remove_file(dir.join(KEY_FILE))
+}
+
+/// Remembers the key in the keyring stand-in: a file only the user can
+/// read, in the runtime directory where there is one so it goes away on
+/// logout.
+pub fn cache_key(dir: &Path, cipher: &Cipher) -> io::Result<PathBuf> {
+    let path = keyring_file(&salt_of(dir)?)?;
+    fs::create_dir_all(path.parent().unwrap_or(Path::new(".")))?;
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options
+        .open(&path)?
+        .write_all(to_hex(&cipher.key).as_bytes())?;
+    Ok(path)
+}
+
+pub fn forget_key(dir: &Path) -> io::Result<()> {
+    if !is_enabled(dir) {
+        return Ok(());
+    }
+    let path = keyring_file(&salt_of(dir)?)?;
+    if path.exists() {
+        fs::remove_file(path)?;
+    }
+    Ok(())
+}
+
+fn cached_key(salt: &[u8]) -> Option<Cipher> {
+    let hex = fs::read_to_string(keyring_file(salt).ok()?).ok()?;
+    let key = from_hex(hex.trim()).ok()?.try_into().ok()?;
+    Some(Cipher { key })
+}
+
+fn salt_of(dir: &Path) -> io::Result<Vec<u8>> {
+    let info: KeyInfo = serde_json::from_str(&fs::read_to_string(dir.join(KEY_FILE))?)?;
+    from_hex(&info.salt)
+}
+
+/// One cached key per data directory, told apart by the salt.
+fn keyring_file(salt: &[u8]) -> io::Result<PathBuf> {
+    let dir = dirs::runtime_dir()
+        .or_else(dirs::cache_dir)
+        .ok_or_else(|| io::Error::other("no runtime or cache directory to keep the key in"))?;
+    Ok(dir
+        .join("habit_tracker")
+        .join(format!("key-{}", to_hex(&salt[..8.min(salt.len())]))))
+}
+
+/// Reads a line from the terminal without echoing it.
+pub fn prompt(label: &str) -> io::Result<String> {
+    eprint!("{}", label);
+    io::stderr().flush()?;
+    terminal::enable_raw_mode()?;
+    let mut input = String::new();
+    let result = loop {
+        match event::read() {
+            Ok(Event::Key(key)) if key.kind != KeyEventKind::Release => match key.code {
+                KeyCode::Enter => break Ok(input),
+                KeyCode::Backspace => {
+                    input.pop();
+                }
+                KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                    break Err(io::Error::new(io::ErrorKind::Interrupted, "cancelled"))
+                }
+                KeyCode::Char(c) => input.push(c),
+                KeyCode::Esc => break Err(io::Error::new(io::ErrorKind::Interrupted, "cancelled")),
+                _ => {}
+            },
+            Ok(_) => {}
+            Err(err) => break Err(err),
+        }
+    };
+    terminal::disable_raw_mode()?;
+    eprintln!();
+    result
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex(hex: &str) -> io::Result<Vec<u8>> {
+    let digits = hex
+        .chars()
+        .map(|c| c.to_digit(16).map(|d| d as u8))
+        .collect::<Option<Vec<u8>>>()
+        .ok_or_else(|| invalid("invalid hex digit"))?;
+    if !digits.len().is_multiple_of(2) {
+        return Err(invalid("odd-length hex string"));
+    }
+    Ok(digits
+        .chunks(2)
+        .map(|pair| pair[0] << 4 | pair[1])
+        .collect())
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+fn wrong_passphrase(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::PermissionDenied, message.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "habit_tracker-crypto-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn cipher(passphrase: &str) -> Cipher {
+        Cipher::derive(passphrase, b"0123456789abcdef").unwrap()
+    }
+
+    #[test]
+    fn decrypts_what_it_encrypted() {
+        let cipher = cipher("correct horse");
+        let data = cipher.encrypt(b"{\"habits\": []}");
+        assert!(is_encrypted(&data));
+        assert_eq!(cipher.decrypt(&data).unwrap(), b"{\"habits\": []}");
+        // A fresh nonce every time.
+        assert_ne!(cipher.encrypt(b"same"), cipher.encrypt(b"same"));
+    }
+
+    #[test]
+    fn a_wrong_passphrase_does_not_decrypt() {
+        let data = cipher("correct horse").encrypt(b"secret");
+        let err = cipher("battery staple").decrypt(&data).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn truncated_or_altered_data_is_refused() {
+        let cipher = cipher("correct horse");
+        let data = cipher.encrypt(b"secret");
+        let short = &data[..MAGIC.len() + NONCE_LEN - 1];
+        assert!(cipher
+            .decrypt(short)
+            .unwrap_err()
+            .to_string()
+            .contains("truncated"));
+        assert!(cipher.decrypt(&data[..data.len() - 1]).is_err());
+        let mut flipped = data.clone();
+        *flipped.last_mut().unwrap() ^= 1;
+        assert!(cipher.decrypt(&flipped).is_err());
+    }
+
+    #[test]
+    fn plain_data_is_only_accepted_when_converting() {
+        let cipher = cipher("correct horse");
+        let err = cipher.decrypt(b"{\"habits\": []}").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+        assert_eq!(cipher.decrypt_or_plain(b"plain").unwrap(), b"plain");
+        let data = cipher.encrypt(b"secret");
+        assert_eq!(cipher.decrypt_or_plain(&data).unwrap(), b"secret");
+    }
+
+    #[test]
+    fn enabling_writes_a_key_file_the_passphrase_checks_against() {
+        let dir = scratch_dir("enable");
+        assert!(!is_enabled(&dir));
+        let cipher = enable(&dir, "correct horse").unwrap();
+        assert!(is_enabled(&dir));
+        let info: KeyInfo =
+            serde_json::from_str(&fs::read_to_string(dir.join(KEY_FILE)).unwrap()).unwrap();
+        let salt = from_hex(&info.salt).unwrap();
+        assert_eq!(salt, salt_of(&dir).unwrap());
+        let check = from_hex(&info.check).unwrap();
+        let again = Cipher::derive("correct horse", &salt).unwrap();
+        assert_eq!(again.key, cipher.key);
+        assert_eq!(again.decrypt(&check).unwrap(), CHECK_TOKEN);
+    }
+
+    #[test]
+    fn hex_round_trips_and_rejects_bad_input() {
+        assert_eq!(
+            from_hex(&to_hex(&[0, 1, 0xab, 0xff])).unwrap(),
+            [0, 1, 0xab, 0xff]
+        );
+        assert_eq!(from_hex("ABcd").unwrap(), [0xab, 0xcd]);
+        assert!(from_hex("abc").is_err());
+        assert!(from_hex("zz").is_err());
+        assert!(from_hex("+f").is_err());
+        // Multi-byte characters used to panic when sliced.
+        assert!(from_hex("é0").is_err());
+        assert!(from_hex("0é").is_err());
+    }
+}
@@ -0,0 +1,321 @@
+use crate::clock;
+use crate::config::Daemon;
+use crate::habit::{Frequency, Habit};
+use crate::storage::Storage;
+use crate::todo::Todo;
+use chrono::{NaiveDateTime, NaiveTime};
+use std::io;
+use std::process::Command;
+use std::thread;
+use std::time::Duration;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Notification {
+    pub title: String,
+    pub body: String,
+}
+
+/// Shows notifications to the user.
+pub trait Notifier {
+    fn notify(&mut self, notification: &Notification) -> io::Result<()>;
+}
+
+/// Desktop notifications through `notify-send`, which talks to the
+/// notification server over D-Bus.
+pub struct NotifySend;
+
+impl Notifier for NotifySend {
+    fn notify(&mut self, notification: &Notification) -> io::Result<()> {
+        let status = Command::new("notify-send")
+            .args(["--app-name", "habit_tracker"])
+            .arg(&notification.title)
+            .arg(&notification.body)
+            .status()?;
+        if status.success() {
+            Ok(())
+        } else {
+            Err(io::Error::other(format!("notify-send: {}", status)))
+        }
+    }
+}
+
+/// Checks for due reminders every `interval_secs` until the process is
+/// stopped, reading the data afresh each time so edits made meanwhile count.
+pub fn run(config: &Daemon, storage: &dyn Storage, notifier: &mut dyn Notifier) -> io::Result<()> {
+    let interval = Duration::from_secs(config.interval_secs.max(1));
+    let mut last = clock::now();
+    println!("Watching for reminders; stop with Ctrl-C");
+    loop {
+        thread::sleep(interval);
+        let now = clock::now();
+        // A file caught halfway through a save reads fine next time.
+        let data = storage
+            .load_habits()
+            .and_then(|h| Ok((h, storage.load_todos()?)));
+        match data {
+            Ok((habits, todos)) => {
+                let day_start_hour = clock::day_start_hour();
+                let window = (last, now);
+                remind(
+                    &habits,
+                    &todos,
+                    window,
+                    config.todo_time,
+                    day_start_hour,
+                    notifier,
+                );
+                last = now;
+            }
+            Err(err) => eprintln!("Could not read data: {}", err),
+        }
+    }
+}
+
+/// Sends the notifications `due` finds.
+pub fn remind(
+    habits: &[Habit],
+    todos: &[Todo],
+    window: (NaiveDateTime, NaiveDateTime),
+    todo_time: NaiveTime,
+    day_start_hour: u32,
+    notifier: &mut dyn Notifier,
+) {
+    for notification in due(habits, todos, window, todo_time, day_start_hour) {
+        if let Err(err) = notifier.notify(&notification) {
+            eprintln!("Could not notify: {}", err);
+        }
+    }
+}
+
+/// The reminders falling in the window, after its start up to and
+/// including its end: habits not paused and not yet done for their period
+/// at each of their reminder times, and at `todo_time` each day the open
+/// todos due that day or the next. Days start at `day_start_hour`, so a
+/// reminder at 01:00 belongs to the day before.
+fn due(
+    habits: &[Habit],
+    todos: &[Todo],
+    (from, to): (NaiveDateTime, NaiveDateTime),
+    todo_time: NaiveTime,
+    day_start_hour: u32,
+) -> Vec<Notification> {
+    let in_window = |date, time| {
+        let at = clock::instant(date, time, day_start_hour);
+        from < at && at <= to
+    };
+    let first = clock::date_of(from, day_start_hour);
+    let last = clock::date_of(to, day_start_hour);
+    let mut notifications = Vec::new();
+    for date in first.iter_days().take_while(|d| *d <= last) {
+        for habit in habits.iter().filter(|h| !h.paused) {
+            let reminded = habit.reminders.iter().any(|t| in_window(date, *t));
+            if reminded && !habit.is_done_in_period(date) {
+                let period = match habit.frequency {
+                    Frequency::Daily => "today",
+                    Frequency::Weekly => "this week",
+                    Frequency::Monthly => "this month",
+                };
+                notifications.push(Notification {
+                    title: habit.name.clone(),
+                    body: format!("{}: not done yet {}", habit.category, period),
+                });
+            }
+        }
+        if !in_window(date, todo_time) {
+            continue;
+        }
+        let tomorrow = date.succ_opt().unwrap_or(date);
+        for todo in todos.iter().filter(|t| !t.completed) {
+            let title = match todo.due_date() {
+                Some(due) if due == date => "Todo due today",
+                Some(due) if due == tomorrow => "Todo due tomorrow",
+                _ => continue,
+            };
+            notifications.push(Notification {
+                title: title.to_string(),
+                body: todo.description.clone(),
+            });
+        }
+    }
+    notifications
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    /// Collects notifications instead of showing them.
+    #[derive(Default)]
+    struct Stub(Vec<Notification>);
+
+    impl Notifier for Stub {
+        fn notify(&mut self, notification: &Notification) -> io::Result<()> {
+            self.0.push(notification.clone());
+            Ok(())
+        }
+    }
+
+    fn at(date: &str, time: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(&format!("{} {}", date, time), "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    fn time(time: &str) -> NaiveTime {
+        NaiveTime::parse_from_str(time, "%H:%M").unwrap()
+    }
+
+    fn date(date: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(date, "%Y-%m-%d").unwrap()
+    }
+
+    fn habit(name: &str, frequency: Frequency, reminders: &[&str]) -> Habit {
+        let mut habit = Habit::new(name.to_string(), "Health".to_string(), frequency);
+        habit.reminders = reminders.iter().map(|t| time(t)).collect();
+        habit
+    }
+
+    fn notify_due(
+        habits: &[Habit],
+        todos: &[Todo],
+        from: NaiveDateTime,
+        to: NaiveDateTime,
+    ) -> Vec<String> {
+        let mut stub = Stub::default();
+        remind(habits, todos, (from, to), time("09:00"), 0, &mut stub);
+        stub.0
+            .into_iter()
+            .map(|n| format!("{}: {}", n.title, n.body))
+            .collect()
+    }
+
+    #[test]
+    fn reminds_of_habits_not_done_yet() {
+        let mut done = habit("Stretch", Frequency::Daily, &["08:00"]);
+        done.mark_completed(date("2024-05-01"));
+        let habits = [habit("Run", Frequency::Daily, &["08:00", "20:00"]), done];
+
+        let sent = notify_due(
+            &habits,
+            &[],
+            at("2024-05-01", "07:59"),
+            at("2024-05-01", "08:00"),
+        );
+
+        assert_eq!(sent, vec!["Run: Health: not done yet today"]);
+    }
+
+    #[test]
+    fn reminders_outside_the_window_wait() {
+        let habits = [habit("Run", Frequency::Daily, &["08:00"])];
+        assert!(notify_due(
+            &habits,
+            &[],
+            at("2024-05-01", "08:00"),
+            at("2024-05-01", "08:01")
+        )
+        .is_empty());
+        assert!(notify_due(
+            &habits,
+            &[],
+            at("2024-05-01", "07:00"),
+            at("2024-05-01", "07:59")
+        )
+        .is_empty());
+    }
+
+    #[test]
+    fn paused_habits_are_skipped() {
+        let mut paused = habit("Run", Frequency::Daily, &["08:00"]);
+        paused.paused = true;
+        assert!(notify_due(
+            &[paused],
+            &[],
+            at("2024-05-01", "07:00"),
+            at("2024-05-01", "09:00")
+        )
+        .is_empty());
+    }
+
+    #[test]
+    fn weekly_habits_count_the_whole_week() {
+        let mut weekly = habit("Swim", Frequency::Weekly, &["18:00"]);
+        // Wednesday 2024-05-01 is in the week starting Monday 2024-04-29.
+        weekly.mark_completed(date("2024-04-29"));
+        assert!(notify_due(
+            &[weekly.clone()],
+            &[],
+            at("2024-05-01", "17:00"),
+            at("2024-05-01", "19:00")
+        )
+        .is_empty());
+
+        let sent = notify_due(
+            &[weekly],
+            &[],
+            at("2024-05-06", "17:00"),
+            at("2024-05-06", "19:00"),
+        );
+        assert_eq!(sent, vec!["Swim: Health: not done yet this week"]);
+    }
+
+    #[test]
+    fn reminds_of_todos_due_soon_at_the_todo_time() {
+        let todos: Vec<Todo> = [
+            "Pay rent due:2024-05-01",
+            "Call mom due:2024-05-02",
+            "Renew passport due:2024-06-01",
+            "x 2024-04-30 File taxes due:2024-05-01",
+        ]
+        .iter()
+        .filter_map(|line| Todo::from_todotxt(line))
+        .collect();
+
+        let sent = notify_due(
+            &[],
+            &todos,
+            at("2024-05-01", "08:30"),
+            at("2024-05-01", "09:00"),
+        );
+
+        assert_eq!(
+            sent,
+            vec![
+                "Todo due today: Pay rent due:2024-05-01",
+                "Todo due tomorrow: Call mom due:2024-05-02"
+            ]
+        );
+        assert!(notify_due(
+            &[],
+            &todos,
+            at("2024-05-01", "09:00"),
+            at("2024-05-01", "12:00")
+        )
+        .is_empty());
+    }
+
+    #[test]
+    fn catches_up_over_several_days() {
+        let habits = [habit("Run", Frequency::Daily, &["08:00"])];
+        let sent = notify_due(
+            &habits,
+            &[],
+            at("2024-05-01", "12:00"),
+            at("2024-05-03", "12:00"),
+        );
+        assert_eq!(sent.len(), 2);
+    }
+
+    #[test]
+    fn late_reminders_belong_to_the_day_before_with_a_later_day_start() {
+        let mut owl = habit("Read", Frequency::Daily, &["01:00"]);
+        owl.mark_completed(date("2024-05-01"));
+        let mut stub = Stub::default();
+        let window = (at("2024-05-02", "00:30"), at("2024-05-02", "01:30"));
+
+        remind(&[owl.clone()], &[], window, time("09:00"), 4, &mut stub);
+        assert!(stub.0.is_empty(), "done on the evening of May 1st");
+
+        remind(&[owl], &[], window, time("09:00"), 0, &mut stub);
+        assert_eq!(stub.0.len(), 1);
+    }
+}
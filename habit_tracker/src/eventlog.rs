@@ -0,0 +1,824 @@
+use crate::clock::Zone;
+use crate::habit::{self, CategoryColors, CheckIn, Frequency, Habit};
+use crate::storage::{self, Storage};
+use crate::todo::Todo;
+use chrono::{DateTime, Local, NaiveDate, NaiveTime};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+pub const LOG_FILE: &str = "events.jsonl";
+pub const SNAPSHOT_FILE: &str = "snapshot.json";
+/// The log being folded into the snapshot, moved aside so events appended
+/// meanwhile go to a fresh log.
+const FOLDING_FILE: &str = "events.jsonl.folding";
+
+/// One line of the log: a mutation, when it happened and who made it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entry {
+    pub time: DateTime<Local>,
+    pub actor: String,
+    #[serde(flatten)]
+    pub event: Event,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event {
+    HabitAdded {
+        habit: String,
+        category: String,
+        frequency: Frequency,
+    },
+    HabitRemoved {
+        habit: String,
+        category: String,
+    },
+    HabitRenamed {
+        habit: String,
+        category: String,
+        new_name: String,
+        new_category: String,
+    },
+    FrequencyChanged {
+        habit: String,
+        category: String,
+        frequency: Frequency,
+    },
+    /// The habit was given an id for syncing.
+    IdAssigned {
+        habit: String,
+        category: String,
+        id: String,
+    },
+    RemindersSet {
+        habit: String,
+        category: String,
+        reminders: Vec<NaiveTime>,
+    },
+    Paused {
+        habit: String,
+        category: String,
+    },
+    Resumed {
+        habit: String,
+        category: String,
+    },
+    /// A category was given a color, or had it removed.
+    CategoryColorSet {
+        category: String,
+        color: Option<String>,
+    },
+    Checked {
+        habit: String,
+        category: String,
+        date: NaiveDate,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        check_in: Option<CheckIn>,
+    },
+    Unchecked {
+        habit: String,
+        category: String,
+        date: NaiveDate,
+    },
+    /// A todo was added, or changed in any way; todos are identified by
+    /// their description.
+    TodoSaved {
+        todo: Todo,
+    },
+    TodoRemoved {
+        description: String,
+    },
+}
+
+impl Event {
+    /// The habit the event is about, as it was named at the time.
+    pub fn habit(&self) -> Option<(&str, &str)> {
+        match self {
+            Event::HabitAdded {
+                habit, category, ..
+            }
+            | Event::HabitRemoved { habit, category }
+            | Event::HabitRenamed {
+                habit, category, ..
+            }
+            | Event::FrequencyChanged {
+                habit, category, ..
+            }
+            | Event::IdAssigned {
+                habit, category, ..
+            }
+            | Event::RemindersSet {
+                habit, category, ..
+            }
+            | Event::Paused { habit, category }
+            | Event::Resumed { habit, category }
+            | Event::Checked {
+                habit, category, ..
+            }
+            | Event::Unchecked {
+                habit, category, ..
+            } => Some((habit, category)),
+            _ => None,
+        }
+    }
+
+    /// Applies the event to the data. Events are idempotent, so replaying
+    /// one that is already part of a snapshot does no harm: a rename whose
+    /// new name is taken, as it is once applied, changes nothing.
+    fn apply(&self, habits: &mut Vec<Habit>, todos: &mut Vec<Todo>, colors: &mut CategoryColors) {
+        let find = |habits: &mut Vec<Habit>, name: &str, category: &str| {
+            habits
+                .iter_mut()
+                .position(|h| h.name == name && h.category == category)
+        };
+        match self {
+            Event::HabitAdded {
+                habit,
+                category,
+                frequency,
+            } => {
+                if find(habits, habit, category).is_none() {
+                    habits.push(Habit::new(habit.clone(), category.clone(), *frequency));
+                }
+            }
+            Event::HabitRemoved { habit, category } => {
+                habits.retain(|h| !(h.name == *habit && h.category == *category));
+            }
+            Event::HabitRenamed {
+                habit,
+                category,
+                new_name,
+                new_category,
+            } => {
+                if find(habits, new_name, new_category).is_some() {
+                    return;
+                }
+                if let Some(index) = find(habits, habit, category) {
+                    habits[index].name = new_name.clone();
+                    habits[index].category = new_category.clone();
+                }
+            }
+            Event::FrequencyChanged {
+                habit,
+                category,
+                frequency,
+            } => {
+                if let Some(index) = find(habits, habit, category) {
+                    habits[index].frequency = *frequency;
+                }
+            }
+            Event::IdAssigned {
+                habit,
+                category,
+                id,
+            } => {
+                if let Some(index) = find(habits, habit, category) {
+                    habits[index].id = id.clone();
+                }
+            }
+            Event::RemindersSet {
+                habit,
+                category,
+                reminders,
+            } => {
+                if let Some(index) = find(habits, habit, category) {
+                    habits[index].reminders = reminders.clone();
+                }
+            }
+            Event::Paused { habit, category } | Event::Resumed { habit, category } => {
+                if let Some(index) = find(habits, habit, category) {
+                    habits[index].paused = matches!(self, Event::Paused { .. });
+                }
+            }
+            Event::CategoryColorSet { category, color } => {
+                habit::set_category_color(colors, category, color.as_deref());
+            }
+            Event::Checked {
+                habit,
+                category,
+                date,
+                check_in,
+            } => {
+                if let Some(index) = find(habits, habit, category) {
+                    match check_in {
+                        Some(check_in) => habits[index].check_in(*date, check_in.clone()),
+                        None => habits[index].mark_completed(*date),
+                    }
+                }
+            }
+            Event::Unchecked {
+                habit,
+                category,
+                date,
+            } => {
+                if let Some(index) = find(habits, habit, category) {
+                    habits[index].unmark_completed(*date);
+                }
+            }
+            Event::TodoSaved { todo } => {
+                match todos.iter_mut().find(|t| t.description == todo.description) {
+                    Some(existing) => *existing = todo.clone(),
+                    None => todos.push(todo.clone()),
+                }
+            }
+            Event::TodoRemoved { description } => {
+                todos.retain(|t| t.description != *description);
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for Event {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Event::HabitAdded { frequency, .. } => write!(f, "added ({})", frequency),
+            Event::HabitRemoved { .. } => write!(f, "removed"),
+            Event::HabitRenamed {
+                new_name,
+                new_category,
+                ..
+            } => write!(f, "renamed to {} ({})", new_name, new_category),
+            Event::FrequencyChanged { frequency, .. } => {
+                write!(f, "frequency changed to {}", frequency)
+            }
+            Event::IdAssigned { id, .. } => write!(f, "assigned sync id {}", id),
+            Event::RemindersSet { reminders, .. } if reminders.is_empty() => {
+                write!(f, "reminders removed")
+            }
+            Event::RemindersSet { reminders, .. } => {
+                let times: Vec<String> = reminders
+                    .iter()
+                    .map(|time| time.format("%H:%M").to_string())
+                    .collect();
+                write!(f, "reminders set to {}", times.join(", "))
+            }
+            Event::Paused { .. } => write!(f, "paused"),
+            Event::Resumed { .. } => write!(f, "resumed"),
+            Event::CategoryColorSet {
+                category,
+                color: None,
+            } => write!(f, "color of {} removed", category),
+            Event::CategoryColorSet {
+                category,
+                color: Some(color),
+            } => write!(f, "color of {} set to {}", category, color),
+            Event::Checked {
+                date,
+                check_in: Some(check_in),
+                ..
+            } => {
+                // In the zone it was made in, as the user saw the clock.
+                let at = match Zone::named(&check_in.zone) {
+                    Ok(zone) => zone.local_time(check_in.at),
+                    Err(_) => check_in.at.naive_utc(),
+                };
+                write!(
+                    f,
+                    "checked {} at {} {}",
+                    date,
+                    at.format("%Y-%m-%d %H:%M"),
+                    check_in.zone
+                )
+            }
+            Event::Checked { date, .. } => write!(f, "checked {}", date),
+            Event::Unchecked { date, .. } => write!(f, "unchecked {}", date),
+            Event::TodoSaved { todo } => write!(f, "todo saved: {}", todo.description),
+            Event::TodoRemoved { description } => write!(f, "todo removed: {}", description),
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct Snapshot {
+    habits: Vec<Habit>,
+    todos: Vec<Todo>,
+    #[serde(skip_serializing_if = "CategoryColors::is_empty")]
+    category_colors: CategoryColors,
+}
+
+/// Keeps every mutation in an append-only JSON Lines log. The current data
+/// is the last snapshot with the log replayed on top; compacting folds the
+/// log into a new snapshot.
+pub struct EventLogStorage {
+    log_file: PathBuf,
+    folding_file: PathBuf,
+    snapshot_file: PathBuf,
+}
+
+impl EventLogStorage {
+    pub fn new(dir: &Path) -> Self {
+        EventLogStorage {
+            log_file: dir.join(LOG_FILE),
+            folding_file: dir.join(FOLDING_FILE),
+            snapshot_file: dir.join(SNAPSHOT_FILE),
+        }
+    }
+
+    /// Every entry since the last compaction, oldest first, including those
+    /// of a compaction that was interrupted.
+    pub fn entries(&self) -> io::Result<Vec<Entry>> {
+        let mut entries = read_entries(&self.folding_file)?;
+        entries.extend(read_entries(&self.log_file)?);
+        Ok(entries)
+    }
+
+    fn snapshot(&self) -> io::Result<Snapshot> {
+        if !self.snapshot_file.exists() {
+            return Ok(Snapshot::default());
+        }
+        serde_json::from_str(&fs::read_to_string(&self.snapshot_file)?)
+            .map_err(|e| storage::corrupt(&self.snapshot_file, e))
+    }
+
+    fn replay(&self) -> io::Result<Snapshot> {
+        let mut snapshot = self.snapshot()?;
+        for entry in self.entries()? {
+            entry.event.apply(
+                &mut snapshot.habits,
+                &mut snapshot.todos,
+                &mut snapshot.category_colors,
+            );
+        }
+        Ok(snapshot)
+    }
+
+    fn append(&self, events: Vec<Event>) -> io::Result<()> {
+        if events.is_empty() {
+            return Ok(());
+        }
+        let time = Local::now();
+        let actor = actor();
+        let mut lines = String::new();
+        for event in events {
+            let entry = Entry {
+                time,
+                actor: actor.clone(),
+                event,
+            };
+            lines.push_str(&serde_json::to_string(&entry)?);
+            lines.push('\n');
+        }
+        // One write, so concurrent writers never interleave within a line.
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.log_file)?;
+        file.write_all(lines.as_bytes())?;
+        file.sync_all()
+    }
+
+    /// Folds the log into the snapshot. The log is first moved aside, so
+    /// events other processes append while this runs start a new log
+    /// instead of being lost. Returns how many events were folded.
+    pub fn compact(&self) -> io::Result<usize> {
+        // Left behind by an interrupted compaction, it is folded first and
+        // the current log waits for the next run.
+        if !self.folding_file.exists() && self.log_file.exists() {
+            fs::rename(&self.log_file, &self.folding_file)?;
+        }
+        let entries = read_entries(&self.folding_file)?;
+        let mut snapshot = self.snapshot()?;
+        for entry in &entries {
+            entry.event.apply(
+                &mut snapshot.habits,
+                &mut snapshot.todos,
+                &mut snapshot.category_colors,
+            );
+        }
+        let tmp_path = self.snapshot_file.with_extension("json.tmp");
+        fs::write(&tmp_path, serde_json::to_string_pretty(&snapshot)?)?;
+        fs::rename(&tmp_path, &self.snapshot_file)?;
+        // A crash before this point only means replaying events twice.
+        if self.folding_file.exists() {
+            fs::remove_file(&self.folding_file)?;
+        }
+        Ok(entries.len())
+    }
+}
+
+impl Storage for EventLogStorage {
+    fn load_habits(&self) -> io::Result<Vec<Habit>> {
+        Ok(self.replay()?.habits)
+    }
+
+    fn save_habits(&mut self, habits: &[Habit]) -> io::Result<()> {
+        let current = self.load_habits()?;
+        self.append(habit_events(&current, habits))
+    }
+
+    fn load_todos(&self) -> io::Result<Vec<Todo>> {
+        Ok(self.replay()?.todos)
+    }
+
+    fn save_todos(&mut self, todos: &[Todo]) -> io::Result<()> {
+        let current = self.load_todos()?;
+        let mut events = Vec::new();
+        for todo in todos {
+            if !current.contains(todo) {
+                events.push(Event::TodoSaved { todo: todo.clone() });
+            }
+        }
+        for old in &current {
+            if !todos.iter().any(|t| t.description == old.description) {
+                events.push(Event::TodoRemoved {
+                    description: old.description.clone(),
+                });
+            }
+        }
+        self.append(events)
+    }
+
+    fn load_category_colors(&self) -> io::Result<CategoryColors> {
+        Ok(self.replay()?.category_colors)
+    }
+
+    fn save_category_colors(&mut self, colors: &CategoryColors) -> io::Result<()> {
+        let current = self.load_category_colors()?;
+        let mut events = Vec::new();
+        for (category, color) in colors {
+            if current.get(category) != Some(color) {
+                events.push(Event::CategoryColorSet {
+                    category: category.clone(),
+                    color: Some(color.clone()),
+                });
+            }
+        }
+        for category in current.keys() {
+            if !colors.contains_key(category) {
+                events.push(Event::CategoryColorSet {
+                    category: category.clone(),
+                    color: None,
+                });
+            }
+        }
+        self.append(events)
+    }
+
+    fn watched_files(&self) -> Vec<PathBuf> {
+        vec![
+            self.log_file.clone(),
+            self.folding_file.clone(),
+            self.snapshot_file.clone(),
+        ]
+    }
+
+    fn record_rename(
+        &mut self,
+        name: &str,
+        category: &str,
+        new_name: &str,
+        new_category: &str,
+    ) -> io::Result<()> {
+        self.append(vec![Event::HabitRenamed {
+            habit: name.to_string(),
+            category: category.to_string(),
+            new_name: new_name.to_string(),
+            new_category: new_category.to_string(),
+        }])
+    }
+
+    /// Logged right away, so the event carries the time the box was ticked.
+    fn record_check_in(&mut self, habit: &Habit, date: NaiveDate) -> io::Result<bool> {
+        let (name, category) = (habit.name.clone(), habit.category.clone());
+        self.append(vec![if habit.is_completed(date) {
+            Event::Checked {
+                habit: name,
+                category,
+                date,
+                check_in: habit.check_in_on(date).cloned(),
+            }
+        } else {
+            Event::Unchecked {
+                habit: name,
+                category,
+                date,
+            }
+        }])?;
+        Ok(true)
+    }
+}
+
+/// The events turning `old` into `new`. Habits are matched by id, then by
+/// name and category. Without an id a rename looks like a removal and an
+/// addition, so the UI logs renames as they happen with `record_rename`.
+fn habit_events(old: &[Habit], new: &[Habit]) -> Vec<Event> {
+    let mut unmatched_old: Vec<&Habit> = old.iter().collect();
+    let mut pairs = Vec::new();
+    let mut added = Vec::new();
+    for habit in new {
+        let position = unmatched_old
+            .iter()
+            .position(|h| !h.id.is_empty() && h.id == habit.id)
+            .or_else(|| {
+                unmatched_old
+                    .iter()
+                    .position(|h| h.name == habit.name && h.category == habit.category)
+            });
+        match position {
+            Some(index) => pairs.push((unmatched_old.remove(index), habit)),
+            None => added.push(habit),
+        }
+    }
+    let mut events = Vec::new();
+    for old in unmatched_old {
+        events.push(Event::HabitRemoved {
+            habit: old.name.clone(),
+            category: old.category.clone(),
+        });
+    }
+    for (old, new) in pairs {
+        if old.name != new.name || old.category != new.category {
+            events.push(Event::HabitRenamed {
+                habit: old.name.clone(),
+                category: old.category.clone(),
+                new_name: new.name.clone(),
+                new_category: new.category.clone(),
+            });
+        }
+        if old.frequency != new.frequency {
+            events.push(Event::FrequencyChanged {
+                habit: new.name.clone(),
+                category: new.category.clone(),
+                frequency: new.frequency,
+            });
+        }
+        events.extend(setting_events(old, new));
+        events.extend(date_events(old, new));
+    }
+    for habit in added {
+        events.push(Event::HabitAdded {
+            habit: habit.name.clone(),
+            category: habit.category.clone(),
+            frequency: habit.frequency,
+        });
+        events.extend(setting_events(&Habit::default(), habit));
+        events.extend(date_events(&Habit::default(), habit));
+    }
+    events
+}
+
+/// Events for the sync id, reminders and pausing, which are set on a habit
+/// as a whole.
+fn setting_events(old: &Habit, new: &Habit) -> Vec<Event> {
+    let (habit, category) = (new.name.clone(), new.category.clone());
+    let mut events = Vec::new();
+    if old.id != new.id {
+        events.push(Event::IdAssigned {
+            habit: habit.clone(),
+            category: category.clone(),
+            id: new.id.clone(),
+        });
+    }
+    if old.reminders != new.reminders {
+        events.push(Event::RemindersSet {
+            habit: habit.clone(),
+            category: category.clone(),
+            reminders: new.reminders.clone(),
+        });
+    }
+    if old.paused != new.paused {
+        events.push(if new.paused {
+            Event::Paused { habit, category }
+        } else {
+            Event::Resumed { habit, category }
+        });
+    }
+    events
+}
+
+/// Check-ins and their removal. A date checked before but given a different
+/// check-in, as a merge may do, is logged again.
+fn date_events(old: &Habit, new: &Habit) -> Vec<Event> {
+    let checked = new
+        .completed_dates()
+        .iter()
+        .filter(|d| {
+            !old.is_completed(**d)
+                || (new.check_in_on(**d).is_some() && old.check_in_on(**d) != new.check_in_on(**d))
+        })
+        .map(|&date| Event::Checked {
+            habit: new.name.clone(),
+            category: new.category.clone(),
+            date,
+            check_in: new.check_in_on(date).cloned(),
+        });
+    let unchecked = old
+        .completed_dates()
+        .iter()
+        .filter(|d| !new.is_completed(**d))
+        .map(|&date| Event::Unchecked {
+            habit: new.name.clone(),
+            category: new.category.clone(),
+            date,
+        });
+    checked.chain(unchecked).collect()
+}
+
+fn read_entries(path: &Path) -> io::Result<Vec<Entry>> {
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    fs::read_to_string(path)?
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| {
+            serde_json::from_str(line)
+                .map_err(|e| storage::corrupt(path, format!("line {}: {}", index + 1, e)))
+        })
+        .collect()
+}
+
+/// Who to record as making a change: the login name, if known.
+fn actor() -> String {
+    std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .unwrap_or_else(|_| "unknown".to_string())
+}
+
+/// The entries about one habit, oldest first, following it back through
+/// renames. Without a category, habits of that name in any category match.
+pub fn history<'a>(entries: &'a [Entry], name: &str, category: Option<&str>) -> Vec<&'a Entry> {
+    let mut aliases = vec![(name.to_string(), category.map(str::to_string))];
+    let matches = |aliases: &[(String, Option<String>)], name: &str, category: &str| {
+        aliases
+            .iter()
+            .any(|(n, c)| n == name && c.as_deref().is_none_or(|c| c == category))
+    };
+
+    let mut found = Vec::new();
+    for entry in entries.iter().rev() {
+        if let Event::HabitRenamed {
+            habit,
+            category,
+            new_name,
+            new_category,
+        } = &entry.event
+        {
+            if matches(&aliases, new_name, new_category) {
+                aliases.push((habit.clone(), Some(category.clone())));
+                found.push(entry);
+                continue;
+            }
+        }
+        if let Some((habit, category)) = entry.event.habit() {
+            if matches(&aliases, habit, category) {
+                found.push(entry);
+            }
+        }
+    }
+    found.reverse();
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "habit_tracker-eventlog-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn day(d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 5, d).unwrap()
+    }
+
+    #[test]
+    fn events_appended_during_compaction_are_kept() {
+        let dir = scratch_dir("compact");
+        let mut log = EventLogStorage::new(&dir);
+        let mut run = Habit::new("Run".into(), "Health".into(), Frequency::Daily);
+        log.save_habits(std::slice::from_ref(&run)).unwrap();
+        log.record_check_in(&run, day(1)).unwrap();
+
+        // A compaction interrupted after moving the log aside, with a check
+        // made by another process since.
+        fs::rename(&log.log_file, &log.folding_file).unwrap();
+        let check_in = CheckIn {
+            at: "2024-05-02T08:00:00Z".parse().unwrap(),
+            zone: "UTC".to_string(),
+        };
+        run.check_in(day(2), check_in.clone());
+        log.record_check_in(&run, day(2)).unwrap();
+        assert_eq!(log.entries().unwrap().len(), 3);
+
+        assert_eq!(log.compact().unwrap(), 2);
+        assert_eq!(log.entries().unwrap().len(), 1);
+        assert_eq!(log.compact().unwrap(), 1);
+        assert!(log.entries().unwrap().is_empty());
+        let habits = log.load_habits().unwrap();
+        assert_eq!(habits[0].completed_dates(), &[day(2)]);
+        assert_eq!(habits[0].check_in_on(day(2)), Some(&check_in));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    fn habit(name: &str) -> Habit {
+        Habit::new(name.into(), "Health".into(), Frequency::Daily)
+    }
+
+    #[test]
+    fn deleting_one_habit_and_adding_another_is_not_a_rename() {
+        let events = habit_events(&[habit("Run")], &[habit("Read")]);
+        assert_eq!(
+            events,
+            [
+                Event::HabitRemoved {
+                    habit: "Run".into(),
+                    category: "Health".into(),
+                },
+                Event::HabitAdded {
+                    habit: "Read".into(),
+                    category: "Health".into(),
+                    frequency: Frequency::Daily,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn renames_are_logged_and_followed_by_history() {
+        let dir = scratch_dir("rename");
+        let mut log = EventLogStorage::new(&dir);
+        log.save_habits(&[habit("Run")]).unwrap();
+        log.record_rename("Run", "Health", "Jog", "Health").unwrap();
+        // Saving the renamed list afterwards adds nothing more.
+        log.save_habits(&[habit("Jog")]).unwrap();
+
+        let entries = log.entries().unwrap();
+        assert_eq!(entries.len(), 2);
+        let found = history(&entries, "Jog", None);
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[1].event.to_string(), "renamed to Jog (Health)");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn category_colors_are_logged_once_per_category() {
+        let dir = scratch_dir("colors");
+        let mut log = EventLogStorage::new(&dir);
+        log.save_habits(&[habit("Run"), habit("Swim")]).unwrap();
+        let mut colors = CategoryColors::from([("Health".to_string(), "green".to_string())]);
+        log.save_category_colors(&colors).unwrap();
+        // Saving the same colors again adds nothing.
+        log.save_category_colors(&colors).unwrap();
+        assert_eq!(log.entries().unwrap().len(), 3);
+        assert_eq!(log.load_category_colors().unwrap(), colors);
+
+        colors.clear();
+        log.save_category_colors(&colors).unwrap();
+        let entries = log.entries().unwrap();
+        assert_eq!(entries[3].event.to_string(), "color of Health removed");
+        // A category event belongs to no habit's history.
+        assert_eq!(history(&entries, "Run", None).len(), 1);
+
+        log.compact().unwrap();
+        assert!(log.load_category_colors().unwrap().is_empty());
+        assert_eq!(log.load_habits().unwrap().len(), 2);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn unreadable_log_lines_and_snapshots_are_reported_with_their_file() {
+        let dir = scratch_dir("corrupt");
+        let log = EventLogStorage::new(&dir);
+        fs::write(&log.log_file, "\n{\"type\": \"paused\"\n").unwrap();
+        let err = log.load_habits().unwrap_err();
+        let corrupt = storage::corrupt_file(&err).unwrap();
+        assert_eq!(corrupt.path, log.log_file);
+        assert!(corrupt.reason.starts_with("line 2: "), "{}", corrupt.reason);
+
+        fs::remove_file(&log.log_file).unwrap();
+        fs::write(&log.snapshot_file, "{ \"habits\": 3 }").unwrap();
+        let err = log.load_todos().unwrap_err();
+        assert_eq!(storage::corrupt_file(&err).unwrap().path, log.snapshot_file);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn replaying_a_rename_leaves_a_new_habit_of_the_old_name_alone() {
+        let rename = Event::HabitRenamed {
+            habit: "Run".into(),
+            category: "Health".into(),
+            new_name: "Jog".into(),
+            new_category: "Health".into(),
+        };
+        let (mut habits, mut todos) = (vec![habit("Run")], Vec::new());
+        let mut colors = CategoryColors::new();
+        rename.apply(&mut habits, &mut todos, &mut colors);
+        habits.push(habit("Run"));
+        rename.apply(&mut habits, &mut todos, &mut colors);
+        let names: Vec<&str> = habits.iter().map(|h| h.name.as_str()).collect();
+        assert_eq!(names, ["Jog", "Run"]);
+    }
+}
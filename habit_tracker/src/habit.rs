@@ -1,10 +1,14 @@
+use crate::clock;
 use chrono::Datelike;
-use chrono::NaiveDate;
+use chrono::{DateTime, NaiveDate, NaiveTime, Utc};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fmt;
+use std::str::FromStr;
 
-#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[derive(Debug, Default, Serialize, Deserialize, Clone, Copy, PartialEq)]
 pub enum Frequency {
+    #[default]
     Daily,
     Weekly,
     Monthly,
@@ -20,21 +24,70 @@ impl fmt::Display for Frequency {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+impl FromStr for Frequency {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "daily" => Ok(Frequency::Daily),
+            "weekly" => Ok(Frequency::Weekly),
+            "monthly" => Ok(Frequency::Monthly),
+            _ => Err(format!("unknown frequency: {}", s)),
+        }
+    }
+}
+
+/// The first and last day of the week containing `date`, with weeks
+/// starting on the configured day.
+pub fn week_bounds(date: NaiveDate) -> (NaiveDate, NaiveDate) {
+    let start =
+        date - chrono::Duration::days(date.weekday().days_since(clock::week_start()) as i64);
+    (start, start + chrono::Duration::days(6))
+}
+
+/// When a habit was checked off and the time zone the clock was in, kept
+/// for check-ins made live rather than backfilled.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct CheckIn {
+    pub at: DateTime<Utc>,
+    pub zone: String,
+}
+
+/// Fields missing from a data file fall back to their defaults, so records
+/// written before a field existed still load.
+#[derive(Debug, Default, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
 pub struct Habit {
+    /// Stable identifier used when syncing with a server; assigned the
+    /// first time the habit is synced, so it survives renames.
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub id: String,
     pub name: String,
     pub category: String,
     pub frequency: Frequency,
     completed_dates: Vec<NaiveDate>,
+    /// Times of day `habit_tracker daemon` reminds about the habit while it
+    /// is not done for the current period.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub reminders: Vec<NaiveTime>,
+    /// Paused habits get no reminders.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub paused: bool,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    check_ins: BTreeMap<NaiveDate, CheckIn>,
 }
 
 impl Habit {
     pub fn new(name: String, category: String, frequency: Frequency) -> Self {
         Habit {
+            id: String::new(),
             name,
             category,
             frequency,
             completed_dates: Vec::new(),
+            reminders: Vec::new(),
+            paused: false,
+            check_ins: BTreeMap::new(),
         }
     }
 
@@ -45,8 +98,36 @@ impl Habit {
         }
     }
 
+    /// Marks `date` completed, recording when and where that happened.
+    pub fn check_in(&mut self, date: NaiveDate, check_in: CheckIn) {
+        self.mark_completed(date);
+        self.check_ins.insert(date, check_in);
+    }
+
+    /// Marks or unmarks `date`; a check-in, if any, tells how it was marked.
+    pub fn set_completed(&mut self, date: NaiveDate, done: bool, check_in: Option<CheckIn>) {
+        match (done, check_in) {
+            (true, Some(check_in)) => self.check_in(date, check_in),
+            (true, None) => self.mark_completed(date),
+            (false, _) => self.unmark_completed(date),
+        }
+    }
+
     pub fn unmark_completed(&mut self, date: NaiveDate) {
         self.completed_dates.retain(|&d| d != date);
+        self.check_ins.remove(&date);
+    }
+
+    pub fn check_in_on(&self, date: NaiveDate) -> Option<&CheckIn> {
+        self.check_ins.get(&date)
+    }
+
+    pub fn check_ins(&self) -> &BTreeMap<NaiveDate, CheckIn> {
+        &self.check_ins
+    }
+
+    pub fn completed_dates(&self) -> &[NaiveDate] {
+        &self.completed_dates
     }
 
     pub fn is_completed(&self, date: NaiveDate) -> bool {
@@ -73,9 +154,9 @@ impl Habit {
                     }
                 }
                 Frequency::Weekly => {
-                    if last_completed.iso_week() == current_date.iso_week() {
+                    if week_bounds(*last_completed).0 == week_bounds(current_date).0 {
                         streak += 1;
-                        current_date = current_date - chrono::Duration::weeks(1);
+                        current_date -= chrono::Duration::weeks(1);
                     } else {
                         break;
                     }
@@ -116,4 +197,42 @@ impl Habit {
     pub fn get_current_streak(&self, date: NaiveDate) -> u32 {
         self.get_streak(date)
     }
+
+    /// Whether the habit was done in the day, week or month (by frequency)
+    /// containing `date`.
+    pub fn is_done_in_period(&self, date: NaiveDate) -> bool {
+        match self.frequency {
+            Frequency::Daily => self.is_completed(date),
+            Frequency::Weekly => {
+                let (start, end) = week_bounds(date);
+                self.completed_dates
+                    .iter()
+                    .any(|d| (start..=end).contains(d))
+            }
+            Frequency::Monthly => self
+                .completed_dates
+                .iter()
+                .any(|d| d.year() == date.year() && d.month() == date.month()),
+        }
+    }
+}
+
+/// Colors given to categories, by category name. Kept apart from the
+/// habits, so a category has one color however many habits it holds.
+pub type CategoryColors = BTreeMap<String, String>;
+
+/// Sets the color of `category`, or removes it.
+pub fn set_category_color(colors: &mut CategoryColors, category: &str, color: Option<&str>) {
+    match color {
+        Some(color) => colors.insert(category.to_string(), color.to_string()),
+        None => colors.remove(category),
+    };
+}
+
+/// Moves the color of a renamed category to its new name, unless that
+/// category has a color already.
+pub fn rename_category_color(colors: &mut CategoryColors, category: &str, new_category: &str) {
+    if let Some(color) = colors.remove(category) {
+        colors.entry(new_category.to_string()).or_insert(color);
+    }
 }
@@ -0,0 +1,338 @@
+use crate::habit::{Frequency, Habit};
+use chrono::NaiveDate;
+use std::collections::BTreeSet;
+use std::fmt;
+use std::io;
+use std::str::FromStr;
+
+const DATE_FORMAT: &str = "%Y-%m-%d";
+const LONG_HEADER: [&str; 4] = ["habit", "category", "date", "value"];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Layout {
+    /// One row per check-in: habit, category, date, value.
+    Long,
+    /// One row per day and one column per habit, headed `category/habit`
+    /// with `/` and `\\` in either part escaped by a backslash.
+    Wide,
+}
+
+/// What to do when an imported habit already exists.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Conflict {
+    /// Add the imported completions to the existing ones.
+    Merge,
+    /// Let imported values win, unmarking days imported as 0.
+    Overwrite,
+    /// Leave existing habits untouched.
+    Skip,
+}
+
+impl FromStr for Conflict {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "merge" => Ok(Conflict::Merge),
+            "overwrite" => Ok(Conflict::Overwrite),
+            "skip" => Ok(Conflict::Skip),
+            _ => Err(format!("unknown conflict mode: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ImportSummary {
+    pub habits_added: usize,
+    pub habits_skipped: usize,
+    pub days_marked: usize,
+    pub days_unmarked: usize,
+    pub bad_rows: Vec<String>,
+}
+
+impl fmt::Display for ImportSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} habits added, {} skipped, {} days marked, {} unmarked",
+            self.habits_added, self.habits_skipped, self.days_marked, self.days_unmarked
+        )?;
+        if !self.bad_rows.is_empty() {
+            write!(f, ", {} rows ignored", self.bad_rows.len())?;
+        }
+        Ok(())
+    }
+}
+
+pub fn export(habits: &[Habit], layout: Layout) -> io::Result<String> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    match layout {
+        Layout::Long => {
+            writer.write_record(LONG_HEADER)?;
+            for habit in habits {
+                for date in habit.completed_dates() {
+                    let date = date.format(DATE_FORMAT).to_string();
+                    writer.write_record([&habit.name, &habit.category, &date, "1"])?;
+                }
+            }
+        }
+        Layout::Wide => {
+            let mut header = vec!["date".to_string()];
+            header.extend(habits.iter().map(column_name));
+            writer.write_record(&header)?;
+
+            let dates = habits.iter().flat_map(|h| h.completed_dates().iter());
+            if let (Some(&first), Some(&last)) = (dates.clone().min(), dates.max()) {
+                for date in first.iter_days().take_while(|d| *d <= last) {
+                    let mut row = vec![date.format(DATE_FORMAT).to_string()];
+                    row.extend(
+                        habits
+                            .iter()
+                            .map(|h| if h.is_completed(date) { "1" } else { "0" }.to_string()),
+                    );
+                    writer.write_record(&row)?;
+                }
+            }
+        }
+    }
+    let bytes = writer.into_inner().map_err(|e| e.into_error())?;
+    String::from_utf8(bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Imports check-ins from either layout, recognised by the header row.
+/// Habits not present yet are created as daily habits.
+pub fn import(
+    habits: &mut Vec<Habit>,
+    contents: &str,
+    conflict: Conflict,
+) -> io::Result<ImportSummary> {
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .from_reader(contents.as_bytes());
+    let header: Vec<String> = reader.headers()?.iter().map(str::to_string).collect();
+
+    let mut check_ins = Vec::new();
+    let mut summary = ImportSummary::default();
+    let layout = if header.first().map(String::as_str) == Some("date") {
+        Layout::Wide
+    } else if header.iter().take(LONG_HEADER.len()).eq(LONG_HEADER.iter()) {
+        // Older exports had an always empty `note` column after these.
+        Layout::Long
+    } else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "unrecognised CSV header; expected `habit,category,date,value` or `date,...`",
+        ));
+    };
+
+    for (index, record) in reader.records().enumerate() {
+        let record = record?;
+        let line = index + 2;
+        let date = match layout {
+            Layout::Long => record.get(2),
+            Layout::Wide => record.get(0),
+        };
+        let Some(date) = date.and_then(|d| NaiveDate::parse_from_str(d.trim(), DATE_FORMAT).ok())
+        else {
+            summary
+                .bad_rows
+                .push(format!("line {}: missing or invalid date", line));
+            continue;
+        };
+        match layout {
+            Layout::Long => {
+                let (Some(name), Some(category)) = (record.get(0), record.get(1)) else {
+                    summary
+                        .bad_rows
+                        .push(format!("line {}: missing habit", line));
+                    continue;
+                };
+                let value = parse_value(record.get(3).unwrap_or("1"));
+                check_ins.push((name.to_string(), category.to_string(), date, value));
+            }
+            Layout::Wide => {
+                for (column, value) in header.iter().zip(record.iter()).skip(1) {
+                    let (category, name) = split_column(column);
+                    check_ins.push((name, category, date, parse_value(value)));
+                }
+            }
+        }
+    }
+
+    let existing: BTreeSet<(String, String)> = habits
+        .iter()
+        .map(|h| (h.name.clone(), h.category.clone()))
+        .collect();
+    let mut skipped = BTreeSet::new();
+    for (name, category, date, value) in check_ins {
+        let key = (name, category);
+        if existing.contains(&key) && conflict == Conflict::Skip {
+            skipped.insert(key);
+            continue;
+        }
+        let index = match habits
+            .iter()
+            .position(|h| h.name == key.0 && h.category == key.1)
+        {
+            Some(index) => index,
+            None => {
+                habits.push(Habit::new(key.0, key.1, Frequency::Daily));
+                summary.habits_added += 1;
+                habits.len() - 1
+            }
+        };
+        let habit = &mut habits[index];
+        if value && !habit.is_completed(date) {
+            habit.mark_completed(date);
+            summary.days_marked += 1;
+        } else if !value && conflict == Conflict::Overwrite && habit.is_completed(date) {
+            habit.unmark_completed(date);
+            summary.days_unmarked += 1;
+        }
+    }
+    summary.habits_skipped = skipped.len();
+    Ok(summary)
+}
+
+/// The wide layout's `category/habit` column header.
+fn column_name(habit: &Habit) -> String {
+    let escape = |s: &str| s.replace('\\', "\\\\").replace('/', "\\/");
+    format!("{}/{}", escape(&habit.category), escape(&habit.name))
+}
+
+/// Category and habit name from a column header, split at the first `/`
+/// not escaped; a header without one is a habit without category.
+fn split_column(column: &str) -> (String, String) {
+    let mut category = String::new();
+    let mut name: Option<String> = None;
+    let mut chars = column.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '/' if name.is_none() => name = Some(String::new()),
+            '\\' => {
+                let escaped = chars.next();
+                name.as_mut().unwrap_or(&mut category).extend(escaped);
+            }
+            c => name.as_mut().unwrap_or(&mut category).push(c),
+        }
+    }
+    match name {
+        Some(name) => (category, name),
+        None => (String::new(), category),
+    }
+}
+
+fn parse_value(value: &str) -> bool {
+    match value.trim().to_ascii_lowercase().as_str() {
+        "" | "0" | "false" | "no" | "n" => false,
+        "1" | "true" | "yes" | "y" | "x" | "✓" => true,
+        other => other.parse::<f64>().map(|v| v > 0.0).unwrap_or(false),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn day(d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 5, d).unwrap()
+    }
+
+    fn habit(name: &str, category: &str, done: &[u32]) -> Habit {
+        let mut habit = Habit::new(name.into(), category.into(), Frequency::Daily);
+        for d in done {
+            habit.mark_completed(day(*d));
+        }
+        habit
+    }
+
+    fn sample() -> Vec<Habit> {
+        vec![
+            habit("Run", "Health", &[1, 3]),
+            habit("Push/pull", "Gym/Strength", &[2]),
+            habit("C:\\temp", "Chores", &[3]),
+        ]
+    }
+
+    fn round_trip(layout: Layout) -> Vec<Habit> {
+        let csv = export(&sample(), layout).unwrap();
+        let mut habits = Vec::new();
+        let summary = import(&mut habits, &csv, Conflict::Merge).unwrap();
+        assert_eq!(summary.habits_added, 3);
+        assert_eq!(summary.days_marked, 4);
+        assert!(summary.bad_rows.is_empty(), "{:?}", summary.bad_rows);
+        habits
+    }
+
+    #[test]
+    fn both_layouts_round_trip() {
+        assert_eq!(round_trip(Layout::Long), sample());
+        assert_eq!(round_trip(Layout::Wide), sample());
+    }
+
+    #[test]
+    fn writes_the_expected_layouts() {
+        let habits = [habit("Run", "Health", &[1, 3])];
+        assert_eq!(
+            export(&habits, Layout::Long).unwrap(),
+            "habit,category,date,value\nRun,Health,2024-05-01,1\nRun,Health,2024-05-03,1\n"
+        );
+        assert_eq!(
+            export(&habits, Layout::Wide).unwrap(),
+            "date,Health/Run\n2024-05-01,1\n2024-05-02,0\n2024-05-03,1\n"
+        );
+        let slashes = [habit("Push/pull", "Gym/Strength", &[])];
+        assert!(export(&slashes, Layout::Wide)
+            .unwrap()
+            .starts_with("date,Gym\\/Strength/Push\\/pull\n"));
+    }
+
+    #[test]
+    fn reads_older_exports_and_headers_without_escapes() {
+        let long = "habit,category,date,value,note\nRun,Health,2024-05-01,1,\n";
+        let mut habits = Vec::new();
+        import(&mut habits, long, Conflict::Merge).unwrap();
+        assert_eq!(habits, [habit("Run", "Health", &[1])]);
+
+        let wide = "date,Health/Push/pull,Stretch\n2024-05-02,yes,x\n";
+        let mut habits = Vec::new();
+        import(&mut habits, wide, Conflict::Merge).unwrap();
+        assert_eq!(
+            habits,
+            [
+                habit("Push/pull", "Health", &[2]),
+                habit("Stretch", "", &[2])
+            ]
+        );
+    }
+
+    #[test]
+    fn conflicts_merge_overwrite_or_skip() {
+        let csv = "habit,category,date,value\n\
+                   Run,Health,2024-05-01,0\n\
+                   Run,Health,2024-05-02,1\n\
+                   Run,Health,someday,1\n";
+        let existing = || vec![habit("Run", "Health", &[1])];
+
+        let mut habits = existing();
+        let summary = import(&mut habits, csv, Conflict::Merge).unwrap();
+        assert_eq!(habits, [habit("Run", "Health", &[1, 2])]);
+        assert_eq!(summary.bad_rows, ["line 4: missing or invalid date"]);
+
+        let mut habits = existing();
+        let summary = import(&mut habits, csv, Conflict::Overwrite).unwrap();
+        assert_eq!(habits, [habit("Run", "Health", &[2])]);
+        assert_eq!((summary.days_marked, summary.days_unmarked), (1, 1));
+
+        let mut habits = existing();
+        let summary = import(&mut habits, csv, Conflict::Skip).unwrap();
+        assert_eq!(habits, existing());
+        assert_eq!(summary.habits_skipped, 1);
+    }
+
+    #[test]
+    fn rejects_unknown_headers() {
+        let err = import(&mut Vec::new(), "name,when\nRun,today\n", Conflict::Merge).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,508 @@
+use crate::config::{Hooks, Webhook};
+use crate::habit::{Frequency, Habit};
+use crate::todo::Todo;
+use crate::webhook::{self, Delivery, QueueLock};
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use std::fs::OpenOptions;
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::process::{Child, Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// Failed and timed-out hooks and webhooks are recorded here, in the data
+/// directory.
+pub const LOG_FILE: &str = "hooks.log";
+
+/// How long quitting waits for webhooks being sent.
+const WEBHOOK_GRACE: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    HabitCompleted,
+    StreakMilestone,
+    TodoDone,
+    DayCompleted,
+}
+
+/// Something that happened in the UI that hooks can react to.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    HabitCompleted {
+        habit: String,
+        category: String,
+        date: NaiveDate,
+        streak: u32,
+    },
+    /// A habit's streak reached one of the configured milestones, counted
+    /// in days, weeks or months by `frequency`.
+    StreakMilestone {
+        habit: String,
+        category: String,
+        date: NaiveDate,
+        streak: u32,
+        frequency: Frequency,
+    },
+    TodoDone {
+        description: String,
+    },
+    /// The last habit not paused and not yet done for its period containing
+    /// `date` was checked.
+    DayCompleted {
+        date: NaiveDate,
+        habits: usize,
+    },
+}
+
+impl Event {
+    pub fn kind(&self) -> EventKind {
+        match self {
+            Event::HabitCompleted { .. } => EventKind::HabitCompleted,
+            Event::StreakMilestone { .. } => EventKind::StreakMilestone,
+            Event::TodoDone { .. } => EventKind::TodoDone,
+            Event::DayCompleted { .. } => EventKind::DayCompleted,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self.kind() {
+            EventKind::HabitCompleted => "habit_completed",
+            EventKind::StreakMilestone => "streak_milestone",
+            EventKind::TodoDone => "todo_done",
+            EventKind::DayCompleted => "day_completed",
+        }
+    }
+
+    /// A sentence describing the event, for chat messages.
+    pub fn message(&self) -> String {
+        match self {
+            Event::HabitCompleted { habit, streak, .. } => {
+                format!("{} done, streak {}", habit, streak)
+            }
+            Event::StreakMilestone {
+                habit,
+                streak,
+                frequency,
+                ..
+            } => {
+                let unit = match frequency {
+                    Frequency::Daily => "day",
+                    Frequency::Weekly => "week",
+                    Frequency::Monthly => "month",
+                };
+                format!("{} reached a {}-{} streak!", habit, streak, unit)
+            }
+            Event::TodoDone { description } => format!("Done: {}", description),
+            Event::DayCompleted { date, habits } => {
+                format!("All {} habits done for {}!", habits, date)
+            }
+        }
+    }
+
+    /// Whether a consumer listening for `milestones` cares about this event:
+    /// only streak milestones are filtered.
+    fn reaches(&self, milestones: &[u32]) -> bool {
+        match self {
+            Event::StreakMilestone { streak, .. } => milestones.contains(streak),
+            _ => true,
+        }
+    }
+}
+
+/// The events brought about by going from the old habits and todos to the
+/// new ones while `date` is shown.
+pub fn events(
+    old: (&[Habit], &[Todo]),
+    new: (&[Habit], &[Todo]),
+    date: NaiveDate,
+    milestones: &[u32],
+) -> Vec<Event> {
+    let (old_habits, old_todos) = old;
+    let (habits, todos) = new;
+    let mut events = Vec::new();
+    for habit in habits.iter().filter(|h| h.is_completed(date)) {
+        let was_completed = old_habits
+            .iter()
+            .any(|h| h.name == habit.name && h.category == habit.category && h.is_completed(date));
+        if was_completed {
+            continue;
+        }
+        let streak = habit.get_current_streak(date);
+        events.push(Event::HabitCompleted {
+            habit: habit.name.clone(),
+            category: habit.category.clone(),
+            date,
+            streak,
+        });
+        if milestones.contains(&streak) {
+            events.push(Event::StreakMilestone {
+                habit: habit.name.clone(),
+                category: habit.category.clone(),
+                date,
+                streak,
+                frequency: habit.frequency,
+            });
+        }
+    }
+    for todo in todos.iter().filter(|t| t.completed) {
+        if !old_todos
+            .iter()
+            .any(|t| t.description == todo.description && t.completed)
+        {
+            events.push(Event::TodoDone {
+                description: todo.description.clone(),
+            });
+        }
+    }
+    let active = |habits: &[Habit]| -> Vec<bool> {
+        habits
+            .iter()
+            .filter(|h| !h.paused)
+            .map(|h| h.is_done_in_period(date))
+            .collect()
+    };
+    let all_done = |done: &[bool]| !done.is_empty() && done.iter().all(|d| *d);
+    let (done, old_done) = (active(habits), active(old_habits));
+    if all_done(&done) && !all_done(&old_done) {
+        events.push(Event::DayCompleted {
+            date,
+            habits: done.len(),
+        });
+    }
+    events
+}
+
+/// Runs the configured hook commands and webhooks in the background, so a
+/// slow one does not hold up the UI.
+pub struct Runner {
+    config: Hooks,
+    webhooks: Vec<Webhook>,
+    queue: Arc<QueueLock>,
+    /// Hook commands still running.
+    running: Vec<JoinHandle<()>>,
+    /// The thread sending the webhook queue, if any.
+    sender: Option<JoinHandle<()>>,
+}
+
+impl Runner {
+    /// Also starts sending webhooks queued while offline in an earlier
+    /// session.
+    pub fn new(config: Hooks, webhooks: Vec<Webhook>) -> Self {
+        let mut runner = Runner {
+            config,
+            webhooks,
+            queue: Arc::new(Mutex::new(false)),
+            running: Vec::new(),
+            sender: None,
+        };
+        if Path::new(webhook::QUEUE_FILE).exists() {
+            runner.send(Vec::new());
+        }
+        runner
+    }
+
+    /// Streak lengths anyone listens for, hooks or webhooks.
+    pub fn milestones(&self) -> Vec<u32> {
+        let mut milestones = self.config.milestones.clone();
+        for webhook in &self.webhooks {
+            milestones.extend(&webhook.milestones);
+        }
+        milestones
+    }
+
+    /// Starts the hook and webhooks for each event that has them. A hook
+    /// command is run by `sh -c` with the event as JSON on stdin and in
+    /// `HABIT_TRACKER_EVENT_JSON`, and each of its fields in
+    /// `HABIT_TRACKER_<FIELD>`.
+    pub fn fire(&mut self, events: &[Event]) {
+        self.running.retain(|handle| !handle.is_finished());
+        let deliveries: Vec<Delivery> = events
+            .iter()
+            .flat_map(|event| {
+                self.webhooks
+                    .iter()
+                    .filter(|w| w.events.contains(&event.kind()) && event.reaches(&w.milestones))
+                    .map(|w| Delivery::new(w, event))
+            })
+            .collect();
+        if !deliveries.is_empty() {
+            self.send(deliveries);
+        }
+        for event in events.iter().filter(|e| e.reaches(&self.config.milestones)) {
+            let command = match event.kind() {
+                EventKind::HabitCompleted => &self.config.habit_completed,
+                EventKind::StreakMilestone => &self.config.streak_milestone,
+                EventKind::TodoDone => &self.config.todo_done,
+                EventKind::DayCompleted => &self.config.day_completed,
+            };
+            let Some(command) = command.clone() else {
+                continue;
+            };
+            let event = event.clone();
+            let timeout = Duration::from_secs(self.config.timeout_secs);
+            self.running.push(thread::spawn(move || {
+                if let Err(failure) = run(&command, &event, timeout) {
+                    log(&format!(
+                        "{} hook `{}` failed: {}",
+                        event.name(),
+                        command,
+                        failure
+                    ));
+                }
+            }));
+        }
+    }
+
+    /// Queues the deliveries right away and sends them in the background.
+    fn send(&mut self, deliveries: Vec<Delivery>) {
+        let queue_file = Path::new(webhook::QUEUE_FILE);
+        match webhook::enqueue(deliveries, queue_file, &self.queue) {
+            Ok(true) => {
+                let queue = Arc::clone(&self.queue);
+                self.sender = Some(thread::spawn(move || {
+                    match webhook::send_queued(queue_file, &queue) {
+                        Ok(problems) => problems.iter().for_each(|problem| log(problem)),
+                        Err(err) => log(&format!("webhook queue: {}", err)),
+                    }
+                }));
+            }
+            Ok(false) => {}
+            Err(err) => log(&format!("webhook queue: {}", err)),
+        }
+    }
+}
+
+impl Drop for Runner {
+    /// Waits for hooks still running, which their timeout bounds. Webhooks
+    /// get a moment to finish; any left stay queued for the next session.
+    fn drop(&mut self) {
+        for handle in self.running.drain(..) {
+            let _ = handle.join();
+        }
+        let deadline = Instant::now() + WEBHOOK_GRACE;
+        while self.sender.as_ref().is_some_and(|s| !s.is_finished()) && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(50));
+        }
+    }
+}
+
+fn run(command: &str, event: &Event, timeout: Duration) -> Result<(), String> {
+    let value = serde_json::to_value(event).map_err(|e| e.to_string())?;
+    let json = value.to_string();
+    let mut process = Command::new("sh");
+    process
+        .arg("-c")
+        .arg(command)
+        .env("HABIT_TRACKER_EVENT_JSON", &json)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped());
+    if let Some(fields) = value.as_object() {
+        for (key, field) in fields {
+            let field = match field {
+                serde_json::Value::String(s) => s.clone(),
+                other => other.to_string(),
+            };
+            process.env(format!("HABIT_TRACKER_{}", key.to_uppercase()), field);
+        }
+    }
+    let mut child = process
+        .spawn()
+        .map_err(|e| format!("could not start: {}", e))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // A hook that ignores its input may exit before reading it.
+        let _ = stdin.write_all(json.as_bytes());
+    }
+    match wait(&mut child, timeout).map_err(|e| e.to_string())? {
+        Some(status) if status.success() => Ok(()),
+        Some(status) => {
+            let mut stderr = String::new();
+            if let Some(mut pipe) = child.stderr.take() {
+                let _ = pipe.read_to_string(&mut stderr);
+            }
+            Err(format!("{}: {}", status, stderr.trim()))
+        }
+        None => {
+            let _ = child.kill();
+            let _ = child.wait();
+            Err(format!("killed after {}s", timeout.as_secs()))
+        }
+    }
+}
+
+/// The exit status, or `None` if the child is still running at the timeout.
+fn wait(child: &mut Child, timeout: Duration) -> io::Result<Option<std::process::ExitStatus>> {
+    let start = Instant::now();
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(Some(status));
+        }
+        if start.elapsed() >= timeout {
+            return Ok(None);
+        }
+        thread::sleep(Duration::from_millis(20));
+    }
+}
+
+/// Appends a line to `LOG_FILE`; there is nowhere else to report failures
+/// while the UI owns the terminal.
+pub fn log(message: &str) {
+    let line = format!(
+        "{} {}\n",
+        chrono::Local::now().format("%Y-%m-%d %H:%M:%S"),
+        message
+    );
+    if let Ok(mut file) = OpenOptions::new().create(true).append(true).open(LOG_FILE) {
+        let _ = file.write_all(line.as_bytes());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn day(d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 5, d).unwrap()
+    }
+
+    fn habit(name: &str, frequency: Frequency, done: &[u32]) -> Habit {
+        let mut habit = Habit::new(name.to_string(), "Health".to_string(), frequency);
+        for d in done {
+            habit.mark_completed(day(*d));
+        }
+        habit
+    }
+
+    fn check(habits: &[Habit], name: &str, date: NaiveDate) -> Vec<Habit> {
+        let mut habits = habits.to_vec();
+        for habit in habits.iter_mut().filter(|h| h.name == name) {
+            habit.mark_completed(date);
+        }
+        habits
+    }
+
+    fn kinds(events: &[Event]) -> Vec<&'static str> {
+        events.iter().map(Event::name).collect()
+    }
+
+    fn events_between(old: &[Habit], new: &[Habit], date: NaiveDate) -> Vec<Event> {
+        events((old, &[]), (new, &[]), date, &[3])
+    }
+
+    #[test]
+    fn completing_a_habit_fires_once() {
+        let old = vec![
+            habit("Run", Frequency::Daily, &[6]),
+            habit("Read", Frequency::Daily, &[]),
+        ];
+        let new = check(&old, "Run", day(7));
+        assert_eq!(
+            events_between(&old, &new, day(7)),
+            vec![Event::HabitCompleted {
+                habit: "Run".to_string(),
+                category: "Health".to_string(),
+                date: day(7),
+                streak: 2,
+            }]
+        );
+        // Checking it again, or saving unchanged data, fires nothing more.
+        assert!(events_between(&new, &new, day(7)).is_empty());
+    }
+
+    #[test]
+    fn unchecking_and_checking_again_fires_again() {
+        let old = vec![
+            habit("Run", Frequency::Daily, &[7]),
+            habit("Read", Frequency::Daily, &[]),
+        ];
+        let mut unchecked = old.clone();
+        unchecked[0].unmark_completed(day(7));
+        assert!(events_between(&old, &unchecked, day(7)).is_empty());
+        assert_eq!(
+            kinds(&events_between(&unchecked, &old, day(7))),
+            ["habit_completed"]
+        );
+    }
+
+    #[test]
+    fn reaching_a_milestone_fires_it_too() {
+        let old = vec![
+            habit("Run", Frequency::Daily, &[5, 6]),
+            habit("Read", Frequency::Daily, &[]),
+        ];
+        let new = check(&old, "Run", day(7));
+        assert_eq!(
+            kinds(&events_between(&old, &new, day(7))),
+            ["habit_completed", "streak_milestone"]
+        );
+        let new = check(&old, "Read", day(7));
+        assert_eq!(
+            kinds(&events_between(&old, &new, day(7))),
+            ["habit_completed"]
+        );
+    }
+
+    #[test]
+    fn milestones_are_told_in_the_habit_s_periods() {
+        let old = vec![habit("Run", Frequency::Daily, &[5, 6])];
+        let new = check(&old, "Run", day(7));
+        assert_eq!(
+            events_between(&old, &new, day(7))[1].message(),
+            "Run reached a 3-day streak!"
+        );
+        // Mondays 13, 20 and 27 May, in three weeks in a row.
+        let old = vec![habit("Swim", Frequency::Weekly, &[13, 20])];
+        let new = check(&old, "Swim", day(27));
+        assert_eq!(
+            events_between(&old, &new, day(27))[1].message(),
+            "Swim reached a 3-week streak!"
+        );
+    }
+
+    #[test]
+    fn the_day_is_complete_once_every_active_habit_is_done_for_its_period() {
+        // 2024-05-08 is a Wednesday; weeks start on Monday by default.
+        let mut old = vec![
+            habit("Run", Frequency::Daily, &[]),
+            habit("Swim", Frequency::Weekly, &[6]),
+            habit("Read", Frequency::Daily, &[]),
+        ];
+        old[2].paused = true;
+        let new = check(&old, "Run", day(8));
+        assert_eq!(
+            events_between(&old, &new, day(8)).last(),
+            Some(&Event::DayCompleted {
+                date: day(8),
+                habits: 2,
+            })
+        );
+        // Already complete before, so checking the paused habit adds nothing.
+        let newer = check(&new, "Read", day(8));
+        assert_eq!(
+            kinds(&events_between(&new, &newer, day(8))),
+            ["habit_completed"]
+        );
+    }
+
+    #[test]
+    fn finishing_a_todo_fires_once() {
+        let todo = |completed| Todo {
+            description: "Pay rent".to_string(),
+            completed,
+            ..Todo::default()
+        };
+        let (old, new) = (vec![todo(false)], vec![todo(true)]);
+        let fired = events((&[], &old), (&[], &new), day(7), &[]);
+        assert_eq!(
+            fired,
+            vec![Event::TodoDone {
+                description: "Pay rent".to_string(),
+            }]
+        );
+        assert!(events((&[], &new), (&[], &new), day(7), &[]).is_empty());
+    }
+}
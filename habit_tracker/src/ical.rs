@@ -0,0 +1,320 @@
+use crate::habit::{self, Frequency, Habit};
+use crate::todo::Todo;
+use chrono::{DateTime, Datelike, NaiveDate, Utc};
+
+const PRODID: &str = "-//habit_tracker//EN";
+const UID_DOMAIN: &str = "habit_tracker";
+
+/// Renders habits and todos as an iCalendar document.
+///
+/// Each habit becomes a recurring VTODO whose RRULE follows its frequency,
+/// with one occurrence per day, week or month, and each period it was done
+/// in an override of that occurrence marked completed. Todos become plain
+/// VTODOs with their due date and status.
+pub fn export(habits: &[Habit], todos: &[Todo], today: NaiveDate) -> String {
+    export_at(habits, todos, today, Utc::now())
+}
+
+fn export_at(habits: &[Habit], todos: &[Todo], today: NaiveDate, now: DateTime<Utc>) -> String {
+    let stamp = utc_time(now);
+    let mut cal = Calendar::default();
+    cal.line("BEGIN:VCALENDAR");
+    cal.line("VERSION:2.0");
+    cal.line(&format!("PRODID:{}", PRODID));
+
+    for habit in habits {
+        let uid = format!("habit-{:016x}", hash(&[&habit.category, &habit.name]));
+        let first = habit.completed_dates().first().copied().unwrap_or(today);
+        let start = occurrence(habit.frequency, first);
+        let freq = match habit.frequency {
+            Frequency::Daily => "DAILY",
+            Frequency::Weekly => "WEEKLY",
+            Frequency::Monthly => "MONTHLY",
+        };
+
+        cal.line("BEGIN:VTODO");
+        cal.line(&format!("UID:{}@{}", uid, UID_DOMAIN));
+        cal.line(&format!("DTSTAMP:{}", stamp));
+        cal.line(&format!("SUMMARY:{}", escape(&habit.name)));
+        cal.line(&format!("CATEGORIES:{}", escape(&habit.category)));
+        cal.line(&format!("DTSTART;VALUE=DATE:{}", ical_date(start)));
+        cal.line(&format!("RRULE:FREQ={}", freq));
+        cal.line("STATUS:NEEDS-ACTION");
+        cal.line("END:VTODO");
+
+        let mut done_in = Vec::new();
+        for &date in habit.completed_dates() {
+            // The first check-in of a week or month stands for it.
+            let instance = occurrence(habit.frequency, date);
+            if done_in.contains(&instance) {
+                continue;
+            }
+            done_in.push(instance);
+            cal.line("BEGIN:VTODO");
+            cal.line(&format!("UID:{}@{}", uid, UID_DOMAIN));
+            cal.line(&format!("DTSTAMP:{}", stamp));
+            cal.line(&format!("RECURRENCE-ID;VALUE=DATE:{}", ical_date(instance)));
+            cal.line(&format!("SUMMARY:{}", escape(&habit.name)));
+            cal.line(&format!("CATEGORIES:{}", escape(&habit.category)));
+            cal.line(&format!("DTSTART;VALUE=DATE:{}", ical_date(date)));
+            // Checked live: the moment is known. Backfilled: only the day,
+            // as a floating time so it is that day wherever it is read.
+            match habit.check_in_on(date) {
+                Some(check_in) => cal.line(&format!("COMPLETED:{}", utc_time(check_in.at))),
+                None => cal.line(&format!("COMPLETED:{}", floating(date))),
+            }
+            cal.line("STATUS:COMPLETED");
+            cal.line("END:VTODO");
+        }
+    }
+
+    for todo in todos {
+        cal.line("BEGIN:VTODO");
+        cal.line(&format!(
+            "UID:todo-{:016x}@{}",
+            hash(&[&todo.description]),
+            UID_DOMAIN
+        ));
+        cal.line(&format!("DTSTAMP:{}", stamp));
+        cal.line(&format!("SUMMARY:{}", escape(&todo.description)));
+        // Projects, and contexts with their `@` to tell them apart.
+        let tags: Vec<String> = todo
+            .projects()
+            .into_iter()
+            .map(escape)
+            .chain(
+                todo.contexts()
+                    .into_iter()
+                    .map(|c| escape(&format!("@{}", c))),
+            )
+            .collect();
+        if !tags.is_empty() {
+            cal.line(&format!("CATEGORIES:{}", tags.join(",")));
+        }
+        if let Some(created) = todo.created {
+            cal.line(&format!("CREATED:{}", floating(created)));
+        }
+        if let Some(due) = todo.due_date() {
+            cal.line(&format!("DUE;VALUE=DATE:{}", ical_date(due)));
+        }
+        if let Some(priority) = todo.priority {
+            // (A) is the most important; iCalendar uses 1 (highest) to 9.
+            let level = (priority as u32).saturating_sub('A' as u32).min(8) + 1;
+            cal.line(&format!("PRIORITY:{}", level));
+        }
+        if todo.completed {
+            cal.line("STATUS:COMPLETED");
+            if let Some(done) = todo.completed_on {
+                cal.line(&format!("COMPLETED:{}", floating(done)));
+            }
+        } else {
+            cal.line("STATUS:NEEDS-ACTION");
+        }
+        cal.line("END:VTODO");
+    }
+
+    cal.line("END:VCALENDAR");
+    cal.0
+}
+
+#[derive(Default)]
+struct Calendar(String);
+
+impl Calendar {
+    /// Appends a content line, folded at 75 octets as RFC 5545 requires.
+    fn line(&mut self, line: &str) {
+        let mut width = 0;
+        for c in line.chars() {
+            if width + c.len_utf8() > 75 {
+                self.0.push_str("\r\n ");
+                width = 1;
+            }
+            self.0.push(c);
+            width += c.len_utf8();
+        }
+        self.0.push_str("\r\n");
+    }
+}
+
+/// The first day of the RRULE occurrence containing `date`: the day itself,
+/// the start of its week or the first of its month. Starting the rule on
+/// such a day makes every occurrence fall on one.
+fn occurrence(frequency: Frequency, date: NaiveDate) -> NaiveDate {
+    match frequency {
+        Frequency::Daily => date,
+        Frequency::Weekly => habit::week_bounds(date).0,
+        Frequency::Monthly => date.with_day(1).unwrap_or(date),
+    }
+}
+
+fn ical_date(date: NaiveDate) -> String {
+    date.format("%Y%m%d").to_string()
+}
+
+fn utc_time(time: DateTime<Utc>) -> String {
+    time.format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+/// The start of `date` in whatever zone the calendar is shown in.
+fn floating(date: NaiveDate) -> String {
+    format!("{}T000000", ical_date(date))
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+/// FNV-1a, so UIDs stay the same across runs and builds.
+fn hash(parts: &[&str]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for part in parts {
+        for byte in part.bytes().chain([0]) {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+    }
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::habit::CheckIn;
+    use chrono::TimeZone;
+
+    fn day(d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 5, d).unwrap()
+    }
+
+    fn export(habits: &[Habit], todos: &[Todo]) -> String {
+        let now = Utc.with_ymd_and_hms(2024, 5, 20, 8, 0, 0).unwrap();
+        export_at(habits, todos, day(20), now)
+    }
+
+    /// The unfolded content lines of each VTODO.
+    fn vtodos(calendar: &str) -> Vec<Vec<String>> {
+        let unfolded = calendar.replace("\r\n ", "");
+        let mut todos = Vec::new();
+        for line in unfolded.split("\r\n") {
+            match line {
+                "BEGIN:VTODO" => todos.push(Vec::new()),
+                "END:VTODO" => {}
+                _ => {
+                    if let Some(todo) = todos.last_mut() {
+                        todo.push(line.to_string());
+                    }
+                }
+            }
+        }
+        todos
+    }
+
+    fn has(todo: &[String], line: &str) -> bool {
+        todo.iter().any(|l| l == line)
+    }
+
+    #[test]
+    fn completions_override_occurrences_of_the_habit() {
+        let mut habit = Habit::new("Run".into(), "Health".into(), Frequency::Daily);
+        habit.mark_completed(day(1));
+        let at = Utc.with_ymd_and_hms(2024, 5, 2, 21, 30, 0).unwrap();
+        habit.check_in(
+            day(2),
+            CheckIn {
+                at,
+                zone: "America/New_York".into(),
+            },
+        );
+        let calendar = export(&[habit], &[]);
+        assert!(calendar.starts_with("BEGIN:VCALENDAR\r\nVERSION:2.0\r\n"));
+        assert!(calendar.ends_with("END:VCALENDAR\r\n"));
+        assert!(!calendar.contains("RELATED-TO"));
+
+        let todos = vtodos(&calendar);
+        assert_eq!(todos.len(), 3);
+        let uid = todos[0].iter().find(|l| l.starts_with("UID:")).unwrap();
+        assert!(todos.iter().all(|todo| todo.contains(uid)));
+        assert!(has(&todos[0], "DTSTART;VALUE=DATE:20240501"));
+        assert!(has(&todos[0], "RRULE:FREQ=DAILY"));
+        assert!(has(&todos[0], "DTSTAMP:20240520T080000Z"));
+
+        assert!(has(&todos[1], "RECURRENCE-ID;VALUE=DATE:20240501"));
+        // Backfilled: a floating time, not midnight UTC.
+        assert!(has(&todos[1], "COMPLETED:20240501T000000"));
+        assert!(has(&todos[1], "STATUS:COMPLETED"));
+        assert!(has(&todos[2], "RECURRENCE-ID;VALUE=DATE:20240502"));
+        // Checked live: the actual moment.
+        assert!(has(&todos[2], "COMPLETED:20240502T213000Z"));
+    }
+
+    #[test]
+    fn weekly_and_monthly_habits_recur_once_per_period() {
+        // 2024-05-08 is a Wednesday; weeks start on Monday by default.
+        let mut weekly = Habit::new("Swim".into(), "Health".into(), Frequency::Weekly);
+        for d in [8, 10, 15] {
+            weekly.mark_completed(day(d));
+        }
+        let mut monthly = Habit::new("Budget".into(), "Money".into(), Frequency::Monthly);
+        monthly.mark_completed(day(3));
+        let todos = vtodos(&export(&[weekly, monthly], &[]));
+        assert_eq!(todos.len(), 5);
+        assert!(has(&todos[0], "DTSTART;VALUE=DATE:20240506"));
+        assert!(has(&todos[0], "RRULE:FREQ=WEEKLY"));
+        assert!(has(&todos[1], "RECURRENCE-ID;VALUE=DATE:20240506"));
+        assert!(has(&todos[1], "DTSTART;VALUE=DATE:20240508"));
+        assert!(has(&todos[2], "RECURRENCE-ID;VALUE=DATE:20240513"));
+        assert!(has(&todos[3], "DTSTART;VALUE=DATE:20240501"));
+        assert!(has(&todos[4], "RECURRENCE-ID;VALUE=DATE:20240501"));
+    }
+
+    #[test]
+    fn habits_without_completions_start_today() {
+        let habit = Habit::new("Read".into(), "Mind".into(), Frequency::Daily);
+        let todos = vtodos(&export(&[habit], &[]));
+        assert_eq!(todos.len(), 1);
+        assert!(has(&todos[0], "DTSTART;VALUE=DATE:20240520"));
+        assert!(has(&todos[0], "STATUS:NEEDS-ACTION"));
+    }
+
+    #[test]
+    fn todos_carry_due_dates_priority_and_status() {
+        let open = Todo {
+            description: "Pay rent, on time +home @bank due:2024-05-31".into(),
+            priority: Some('B'),
+            created: Some(day(1)),
+            ..Todo::default()
+        };
+        let done = Todo {
+            description: "Call mum".into(),
+            completed: true,
+            completed_on: Some(day(4)),
+            ..Todo::default()
+        };
+        let todos = vtodos(&export(&[], &[open, done]));
+        assert!(has(
+            &todos[0],
+            "SUMMARY:Pay rent\\, on time +home @bank due:2024-05-31"
+        ));
+        assert!(has(&todos[0], "CATEGORIES:home,@bank"));
+        assert!(has(&todos[0], "DUE;VALUE=DATE:20240531"));
+        assert!(has(&todos[0], "PRIORITY:2"));
+        assert!(has(&todos[0], "CREATED:20240501T000000"));
+        assert!(has(&todos[0], "STATUS:NEEDS-ACTION"));
+        assert!(has(&todos[1], "STATUS:COMPLETED"));
+        assert!(has(&todos[1], "COMPLETED:20240504T000000"));
+    }
+
+    #[test]
+    fn long_lines_are_folded() {
+        let name = "a very long habit name that goes on and on, well past the line limit";
+        let habit = Habit::new(name.into(), "Health".into(), Frequency::Daily);
+        let calendar = export(&[habit], &[]);
+        assert!(calendar.split("\r\n").all(|line| line.len() <= 75));
+        assert!(calendar.contains("\r\n "));
+        let todos = vtodos(&calendar);
+        assert!(has(&todos[0], &format!("SUMMARY:{}", escape(name))));
+    }
+}
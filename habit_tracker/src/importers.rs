@@ -0,0 +1,631 @@
+use crate::habit::{Frequency, Habit};
+use crate::todo::Todo;
+use chrono::{DateTime, Local, NaiveDate};
+use rusqlite::{Connection, OpenFlags};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+const LOOP_CATEGORY: &str = "Loop";
+const HABITICA_CATEGORY: &str = "Habitica";
+
+/// Habits and todos read from another tracker, plus a note for everything
+/// that could not be carried over exactly.
+#[derive(Debug, Default)]
+pub struct Imported {
+    pub habits: Vec<Habit>,
+    pub todos: Vec<Todo>,
+    pub unmapped: Vec<String>,
+}
+
+impl Imported {
+    /// Adds the imported data to `habits` and `todos`. Habits that already
+    /// exist (same name and category) get the imported check-ins added; todos
+    /// already present (same description) are left alone. Returns how many
+    /// habits and todos were new.
+    pub fn merge_into(self, habits: &mut Vec<Habit>, todos: &mut Vec<Todo>) -> (usize, usize) {
+        let mut new_habits = 0;
+        for imported in self.habits {
+            match habits
+                .iter_mut()
+                .find(|h| h.name == imported.name && h.category == imported.category)
+            {
+                Some(habit) => {
+                    for &date in imported.completed_dates() {
+                        habit.mark_completed(date);
+                    }
+                }
+                None => {
+                    habits.push(imported);
+                    new_habits += 1;
+                }
+            }
+        }
+        let mut new_todos = 0;
+        for imported in self.todos {
+            if !todos.iter().any(|t| t.description == imported.description) {
+                todos.push(imported);
+                new_todos += 1;
+            }
+        }
+        (new_habits, new_todos)
+    }
+}
+
+/// Picks the Loop importer from the path: a directory is an unzipped CSV
+/// export, anything else a database backup.
+pub fn loop_backup(path: &Path) -> io::Result<Imported> {
+    if fs::metadata(path)?.is_dir() {
+        loop_csv(path)
+    } else {
+        loop_sqlite(path)
+    }
+}
+
+/// Maps a Loop "n times every d days" frequency onto ours.
+fn loop_frequency(
+    name: &str,
+    numerator: i64,
+    denominator: i64,
+    unmapped: &mut Vec<String>,
+) -> Frequency {
+    match (numerator, denominator) {
+        (1, 1) => Frequency::Daily,
+        (1, 7) => Frequency::Weekly,
+        (1, 30) | (1, 31) => Frequency::Monthly,
+        _ => {
+            let frequency = if denominator >= 28 {
+                Frequency::Monthly
+            } else if denominator >= 7 {
+                Frequency::Weekly
+            } else {
+                Frequency::Daily
+            };
+            unmapped.push(format!(
+                "{}: {} times every {} days imported as {}",
+                name, numerator, denominator, frequency
+            ));
+            frequency
+        }
+    }
+}
+
+/// Whether a Loop check mark value counts as done: for yes/no habits only a
+/// manual check mark (2), not one implied by the frequency (1) or a skip
+/// (3); for numeric habits any amount above zero.
+fn loop_checked(value: f64, numeric: bool) -> bool {
+    if numeric {
+        value > 0.0
+    } else {
+        value == 2.0
+    }
+}
+
+/// Reads an unzipped Loop Habit Tracker CSV export: `Habits.csv` for the
+/// habit list and `Checkmarks.csv` for one column of check marks per habit.
+fn loop_csv(dir: &Path) -> io::Result<Imported> {
+    let mut imported = Imported::default();
+
+    let mut reader = csv::Reader::from_path(dir.join("Habits.csv"))?;
+    let header = reader.headers()?.clone();
+    let column = |names: &[&str]| header.iter().position(|h| names.contains(&h.trim()));
+    let name_col = column(&["Name"]).ok_or_else(|| invalid("Habits.csv has no Name column"))?;
+    let num_col = column(&["FrequencyNumerator", "NumRepetitions"]);
+    let den_col = column(&["FrequencyDenominator", "Interval"]);
+    let type_col = column(&["Type"]);
+    let mut numeric = Vec::new();
+
+    for record in reader.records() {
+        let record = record?;
+        let name = record.get(name_col).unwrap_or_default().to_string();
+        let field = |col: Option<usize>| {
+            col.and_then(|c| record.get(c))
+                .and_then(|v| v.trim().parse::<i64>().ok())
+        };
+        let frequency = loop_frequency(
+            &name,
+            field(num_col).unwrap_or(1),
+            field(den_col).unwrap_or(1),
+            &mut imported.unmapped,
+        );
+        numeric.push(field(type_col) == Some(1));
+        if field(type_col) == Some(1) {
+            imported.unmapped.push(format!(
+                "{}: numeric values imported as plain check-ins",
+                name
+            ));
+        }
+        imported
+            .habits
+            .push(Habit::new(name, LOOP_CATEGORY.to_string(), frequency));
+    }
+
+    let checkmarks = dir.join("Checkmarks.csv");
+    if checkmarks.exists() {
+        let mut reader = csv::Reader::from_path(checkmarks)?;
+        let header = reader.headers()?.clone();
+        for record in reader.records() {
+            let record = record?;
+            let Some(date) = record
+                .get(0)
+                .and_then(|d| NaiveDate::parse_from_str(d.trim(), "%Y-%m-%d").ok())
+            else {
+                continue;
+            };
+            for (name, value) in header.iter().zip(record.iter()).skip(1) {
+                let Some(index) = imported.habits.iter().position(|h| h.name == name.trim()) else {
+                    continue;
+                };
+                let value = value.trim().parse::<f64>();
+                if value.is_ok_and(|v| loop_checked(v, numeric[index])) {
+                    imported.habits[index].mark_completed(date);
+                }
+            }
+        }
+    } else {
+        imported
+            .unmapped
+            .push("Checkmarks.csv not found; no check-ins imported".to_string());
+    }
+    Ok(imported)
+}
+
+/// Reads a Loop Habit Tracker database backup (`.db`).
+fn loop_sqlite(path: &Path) -> io::Result<Imported> {
+    let sql_err = |e: rusqlite::Error| io::Error::other(e);
+    let conn =
+        Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY).map_err(sql_err)?;
+    let mut imported = Imported::default();
+    let mut ids = HashMap::new();
+
+    let mut stmt = conn
+        .prepare(
+            "SELECT id, name, freq_num, freq_den, archived, type FROM Habits ORDER BY position",
+        )
+        .map_err(sql_err)?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, i64>(2)?,
+                row.get::<_, i64>(3)?,
+                row.get::<_, Option<i64>>(4)?.unwrap_or(0),
+                row.get::<_, Option<i64>>(5)?.unwrap_or(0),
+            ))
+        })
+        .map_err(sql_err)?;
+    for row in rows {
+        let (id, name, numerator, denominator, archived, kind) = row.map_err(sql_err)?;
+        let frequency = loop_frequency(&name, numerator, denominator, &mut imported.unmapped);
+        if archived != 0 {
+            imported
+                .unmapped
+                .push(format!("{}: archived in Loop, imported as active", name));
+        }
+        if kind == 1 {
+            imported.unmapped.push(format!(
+                "{}: numeric values imported as plain check-ins",
+                name
+            ));
+        }
+        ids.insert(id, (imported.habits.len(), kind == 1));
+        imported
+            .habits
+            .push(Habit::new(name, LOOP_CATEGORY.to_string(), frequency));
+    }
+
+    let mut stmt = conn
+        .prepare("SELECT habit, timestamp, value FROM Repetitions")
+        .map_err(sql_err)?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, i64>(1)?,
+                row.get::<_, i64>(2)?,
+            ))
+        })
+        .map_err(sql_err)?;
+    for row in rows {
+        let (habit, timestamp, value) = row.map_err(sql_err)?;
+        // Timestamps are midnight UTC of the day, in milliseconds.
+        let date = DateTime::from_timestamp_millis(timestamp).map(|t| t.date_naive());
+        if let (Some(&(index, numeric)), Some(date)) = (ids.get(&habit), date) {
+            if loop_checked(value as f64, numeric) {
+                imported.habits[index].mark_completed(date);
+            }
+        }
+    }
+    Ok(imported)
+}
+
+/// Reads a Habitica JSON data export. Dailies become habits, positive
+/// habits become daily habits checked on the days they were scored up, and
+/// to-dos become todos. Rewards have no equivalent.
+pub fn habitica(contents: &str) -> io::Result<Imported> {
+    let root: Value = serde_json::from_str(contents)?;
+    let tasks = root.get("tasks").unwrap_or(&root);
+    let tag_names: HashMap<&str, &str> = root
+        .get("tags")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(|tag| Some((tag.get("id")?.as_str()?, tag.get("name")?.as_str()?)))
+        .collect();
+    let list = |key: &str| {
+        tasks
+            .get(key)
+            .and_then(Value::as_array)
+            .cloned()
+            .unwrap_or_default()
+    };
+    let text = |task: &Value| {
+        task.get("text")
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .to_string()
+    };
+    let category = |task: &Value| {
+        task.get("tags")
+            .and_then(Value::as_array)
+            .and_then(|tags| tags.first())
+            .and_then(Value::as_str)
+            .and_then(|id| tag_names.get(id))
+            .unwrap_or(&HABITICA_CATEGORY)
+            .to_string()
+    };
+
+    let mut imported = Imported::default();
+
+    for daily in list("dailys") {
+        let name = text(&daily);
+        let every = daily.get("everyX").and_then(Value::as_i64).unwrap_or(1);
+        let weekdays = daily
+            .get("repeat")
+            .and_then(Value::as_object)
+            .map(|days| days.values().filter(|v| v.as_bool() == Some(true)).count())
+            .unwrap_or(7);
+        let frequency = match daily.get("frequency").and_then(Value::as_str) {
+            Some("weekly") if weekdays == 7 && every == 1 => Frequency::Daily,
+            Some("weekly") => {
+                if weekdays != 1 || every != 1 {
+                    imported.unmapped.push(format!(
+                        "{}: {} days every {} weeks imported as Weekly",
+                        name, weekdays, every
+                    ));
+                }
+                Frequency::Weekly
+            }
+            Some("monthly") => Frequency::Monthly,
+            Some("yearly") => {
+                imported
+                    .unmapped
+                    .push(format!("{}: yearly repeat imported as Monthly", name));
+                Frequency::Monthly
+            }
+            _ => {
+                if every != 1 {
+                    imported
+                        .unmapped
+                        .push(format!("{}: every {} days imported as Daily", name, every));
+                }
+                Frequency::Daily
+            }
+        };
+        let mut habit = Habit::new(name, category(&daily), frequency);
+        let mut previous_value = None;
+        for entry in daily
+            .get("history")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+        {
+            let value = entry.get("value").and_then(Value::as_f64);
+            // Newer exports say so directly; older ones only show the task
+            // value going up on days it was completed.
+            let completed = match entry.get("completed").and_then(Value::as_bool) {
+                Some(completed) => completed,
+                None => matches!((previous_value, value), (Some(p), Some(v)) if v > p),
+            };
+            previous_value = value;
+            if let (true, Some(date)) = (completed, history_date(entry)) {
+                habit.mark_completed(date);
+            }
+        }
+        imported.habits.push(habit);
+    }
+
+    for task in list("habits") {
+        let name = text(&task);
+        if task.get("up").and_then(Value::as_bool) == Some(false) {
+            imported
+                .unmapped
+                .push(format!("{}: negative-only habit not imported", name));
+            continue;
+        }
+        let mut habit = Habit::new(name, category(&task), Frequency::Daily);
+        for entry in task
+            .get("history")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+        {
+            let scored_up = entry.get("scoredUp").and_then(Value::as_i64).unwrap_or(0);
+            if let (true, Some(date)) = (scored_up > 0, history_date(entry)) {
+                habit.mark_completed(date);
+            }
+        }
+        imported.habits.push(habit);
+    }
+
+    for task in list("todos") {
+        let mut description = text(&task);
+        if let Some(due) = iso_date(task.get("date")) {
+            description.push_str(&format!(" due:{}", due.format("%Y-%m-%d")));
+        }
+        let priority = task.get("priority").and_then(Value::as_f64).and_then(|p| {
+            if p >= 2.0 {
+                Some('A')
+            } else if p >= 1.5 {
+                Some('B')
+            } else if p >= 1.0 {
+                Some('C')
+            } else {
+                None
+            }
+        });
+        let completed = task.get("completed").and_then(Value::as_bool) == Some(true);
+        if task
+            .get("checklist")
+            .and_then(Value::as_array)
+            .is_some_and(|c| !c.is_empty())
+        {
+            imported
+                .unmapped
+                .push(format!("{}: checklist items not imported", text(&task)));
+        }
+        imported.todos.push(Todo {
+            description,
+            completed,
+            priority,
+            created: iso_date(task.get("createdAt")),
+            completed_on: iso_date(task.get("dateCompleted")).filter(|_| completed),
+        });
+    }
+
+    for reward in list("rewards") {
+        imported
+            .unmapped
+            .push(format!("{}: rewards are not supported", text(&reward)));
+    }
+    Ok(imported)
+}
+
+/// Habitica history entries carry a millisecond timestamp.
+fn history_date(entry: &Value) -> Option<NaiveDate> {
+    let millis = entry.get("date").and_then(Value::as_i64)?;
+    DateTime::from_timestamp_millis(millis).map(|t| t.with_timezone(&Local).date_naive())
+}
+
+fn iso_date(value: Option<&Value>) -> Option<NaiveDate> {
+    let text = value?.as_str()?;
+    DateTime::parse_from_rfc3339(text)
+        .ok()
+        .map(|t| t.with_timezone(&Local).date_naive())
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "habit_tracker-importers-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn day(d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 5, d).unwrap()
+    }
+
+    fn dates(imported: &Imported, name: &str) -> Vec<NaiveDate> {
+        let habit = imported.habits.iter().find(|h| h.name == name).unwrap();
+        habit.completed_dates().to_vec()
+    }
+
+    #[test]
+    fn loop_csv_counts_manual_check_marks_and_numeric_amounts() {
+        let dir = scratch_dir("loop-csv");
+        fs::write(
+            dir.join("Habits.csv"),
+            "Position,Name,Type,Question,Description,NumRepetitions,Interval,Color\n\
+             001,Run,0,,,1,1,#FF8F00\n\
+             002,Swim,0,,,1,7,#00897B\n\
+             003,Water,1,,,1,1,#039BE5\n\
+             004,Stretch,0,,,3,7,#5E35B1\n",
+        )
+        .unwrap();
+        // 2 is a manual check, 1 implied by the frequency, 3 a skip.
+        fs::write(
+            dir.join("Checkmarks.csv"),
+            "Date,Run,Swim,Water,Stretch\n\
+             2024-05-03,2,1,0,3\n\
+             2024-05-02,1,2,2.5,-1\n\
+             2024-05-01,2,0,0,2\n",
+        )
+        .unwrap();
+        let imported = loop_backup(&dir).unwrap();
+        assert_eq!(dates(&imported, "Run"), [day(1), day(3)]);
+        assert_eq!(dates(&imported, "Swim"), [day(2)]);
+        assert_eq!(dates(&imported, "Water"), [day(2)]);
+        assert_eq!(dates(&imported, "Stretch"), [day(1)]);
+        let frequencies: Vec<Frequency> = imported.habits.iter().map(|h| h.frequency).collect();
+        assert_eq!(
+            frequencies,
+            [
+                Frequency::Daily,
+                Frequency::Weekly,
+                Frequency::Daily,
+                Frequency::Weekly
+            ]
+        );
+        assert!(imported.habits.iter().all(|h| h.category == LOOP_CATEGORY));
+        assert_eq!(imported.unmapped.len(), 2, "{:?}", imported.unmapped);
+    }
+
+    #[test]
+    fn loop_database_follows_the_same_rule_as_the_csv_export() {
+        let dir = scratch_dir("loop-db");
+        let path = dir.join("Loop Habits Backup.db");
+        let conn = Connection::open(&path).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE Habits (id INTEGER PRIMARY KEY, name TEXT, freq_num INTEGER,
+                 freq_den INTEGER, archived INTEGER, type INTEGER, position INTEGER);
+             CREATE TABLE Repetitions (id INTEGER PRIMARY KEY, habit INTEGER,
+                 timestamp INTEGER, value INTEGER);
+             INSERT INTO Habits VALUES (1, 'Run', 1, 1, 0, 0, 0);
+             INSERT INTO Habits VALUES (2, 'Water', 1, 1, 1, 1, 1);",
+        )
+        .unwrap();
+        let millis = |d: u32| {
+            day(d)
+                .and_hms_opt(0, 0, 0)
+                .unwrap()
+                .and_utc()
+                .timestamp_millis()
+        };
+        for (habit, d, value) in [(1, 1, 2), (1, 2, 1), (1, 3, 3), (2, 1, 0), (2, 2, 2500)] {
+            conn.execute(
+                "INSERT INTO Repetitions (habit, timestamp, value) VALUES (?1, ?2, ?3)",
+                rusqlite::params![habit, millis(d), value],
+            )
+            .unwrap();
+        }
+        drop(conn);
+        let imported = loop_backup(&path).unwrap();
+        assert_eq!(dates(&imported, "Run"), [day(1)]);
+        assert_eq!(dates(&imported, "Water"), [day(2)]);
+        // Archived, and numeric.
+        assert_eq!(imported.unmapped.len(), 2, "{:?}", imported.unmapped);
+    }
+
+    #[test]
+    fn habitica_dailies_habits_and_todos() {
+        // Noon UTC, so the dates are the same in any zone within 12 hours.
+        let noon = |d: u32| day(d).and_hms_opt(12, 0, 0).unwrap().and_utc();
+        let export = serde_json::json!({
+            "tags": [{"id": "t1", "name": "Health"}],
+            "tasks": {
+                "dailys": [
+                    {
+                        "text": "Floss",
+                        "tags": ["t1"],
+                        "frequency": "weekly",
+                        "everyX": 1,
+                        "repeat": {"m": true, "t": true, "w": true, "th": true,
+                                   "f": true, "s": true, "su": true},
+                        "history": [
+                            {"date": noon(1).timestamp_millis(), "value": 1.0, "completed": true},
+                            {"date": noon(2).timestamp_millis(), "value": 0.5, "completed": false},
+                        ],
+                    },
+                    {
+                        "text": "Review",
+                        "frequency": "weekly",
+                        "everyX": 1,
+                        "repeat": {"m": true},
+                        "history": [
+                            {"date": noon(1).timestamp_millis(), "value": 1.0},
+                            {"date": noon(6).timestamp_millis(), "value": 2.0},
+                        ],
+                    },
+                ],
+                "habits": [
+                    {"text": "Walk", "up": true,
+                     "history": [{"date": noon(3).timestamp_millis(), "scoredUp": 1}]},
+                    {"text": "Snack", "up": false, "history": []},
+                ],
+                "todos": [
+                    {"text": "Pay rent", "priority": 2, "completed": true,
+                     "date": noon(10).to_rfc3339(), "createdAt": noon(1).to_rfc3339(),
+                     "dateCompleted": noon(4).to_rfc3339()},
+                ],
+                "rewards": [{"text": "Cake"}],
+            },
+        });
+        let imported = habitica(&export.to_string()).unwrap();
+
+        let floss = &imported.habits[0];
+        assert_eq!(
+            (floss.category.as_str(), floss.frequency),
+            ("Health", Frequency::Daily)
+        );
+        assert_eq!(floss.completed_dates(), [day(1)]);
+        let review = &imported.habits[1];
+        assert_eq!(
+            (review.category.as_str(), review.frequency),
+            (HABITICA_CATEGORY, Frequency::Weekly)
+        );
+        assert_eq!(review.completed_dates(), [day(6)]);
+        assert_eq!(dates(&imported, "Walk"), [day(3)]);
+        assert_eq!(imported.habits.len(), 3);
+
+        let todo = &imported.todos[0];
+        assert_eq!(todo.description, "Pay rent due:2024-05-10");
+        assert_eq!(todo.priority, Some('A'));
+        assert_eq!(
+            (todo.created, todo.completed_on),
+            (Some(day(1)), Some(day(4)))
+        );
+        // The negative-only habit and the reward.
+        assert_eq!(imported.unmapped.len(), 2, "{:?}", imported.unmapped);
+    }
+
+    #[test]
+    fn merging_adds_check_ins_to_existing_habits() {
+        let mut existing = Habit::new("Run".into(), LOOP_CATEGORY.into(), Frequency::Daily);
+        existing.mark_completed(day(1));
+        let mut habits = vec![existing];
+        let mut todos = vec![Todo {
+            description: "Pay rent".into(),
+            ..Todo::default()
+        }];
+        let mut run = Habit::new("Run".into(), LOOP_CATEGORY.into(), Frequency::Daily);
+        run.mark_completed(day(2));
+        let imported = Imported {
+            habits: vec![
+                run,
+                Habit::new("Swim".into(), LOOP_CATEGORY.into(), Frequency::Weekly),
+            ],
+            todos: vec![
+                Todo {
+                    description: "Pay rent".into(),
+                    completed: true,
+                    ..Todo::default()
+                },
+                Todo {
+                    description: "Call mum".into(),
+                    ..Todo::default()
+                },
+            ],
+            unmapped: Vec::new(),
+        };
+        assert_eq!(imported.merge_into(&mut habits, &mut todos), (1, 1));
+        assert_eq!(habits[0].completed_dates(), [day(1), day(2)]);
+        assert!(!todos[0].completed);
+        assert_eq!(todos.len(), 2);
+    }
+}
@@ -0,0 +1,534 @@
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use serde::de::{self, Deserializer, SeqAccess, Visitor};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+/// Something that can be done from the lists, whatever key it is bound to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    Quit,
+    Add,
+    Edit,
+    Delete,
+    ToggleDone,
+    PreviousDay,
+    NextDay,
+    Up,
+    Down,
+    NextTab,
+    PreviousWeek,
+    NextWeek,
+    ImportExport,
+    /// Select the first entry.
+    First,
+    /// Select the last entry.
+    Last,
+    /// Type a text to select the next entry containing it.
+    Search,
+    SearchNext,
+    SearchPrevious,
+    /// Set the color of the selected category.
+    Color,
+}
+
+impl Action {
+    pub const ALL: [Action; 19] = [
+        Action::Quit,
+        Action::Add,
+        Action::Edit,
+        Action::Delete,
+        Action::ToggleDone,
+        Action::PreviousDay,
+        Action::NextDay,
+        Action::Up,
+        Action::Down,
+        Action::NextTab,
+        Action::PreviousWeek,
+        Action::NextWeek,
+        Action::ImportExport,
+        Action::First,
+        Action::Last,
+        Action::Search,
+        Action::SearchNext,
+        Action::SearchPrevious,
+        Action::Color,
+    ];
+
+    /// The name used for it in `[keys]`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::Quit => "quit",
+            Action::Add => "add",
+            Action::Edit => "edit",
+            Action::Delete => "delete",
+            Action::ToggleDone => "toggle_done",
+            Action::PreviousDay => "previous_day",
+            Action::NextDay => "next_day",
+            Action::Up => "up",
+            Action::Down => "down",
+            Action::NextTab => "next_tab",
+            Action::PreviousWeek => "previous_week",
+            Action::NextWeek => "next_week",
+            Action::ImportExport => "import_export",
+            Action::First => "first",
+            Action::Last => "last",
+            Action::Search => "search",
+            Action::SearchNext => "search_next",
+            Action::SearchPrevious => "search_previous",
+            Action::Color => "color",
+        }
+    }
+
+    /// Whether the action changes data, which a read-only instance must not.
+    pub fn edits(self) -> bool {
+        matches!(
+            self,
+            Action::Add | Action::Edit | Action::Delete | Action::ToggleDone | Action::Color
+        )
+    }
+
+    fn default_keys(self, preset: Preset) -> &'static [&'static str] {
+        match (preset, self) {
+            (_, Action::Quit) => &["q"],
+            (_, Action::Add) => &["a"],
+            (_, Action::Edit) => &["e"],
+            (Preset::Default, Action::Delete) => &["d"],
+            (Preset::Vim, Action::Delete) => &["d d"],
+            (Preset::Default, Action::ToggleDone) => &["enter"],
+            (Preset::Vim, Action::ToggleDone) => &["enter", "space"],
+            (Preset::Default, Action::PreviousDay) => &["left"],
+            (Preset::Vim, Action::PreviousDay) => &["h", "left"],
+            (Preset::Default, Action::NextDay) => &["right"],
+            (Preset::Vim, Action::NextDay) => &["l", "right"],
+            (Preset::Default, Action::Up) => &["up"],
+            (Preset::Vim, Action::Up) => &["k", "up"],
+            (Preset::Default, Action::Down) => &["down"],
+            (Preset::Vim, Action::Down) => &["j", "down"],
+            (Preset::Default, Action::NextTab) => &["tab"],
+            (Preset::Vim, Action::NextTab) => &["tab", "g t"],
+            // In Vim n and N repeat a search, so weeks move to [ and ].
+            (Preset::Default, Action::PreviousWeek) => &["p"],
+            (Preset::Vim, Action::PreviousWeek) => &["["],
+            (Preset::Default, Action::NextWeek) => &["n"],
+            (Preset::Vim, Action::NextWeek) => &["]"],
+            (_, Action::ImportExport) => &["x"],
+            (Preset::Default, Action::First) => &["home"],
+            (Preset::Vim, Action::First) => &["g g", "home"],
+            (Preset::Default, Action::Last) => &["end"],
+            (Preset::Vim, Action::Last) => &["G", "end"],
+            (_, Action::Search) => &["/"],
+            (Preset::Default, Action::SearchNext | Action::SearchPrevious) => &[],
+            (Preset::Vim, Action::SearchNext) => &["n"],
+            (Preset::Vim, Action::SearchPrevious) => &["N"],
+            (_, Action::Color) => &["c"],
+        }
+    }
+}
+
+/// The built-in set of bindings that `[keys]` adjusts.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Preset {
+    #[default]
+    Default,
+    /// hjkl to move, `g g` and `G` for the first and last entry, `d d` to
+    /// delete and `/`, `n` and `N` to search.
+    Vim,
+}
+
+/// A key and the modifiers held with it, written like `q`, `enter`,
+/// `ctrl-d` or `f5`. Shift is part of the character (`G`, not `shift-g`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Key {
+    code: KeyCode,
+    ctrl: bool,
+    alt: bool,
+}
+
+const NAMED_KEYS: [(&str, KeyCode); 16] = [
+    ("enter", KeyCode::Enter),
+    ("tab", KeyCode::Tab),
+    ("backtab", KeyCode::BackTab),
+    ("esc", KeyCode::Esc),
+    ("space", KeyCode::Char(' ')),
+    ("backspace", KeyCode::Backspace),
+    ("delete", KeyCode::Delete),
+    ("insert", KeyCode::Insert),
+    ("home", KeyCode::Home),
+    ("end", KeyCode::End),
+    ("pageup", KeyCode::PageUp),
+    ("pagedown", KeyCode::PageDown),
+    ("up", KeyCode::Up),
+    ("down", KeyCode::Down),
+    ("left", KeyCode::Left),
+    ("right", KeyCode::Right),
+];
+
+impl Key {
+    pub fn matches(&self, event: &KeyEvent) -> bool {
+        self.code == event.code
+            && self.ctrl == event.modifiers.contains(KeyModifiers::CONTROL)
+            && self.alt == event.modifiers.contains(KeyModifiers::ALT)
+    }
+}
+
+impl FromStr for Key {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut key = Key {
+            code: KeyCode::Null,
+            ctrl: false,
+            alt: false,
+        };
+        let mut rest = s;
+        loop {
+            let lower = rest.to_ascii_lowercase();
+            if lower.len() > 5 && lower.starts_with("ctrl-") {
+                key.ctrl = true;
+                rest = &rest[5..];
+            } else if lower.len() > 4 && lower.starts_with("alt-") {
+                key.alt = true;
+                rest = &rest[4..];
+            } else {
+                break;
+            }
+        }
+        let mut chars = rest.chars();
+        key.code = match (chars.next(), chars.next()) {
+            (Some(c), None) => KeyCode::Char(c),
+            _ => {
+                let lower = rest.to_ascii_lowercase();
+                let function = lower
+                    .strip_prefix('f')
+                    .and_then(|n| n.parse::<u8>().ok())
+                    .filter(|n| (1..=12).contains(n));
+                match NAMED_KEYS.iter().find(|(name, _)| *name == lower) {
+                    Some((_, code)) => *code,
+                    None => match function {
+                        Some(n) => KeyCode::F(n),
+                        None => return Err(format!("unknown key `{}`", s)),
+                    },
+                }
+            }
+        };
+        Ok(key)
+    }
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.ctrl {
+            write!(f, "Ctrl-")?;
+        }
+        if self.alt {
+            write!(f, "Alt-")?;
+        }
+        match self.code {
+            KeyCode::Char(' ') => write!(f, "Space"),
+            KeyCode::Char(c) => write!(f, "{}", c),
+            KeyCode::F(n) => write!(f, "F{}", n),
+            KeyCode::Left => write!(f, "←"),
+            KeyCode::Right => write!(f, "→"),
+            KeyCode::Up => write!(f, "↑"),
+            KeyCode::Down => write!(f, "↓"),
+            code => {
+                let name = NAMED_KEYS
+                    .iter()
+                    .find(|(_, c)| *c == code)
+                    .map_or("?", |(name, _)| name);
+                let mut chars = name.chars();
+                let first = chars.next().map(|c| c.to_ascii_uppercase());
+                write!(f, "{}{}", first.unwrap_or('?'), chars.as_str())
+            }
+        }
+    }
+}
+
+/// Keys pressed one after the other, written separated by spaces, like
+/// `g g` or `ctrl-w j`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Binding(Vec<Key>);
+
+impl FromStr for Binding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let keys = match s {
+            // A lone space is the key itself.
+            " " => vec![Key::from_str(" ")?],
+            _ => s
+                .split_whitespace()
+                .map(Key::from_str)
+                .collect::<Result<_, _>>()?,
+        };
+        match keys.is_empty() {
+            true => Err("empty key binding".to_string()),
+            false => Ok(Binding(keys)),
+        }
+    }
+}
+
+impl fmt::Display for Binding {
+    /// Runs of plain keys are shown as typed, like `gg`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let plain = self
+            .0
+            .iter()
+            .all(|key| matches!(key.code, KeyCode::Char(c) if c != ' ') && !key.ctrl && !key.alt);
+        let keys: Vec<String> = self.0.iter().map(Key::to_string).collect();
+        write!(f, "{}", keys.join(if plain { "" } else { " " }))
+    }
+}
+
+/// The bindings of an action in `[keys]`: one or a list of them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Keys(pub Vec<Binding>);
+
+impl<'de> Deserialize<'de> for Keys {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct KeysVisitor;
+
+        impl<'de> Visitor<'de> for KeysVisitor {
+            type Value = Keys;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(
+                    f,
+                    "a key like \"q\", \"ctrl-d\" or \"g g\", or a list of them"
+                )
+            }
+
+            fn visit_str<E: de::Error>(self, value: &str) -> Result<Keys, E> {
+                value
+                    .parse()
+                    .map(|binding| Keys(vec![binding]))
+                    .map_err(E::custom)
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Keys, A::Error> {
+                let mut bindings = Vec::new();
+                while let Some(binding) = seq.next_element::<String>()? {
+                    bindings.push(binding.parse().map_err(de::Error::custom)?);
+                }
+                Ok(Keys(bindings))
+            }
+        }
+
+        deserializer.deserialize_any(KeysVisitor)
+    }
+}
+
+/// What the keys pressed so far amount to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Lookup {
+    Action(Action),
+    /// The start of a longer binding.
+    Pending,
+    Unbound,
+}
+
+/// Which keys do what in the lists.
+#[derive(Debug, Clone)]
+pub struct Keymap {
+    bindings: Vec<(Binding, Action)>,
+}
+
+impl Keymap {
+    /// The preset's bindings, with those of the actions in `overrides`
+    /// replaced. Fails if a binding is given to two actions or one is the
+    /// start of another, which could then never be finished.
+    pub fn new(preset: Preset, overrides: &BTreeMap<Action, Keys>) -> Result<Self, String> {
+        let mut bindings: Vec<(Binding, Action)> = Vec::new();
+        for action in Action::ALL {
+            let action_bindings = match overrides.get(&action) {
+                Some(Keys(bindings)) => bindings.clone(),
+                None => action
+                    .default_keys(preset)
+                    .iter()
+                    .map(|binding| binding.parse().expect("valid default binding"))
+                    .collect(),
+            };
+            for binding in action_bindings {
+                for (other, other_action) in &bindings {
+                    let (shorter, longer) = match other.0.len() <= binding.0.len() {
+                        true => (other, &binding),
+                        false => (&binding, other),
+                    };
+                    if shorter == longer {
+                        return Err(format!(
+                            "`{}` is bound to both {} and {}",
+                            binding,
+                            other_action.name(),
+                            action.name()
+                        ));
+                    }
+                    if longer.0.starts_with(&shorter.0) {
+                        return Err(format!(
+                            "`{}` ({}) is the start of `{}` ({})",
+                            shorter,
+                            if shorter == other {
+                                other_action
+                            } else {
+                                &action
+                            }
+                            .name(),
+                            longer,
+                            if longer == other {
+                                other_action
+                            } else {
+                                &action
+                            }
+                            .name(),
+                        ));
+                    }
+                }
+                bindings.push((binding, action));
+            }
+        }
+        Ok(Keymap { bindings })
+    }
+
+    pub fn lookup(&self, pressed: &[KeyEvent]) -> Lookup {
+        let mut lookup = Lookup::Unbound;
+        for (binding, action) in &self.bindings {
+            let matched = binding.0.len() >= pressed.len()
+                && binding
+                    .0
+                    .iter()
+                    .zip(pressed)
+                    .all(|(key, event)| key.matches(event));
+            if matched && binding.0.len() == pressed.len() {
+                return Lookup::Action(*action);
+            }
+            if matched {
+                lookup = Lookup::Pending;
+            }
+        }
+        lookup
+    }
+
+    /// The bindings of `action`, as shown in the help line.
+    pub fn describe(&self, action: Action) -> String {
+        let keys: Vec<String> = self
+            .bindings
+            .iter()
+            .filter(|(_, a)| *a == action)
+            .map(|(binding, _)| binding.to_string())
+            .collect();
+        keys.join("/")
+    }
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Keymap::new(Preset::Default, &BTreeMap::new()).expect("default keys do not clash")
+    }
+}
+
+/// Keys typed so far towards a binding of several keys.
+#[derive(Debug, Default)]
+pub struct Pending(Vec<KeyEvent>);
+
+impl Pending {
+    /// Adds `event` and returns the action it completes, if any. A key that
+    /// leads nowhere after others starts over on its own, so a stray `g`
+    /// does not swallow the next key.
+    pub fn press(&mut self, keymap: &Keymap, event: KeyEvent) -> Option<Action> {
+        self.0.push(event);
+        loop {
+            match keymap.lookup(&self.0) {
+                Lookup::Action(action) => {
+                    self.0.clear();
+                    return Some(action);
+                }
+                Lookup::Pending => return None,
+                Lookup::Unbound if self.0.len() > 1 => {
+                    self.0.drain(..self.0.len() - 1);
+                }
+                Lookup::Unbound => {
+                    self.0.clear();
+                    return None;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn press(code: KeyCode, modifiers: KeyModifiers) -> KeyEvent {
+        KeyEvent::new(code, modifiers)
+    }
+
+    #[test]
+    fn parses_keys_with_modifiers() {
+        let key: Key = "Ctrl-d".parse().unwrap();
+        assert!(key.matches(&press(KeyCode::Char('d'), KeyModifiers::CONTROL)));
+        assert!(!key.matches(&press(KeyCode::Char('d'), KeyModifiers::NONE)));
+        assert_eq!(key.to_string(), "Ctrl-d");
+
+        let key: Key = "G".parse().unwrap();
+        assert!(key.matches(&press(KeyCode::Char('G'), KeyModifiers::SHIFT)));
+        assert_eq!("pagedown".parse::<Key>().unwrap().code, KeyCode::PageDown);
+        assert_eq!("f5".parse::<Key>().unwrap().code, KeyCode::F(5));
+        assert_eq!("ctrl--".parse::<Key>().unwrap().code, KeyCode::Char('-'));
+        assert!("ctrl-foo".parse::<Key>().is_err());
+    }
+
+    #[test]
+    fn overrides_replace_the_default_keys() {
+        let overrides = BTreeMap::from([(
+            Action::Down,
+            Keys(vec!["j".parse().unwrap(), "down".parse().unwrap()]),
+        )]);
+        let keymap = Keymap::new(Preset::Default, &overrides).unwrap();
+        let j = press(KeyCode::Char('j'), KeyModifiers::NONE);
+        assert_eq!(keymap.lookup(&[j]), Lookup::Action(Action::Down));
+        assert_eq!(keymap.describe(Action::Down), "j/↓");
+        assert_eq!(keymap.describe(Action::Up), "↑");
+    }
+
+    #[test]
+    fn a_key_bound_twice_is_an_error() {
+        let overrides = BTreeMap::from([(Action::Delete, Keys(vec!["q".parse().unwrap()]))]);
+        assert_eq!(
+            Keymap::new(Preset::Default, &overrides).unwrap_err(),
+            "`q` is bound to both quit and delete"
+        );
+    }
+
+    #[test]
+    fn a_binding_that_starts_another_is_an_error() {
+        let overrides = BTreeMap::from([(Action::Quit, Keys(vec!["g".parse().unwrap()]))]);
+        assert_eq!(
+            Keymap::new(Preset::Vim, &overrides).unwrap_err(),
+            "`g` (quit) is the start of `gt` (next_tab)"
+        );
+    }
+
+    #[test]
+    fn vim_sequences() {
+        let keymap = Keymap::new(Preset::Vim, &BTreeMap::new()).unwrap();
+        let mut pending = Pending::default();
+        let key = |c| press(KeyCode::Char(c), KeyModifiers::NONE);
+
+        assert_eq!(pending.press(&keymap, key('g')), None);
+        assert_eq!(pending.press(&keymap, key('g')), Some(Action::First));
+        assert_eq!(
+            pending.press(&keymap, press(KeyCode::Char('G'), KeyModifiers::SHIFT)),
+            Some(Action::Last)
+        );
+        assert_eq!(pending.press(&keymap, key('d')), None);
+        assert_eq!(pending.press(&keymap, key('d')), Some(Action::Delete));
+        // A `g` going nowhere does not swallow the `j` after it.
+        assert_eq!(pending.press(&keymap, key('g')), None);
+        assert_eq!(pending.press(&keymap, key('j')), Some(Action::Down));
+        assert_eq!(keymap.describe(Action::First), "gg/Home");
+    }
+}
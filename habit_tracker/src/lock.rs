@@ -0,0 +1,49 @@
+use fs2::FileExt;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::path::Path;
+
+const LOCK_FILE: &str = ".habit_tracker.lock";
+
+/// An advisory lock on the data directory, held by the instance allowed to
+/// write to it. Released when dropped.
+pub struct DataLock {
+    _file: File,
+}
+
+impl DataLock {
+    /// Returns `None` if another instance already holds the lock.
+    pub fn acquire(dir: &Path) -> io::Result<Option<Self>> {
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(dir.join(LOCK_FILE))?;
+        match file.try_lock_exclusive() {
+            Ok(()) => Ok(Some(DataLock { _file: file })),
+            Err(e) if e.kind() == fs2::lock_contended_error().kind() => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn only_one_instance_holds_the_lock() {
+        let dir = std::env::temp_dir().join(format!("habit_tracker-lock-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let first = DataLock::acquire(&dir).unwrap();
+        assert!(first.is_some());
+        assert!(DataLock::acquire(&dir).unwrap().is_none());
+        // Released once the holder is dropped.
+        drop(first);
+        assert!(DataLock::acquire(&dir).unwrap().is_some());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
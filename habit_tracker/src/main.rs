@@ -4,18 +4,121 @@ use crossterm::{
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
 use std::error::Error;
-use std::io;
+use std::io::{self, Write};
+use std::path::Path;
+use std::time::Duration;
 use tui::backend::CrosstermBackend;
 use tui::Terminal;
 
+mod api;
+mod cli;
+mod clock;
+mod config;
+mod crdt;
+mod crypto;
+mod daemon;
+mod eventlog;
 mod habit;
+mod habit_csv;
+mod hooks;
+mod ical;
+mod importers;
+mod keymap;
+mod lock;
+mod merge;
+mod prompt;
+mod remote;
+mod report;
+mod server;
+mod sqlite;
+mod stats;
 mod storage;
+mod sync;
+mod theme;
 mod todo;
 mod ui;
+mod watcher;
+mod webhook;
 
+use crate::keymap::Action;
 use crate::ui::{InputMode, ListEntry};
 
+const RELOAD_INTERVAL: Duration = Duration::from_millis(500);
+const CSV_FILE: &str = "habits.csv";
+const WIDE_CSV_FILE: &str = "habits-wide.csv";
+
 fn main() -> Result<(), Box<dyn Error>> {
+    let config = match config::load() {
+        Ok(config) => config,
+        Err(err) => {
+            // Printed as is, so TOML errors keep their pointer to the line
+            eprintln!("Invalid configuration: {}", err);
+            std::process::exit(1);
+        }
+    };
+    if let Some(dir) = config.data_dir() {
+        std::fs::create_dir_all(&dir)?;
+        std::env::set_current_dir(&dir)?;
+    }
+    clock::set_day_start_hour(config.day_start_hour);
+    if let Some(day) = config.week_start {
+        clock::set_week_start(day);
+    }
+    if let Some(zone) = &config.home_zone {
+        clock::set_home_zone(clock::Zone::named(zone)?);
+    }
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if !args.is_empty() {
+        return cli::run(&args, &config);
+    }
+
+    // Only one instance may write to the data directory; others are read-only
+    let lock = lock::DataLock::acquire(Path::new("."))?;
+
+    // Load data before touching the terminal, so errors are printed normally
+    let sync_enabled = config.git_sync && lock.is_some();
+    let loaded = recovering(|| {
+        // Opened again after a recovery, as SQLite keeps its file open
+        let storage = storage::open(&config)?;
+        if sync_enabled {
+            // A failed pull (e.g. while offline) should not keep the app from starting.
+            if let Err(err) = pull_changes(&storage.watched_files()) {
+                eprintln!("Sync failed: {}", err);
+            }
+        }
+        let data = load_data(storage.as_ref())?;
+        Ok((storage, data))
+    });
+    let (mut storage, (mut habits, mut todos, mut category_colors)) = match loaded {
+        Ok(Some(loaded)) => loaded,
+        Ok(None) => return Ok(()),
+        Err(err) => {
+            eprintln!("Could not load the data: {}", err);
+            std::process::exit(1);
+        }
+    };
+    if lock.is_some() {
+        sync_with_server(&config, &mut habits, &mut category_colors);
+    }
+    let mut snapshot = Snapshot {
+        habits: habits.clone(),
+        todos: todos.clone(),
+        category_colors: category_colors.clone(),
+    };
+    let mut current_date = clock::today();
+    let mut app_state = ui::AppState {
+        read_only: lock.is_none(),
+        current_tab: config.default_tab.index(),
+        ..Default::default()
+    };
+    let settings = ui::Settings {
+        theme: config.theme(),
+        icons: config.icons,
+        date_format: config.date_format().to_string(),
+        keymap: config.keymap()?,
+    };
+    let mut hooks = hooks::Runner::new(config.hooks.clone(), config.webhooks.clone());
+
     // Set up terminal
     enable_raw_mode()?;
     let mut stdout = io::stdout();
@@ -23,19 +126,18 @@ fn main() -> Result<(), Box<dyn Error>> {
     let backend = CrosstermBackend::new(stdout);
     let mut terminal = Terminal::new(backend)?;
 
-    // Set up application state
-    let mut habits = storage::load_habits()?;
-    let mut todos = storage::load_todos()?;
-    let mut current_date = chrono::Local::now().date_naive();
-    let mut app_state = ui::AppState::default();
-
     // Run the main application loop
     let res = run_app(
         &mut terminal,
+        storage.as_mut(),
+        &mut snapshot,
         &mut habits,
         &mut todos,
+        &mut category_colors,
         &mut current_date,
         &mut app_state,
+        &settings,
+        &mut hooks,
     );
 
     // Restore terminal
@@ -52,28 +154,299 @@ fn main() -> Result<(), Box<dyn Error>> {
     }
 
     // Save habits and todos before exiting
-    storage::save_habits(&habits)?;
-    storage::save_todos(&todos)?;
+    if lock.is_some() {
+        save_data(
+            storage.as_mut(),
+            &mut snapshot,
+            &mut habits,
+            &mut todos,
+            &mut category_colors,
+        )?;
+        if config.sync_server.is_some() {
+            sync_with_server(&config, &mut habits, &mut category_colors);
+            storage.save_habits(&habits)?;
+            storage.save_category_colors(&category_colors)?;
+        }
+        if sync_enabled {
+            let message = format!(
+                "Session ended {}",
+                chrono::Local::now().format("%Y-%m-%d %H:%M")
+            );
+            if let Err(err) = sync::sync(Path::new("."), &storage.watched_files(), &message) {
+                println!("Sync failed: {}", err);
+            }
+        }
+    } else {
+        println!("Another instance holds the data directory; nothing was saved.");
+    }
+
+    Ok(())
+}
+
+/// The data as last read from disk. It is the common ancestor when merging
+/// in changes other processes (such as CLI commands) made to the files.
+struct Snapshot {
+    habits: Vec<habit::Habit>,
+    todos: Vec<todo::Todo>,
+    category_colors: habit::CategoryColors,
+}
 
+/// Merges whatever is on disk now into the in-memory data. Returns true if
+/// anything was different from the snapshot.
+fn merge_from_disk(
+    storage: &dyn storage::Storage,
+    snapshot: &mut Snapshot,
+    habits: &mut Vec<habit::Habit>,
+    todos: &mut Vec<todo::Todo>,
+    category_colors: &mut habit::CategoryColors,
+) -> bool {
+    let mut changed = false;
+    // Unreadable files (e.g. caught mid-write) are skipped until they change again.
+    if let Ok(on_disk) = storage.load_habits() {
+        if on_disk != snapshot.habits {
+            *habits = merge::merge_habits(&snapshot.habits, habits, &on_disk);
+            snapshot.habits = on_disk;
+            changed = true;
+        }
+    }
+    if let Ok(on_disk) = storage.load_todos() {
+        if on_disk != snapshot.todos {
+            *todos = merge::merge_todos(&snapshot.todos, todos, &on_disk);
+            snapshot.todos = on_disk;
+            changed = true;
+        }
+    }
+    if let Ok(on_disk) = storage.load_category_colors() {
+        if on_disk != snapshot.category_colors {
+            *category_colors =
+                merge::merge_category_colors(&snapshot.category_colors, category_colors, &on_disk);
+            snapshot.category_colors = on_disk;
+            changed = true;
+        }
+    }
+    changed
+}
+
+/// Exchanges habit and category color changes with the configured sync
+/// server, if any. Being offline is not an error: changes stay queued for
+/// the next sync.
+fn sync_with_server(
+    config: &config::Config,
+    habits: &mut Vec<habit::Habit>,
+    category_colors: &mut habit::CategoryColors,
+) {
+    let Some(url) = &config.sync_server else {
+        return;
+    };
+    let state_file = Path::new(remote::STATE_FILE);
+    match remote::sync(habits, category_colors, state_file, url, config.sync_user()) {
+        Ok(remote::Outcome::Synced) => {}
+        Ok(remote::Outcome::Offline(pending)) => {
+            println!("Sync server unreachable; {} changes queued", pending)
+        }
+        Err(err) => eprintln!("Sync failed: {}", err),
+    }
+}
+
+/// Commits whatever changed since the last session, then merges in the
+/// remote's changes so the session starts from the latest data.
+fn pull_changes(files: &[std::path::PathBuf]) -> io::Result<()> {
+    let dir = Path::new(".");
+    sync::init(dir, None)?;
+    sync::commit(dir, files, "Changes made outside a session")?;
+    sync::pull(dir)?;
     Ok(())
 }
 
+/// Saves the session after merging in any external changes.
+fn save_data(
+    storage: &mut dyn storage::Storage,
+    snapshot: &mut Snapshot,
+    habits: &mut Vec<habit::Habit>,
+    todos: &mut Vec<todo::Todo>,
+    category_colors: &mut habit::CategoryColors,
+) -> io::Result<()> {
+    merge_from_disk(storage, snapshot, habits, todos, category_colors);
+    storage.save_habits(habits)?;
+    storage.save_todos(todos)?;
+    storage.save_category_colors(category_colors)?;
+    snapshot.habits = habits.clone();
+    snapshot.todos = todos.clone();
+    snapshot.category_colors = category_colors.clone();
+    Ok(())
+}
+
+/// Loads habits, todos and category colors.
+fn load_data(
+    storage: &dyn storage::Storage,
+) -> io::Result<(Vec<habit::Habit>, Vec<todo::Todo>, habit::CategoryColors)> {
+    Ok((
+        storage.load_habits()?,
+        storage.load_todos()?,
+        storage.load_category_colors()?,
+    ))
+}
+
+/// Runs `load` until it succeeds, offering to recover each data file it
+/// finds unreadable, whichever backend it belongs to. Returns `None` if the
+/// user chose to quit.
+fn recovering<T>(mut load: impl FnMut() -> io::Result<T>) -> io::Result<Option<T>> {
+    loop {
+        let err = match load() {
+            Ok(value) => return Ok(Some(value)),
+            Err(err) => err,
+        };
+        match storage::corrupt_file(&err) {
+            Some(corrupt) => {
+                if !recover_corrupt_file(corrupt)? {
+                    return Ok(None);
+                }
+            }
+            None => return Err(err),
+        }
+    }
+}
+
+/// Asks whether to restore the latest backup of an unreadable file or start
+/// without it. The bad file is kept as `<name>.corrupt-<timestamp>` either way.
+fn recover_corrupt_file(corrupt: &storage::CorruptFile) -> io::Result<bool> {
+    let backup = storage::latest_backup(&corrupt.path)?;
+    eprintln!("Could not read {}", corrupt);
+    match &backup {
+        Some(backup) => eprintln!("  [r] Restore the latest backup ({})", backup.display()),
+        None => eprintln!("  (no backups of this file were found)"),
+    }
+    eprintln!("  [n] Start without this data");
+    eprintln!("  [q] Quit and leave the file untouched");
+    eprint!("> ");
+    io::stderr().flush()?;
+
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+    let restore = match (answer.trim(), &backup) {
+        ("r", Some(backup)) => Some(backup),
+        ("n", _) => None,
+        _ => return Ok(false),
+    };
+
+    let moved = storage::quarantine(&corrupt.path)?;
+    eprintln!("Moved the unreadable file to {}", moved.display());
+    if let Some(backup) = restore {
+        storage::restore_backup(&corrupt.path, backup)?;
+    }
+    Ok(true)
+}
+
+/// Writes a CSV export for the TUI and returns a status message.
+fn export_csv(habits: &[habit::Habit], layout: habit_csv::Layout, path: &str) -> String {
+    match habit_csv::export(habits, layout).and_then(|csv| std::fs::write(path, csv)) {
+        Ok(()) => format!("Exported to {}", path),
+        Err(e) => format!("Export failed: {}", e),
+    }
+}
+
+/// Removes the selected entry; a category takes its habits with it.
+fn delete_selected(
+    habits: &mut Vec<habit::Habit>,
+    todos: &mut Vec<todo::Todo>,
+    app_state: &mut ui::AppState,
+) {
+    let Some(index) = app_state.selected else {
+        return;
+    };
+    match &app_state.list_items[index] {
+        ListEntry::Category(category) => {
+            // Remove all habits in the category
+            habits.retain(|h| h.category != *category);
+        }
+        ListEntry::Habit(selected_habit) => {
+            // Remove the selected habit
+            habits
+                .retain(|h| h.name != selected_habit.name || h.category != selected_habit.category);
+        }
+        ListEntry::Todo(selected_todo) => {
+            // Remove the selected todo
+            todos.retain(|t| t.description != selected_todo.description);
+        }
+    }
+    app_state.update_list_items(habits, todos);
+    if !app_state.list_items.is_empty() {
+        app_state.selected = Some(index.min(app_state.total_items - 1));
+    } else {
+        app_state.selected = None;
+    }
+}
+
+/// Answers the delete prompt: `y` deletes the selected entry, any other key
+/// keeps it.
+fn confirm_delete(
+    key: KeyCode,
+    habits: &mut Vec<habit::Habit>,
+    todos: &mut Vec<todo::Todo>,
+    app_state: &mut ui::AppState,
+) {
+    if key == KeyCode::Char('y') {
+        delete_selected(habits, todos, app_state);
+    }
+    app_state.input_mode = InputMode::Normal;
+}
+
+#[allow(clippy::too_many_arguments)]
 fn run_app<B: tui::backend::Backend>(
     terminal: &mut Terminal<B>,
+    storage: &mut dyn storage::Storage,
+    snapshot: &mut Snapshot,
     habits: &mut Vec<habit::Habit>,
     todos: &mut Vec<todo::Todo>,
+    category_colors: &mut habit::CategoryColors,
     current_date: &mut chrono::NaiveDate,
     app_state: &mut ui::AppState,
+    settings: &ui::Settings,
+    hooks: &mut hooks::Runner,
 ) -> io::Result<()> {
+    let mut watcher = watcher::Watcher::new(storage.watched_files());
     app_state.update_list_items(habits, todos);
     loop {
-        terminal.draw(|f| ui::draw(f, habits, todos, current_date, app_state))?;
+        terminal.draw(|f| {
+            ui::draw(
+                f,
+                habits,
+                todos,
+                category_colors,
+                current_date,
+                app_state,
+                settings,
+            )
+        })?;
+
+        // Wake up periodically to pick up edits made by other processes
+        if !event::poll(RELOAD_INTERVAL)? {
+            if watcher.poll() && merge_from_disk(storage, snapshot, habits, todos, category_colors)
+            {
+                app_state.update_list_items(habits, todos);
+                app_state.clamp_selection();
+            }
+            // Save while idle, so other processes (such as `habit_tracker
+            // api`) see changes without waiting for the session to end
+            let changed = *habits != snapshot.habits
+                || *todos != snapshot.todos
+                || *category_colors != snapshot.category_colors;
+            if !app_state.read_only && changed {
+                save_data(storage, snapshot, habits, todos, category_colors)?;
+                watcher.poll();
+                app_state.update_list_items(habits, todos);
+                app_state.clamp_selection();
+            }
+            continue;
+        }
 
         if let Event::Key(key) = event::read()? {
             match app_state.input_mode {
-                InputMode::Normal => match key.code {
-                    KeyCode::Char('q') => return Ok(()),
-                    KeyCode::Char('a') => {
+                InputMode::Normal => match app_state.pending.press(&settings.keymap, key) {
+                    Some(Action::Quit) => return Ok(()),
+                    // A read-only instance can browse but not change anything
+                    Some(action) if action.edits() && app_state.read_only => {}
+                    Some(Action::Add) => {
                         if app_state.current_tab == 4 {
                             app_state.input_mode = InputMode::AddingTodo;
                             app_state.new_todo.clear();
@@ -84,7 +457,7 @@ fn run_app<B: tui::backend::Backend>(
                             app_state.new_habit_frequency = habit::Frequency::Daily;
                         }
                     }
-                    KeyCode::Char('e') => {
+                    Some(Action::Edit) => {
                         if let Some(index) = app_state.selected {
                             match &app_state.list_items[index] {
                                 ListEntry::Category(category) => {
@@ -99,8 +472,9 @@ fn run_app<B: tui::backend::Backend>(
                             }
                         }
                     }
-                    KeyCode::Enter => {
+                    Some(Action::ToggleDone) => {
                         if let Some(index) = app_state.selected {
+                            let old = (habits.clone(), todos.clone());
                             match &app_state.list_items[index] {
                                 ListEntry::Category(category) => {
                                     // Toggle all habits in the category
@@ -108,14 +482,14 @@ fn run_app<B: tui::backend::Backend>(
                                         .iter()
                                         .filter(|h| h.category == *category)
                                         .all(|h| h.is_completed(*current_date));
+                                    let done = !all_completed;
+                                    let check_in =
+                                        done.then(|| clock::check_in_for(*current_date)).flatten();
                                     for habit in
                                         habits.iter_mut().filter(|h| h.category == *category)
                                     {
-                                        if all_completed {
-                                            habit.unmark_completed(*current_date);
-                                        } else {
-                                            habit.mark_completed(*current_date);
-                                        }
+                                        habit.set_completed(*current_date, done, check_in.clone());
+                                        storage.record_check_in(habit, *current_date)?;
                                     }
                                 }
                                 ListEntry::Habit(selected_habit) => {
@@ -123,11 +497,12 @@ fn run_app<B: tui::backend::Backend>(
                                         h.name == selected_habit.name
                                             && h.category == selected_habit.category
                                     }) {
-                                        if habit.is_completed(*current_date) {
-                                            habit.unmark_completed(*current_date);
-                                        } else {
-                                            habit.mark_completed(*current_date);
-                                        }
+                                        let done = !habit.is_completed(*current_date);
+                                        let check_in = done
+                                            .then(|| clock::check_in_for(*current_date))
+                                            .flatten();
+                                        habit.set_completed(*current_date, done, check_in.clone());
+                                        storage.record_check_in(habit, *current_date)?;
                                     }
                                 }
                                 ListEntry::Todo(selected_todo) => {
@@ -139,59 +514,179 @@ fn run_app<B: tui::backend::Backend>(
                                     }
                                 }
                             }
+                            hooks.fire(&hooks::events(
+                                (&old.0, &old.1),
+                                (habits, todos),
+                                *current_date,
+                                &hooks.milestones(),
+                            ));
                             app_state.update_list_items(habits, todos);
                         }
                     }
-                    KeyCode::Char('d') => {
-                        if let Some(index) = app_state.selected {
-                            match &app_state.list_items[index] {
-                                ListEntry::Category(category) => {
-                                    // Remove all habits in the category
-                                    habits.retain(|h| h.category != *category);
-                                }
-                                ListEntry::Habit(selected_habit) => {
-                                    // Remove the selected habit
-                                    habits.retain(|h| {
-                                        h.name != selected_habit.name
-                                            || h.category != selected_habit.category
-                                    });
-                                }
-                                ListEntry::Todo(selected_todo) => {
-                                    // Remove the selected todo
-                                    todos.retain(|t| t.description != selected_todo.description);
-                                }
-                            }
-                            app_state.update_list_items(habits, todos);
-                            if !app_state.list_items.is_empty() {
-                                app_state.selected = Some(index.min(app_state.total_items - 1));
-                            } else {
-                                app_state.selected = None;
-                            }
-                        }
+                    Some(Action::Delete) if app_state.selected.is_some() => {
+                        app_state.input_mode = InputMode::ConfirmDelete;
                     }
-                    KeyCode::Left => {
+                    Some(Action::PreviousDay) => {
                         *current_date = current_date.pred_opt().unwrap_or(*current_date)
                     }
-                    KeyCode::Right => {
+                    Some(Action::NextDay) => {
                         *current_date = current_date.succ_opt().unwrap_or(*current_date)
                     }
-                    KeyCode::Up => {
+                    Some(Action::Up) => {
                         app_state.previous();
                     }
-                    KeyCode::Down => {
+                    Some(Action::Down) => {
                         app_state.next();
                     }
-                    KeyCode::Tab => {
+                    Some(Action::NextTab) => {
                         app_state.current_tab = (app_state.current_tab + 1) % 5;
                         app_state.selected = None;
                         app_state.update_list_items(habits, todos);
                     }
-                    KeyCode::Char('p') => {
+                    Some(Action::PreviousWeek) => {
                         app_state.previous_week();
                     }
-                    KeyCode::Char('n') => {
+                    Some(Action::NextWeek) => {
                         app_state.next_week();
                     }
+                    Some(Action::ImportExport) => {
+                        app_state.input_mode = InputMode::ExportMenu;
+                    }
+                    Some(Action::Color) => {
+                        let category = match app_state.selected.map(|i| &app_state.list_items[i]) {
+                            // The To-Do List header is not a category
+                            _ if app_state.current_tab == 4 => {
+                                app_state.status = "To-dos have no category color".to_string();
+                                None
+                            }
+                            Some(ListEntry::Category(category)) => Some(category),
+                            Some(ListEntry::Habit(habit)) => Some(&habit.category),
+                            _ => None,
+                        };
+                        if let Some(category) = category {
+                            app_state.edit_buffer =
+                                category_colors.get(category).cloned().unwrap_or_default();
+                            app_state.input_mode = InputMode::EditingColor;
+                        }
+                    }
+                    Some(Action::First) => {
+                        app_state.first();
+                    }
+                    Some(Action::Last) => {
+                        app_state.last();
+                    }
+                    Some(Action::Search) => {
+                        app_state.input_mode = InputMode::Searching;
+                        app_state.search.clear();
+                        app_state.search_origin = app_state.selected;
+                    }
+                    Some(action @ (Action::SearchNext | Action::SearchPrevious)) => {
+                        let backwards = action == Action::SearchPrevious;
+                        if !app_state.search.is_empty() && !app_state.search(backwards) {
+                            app_state.status = format!("Not found: {}", app_state.search);
+                        }
+                    }
+                    Some(Action::Delete) | None => {}
+                },
+                InputMode::ConfirmDelete => confirm_delete(key.code, habits, todos, app_state),
+                InputMode::EditingColor => match key.code {
+                    KeyCode::Enter => {
+                        let category = match app_state.selected.map(|i| &app_state.list_items[i]) {
+                            Some(ListEntry::Category(category)) => category.clone(),
+                            Some(ListEntry::Habit(habit)) => habit.category.clone(),
+                            _ => String::new(),
+                        };
+                        let color = app_state.edit_buffer.trim();
+                        match color {
+                            "" => habit::set_category_color(category_colors, &category, None),
+                            _ => match theme::parse_color(color) {
+                                Ok(_) => habit::set_category_color(
+                                    category_colors,
+                                    &category,
+                                    Some(color),
+                                ),
+                                Err(e) => app_state.status = e,
+                            },
+                        }
+                        app_state.input_mode = InputMode::Normal;
+                        app_state.edit_buffer.clear();
+                        app_state.update_list_items(habits, todos);
+                    }
+                    KeyCode::Esc => {
+                        app_state.input_mode = InputMode::Normal;
+                        app_state.edit_buffer.clear();
+                    }
+                    KeyCode::Char(c) => {
+                        app_state.edit_buffer.push(c);
+                    }
+                    KeyCode::Backspace => {
+                        app_state.edit_buffer.pop();
+                    }
+                    _ => {}
+                },
+                InputMode::Searching => match key.code {
+                    KeyCode::Enter => {
+                        app_state.input_mode = InputMode::Normal;
+                    }
+                    KeyCode::Esc => {
+                        app_state.input_mode = InputMode::Normal;
+                        app_state.selected = app_state.search_origin;
+                        app_state.search.clear();
+                    }
+                    KeyCode::Char(c) => {
+                        app_state.search.push(c);
+                        app_state.selected = app_state.search_origin;
+                        app_state.search(false);
+                    }
+                    KeyCode::Backspace => {
+                        app_state.search.pop();
+                        app_state.selected = app_state.search_origin;
+                        app_state.search(false);
+                    }
+                    _ => {}
+                },
+                InputMode::ExportMenu => match key.code {
+                    KeyCode::Char('l') => {
+                        app_state.status = export_csv(habits, habit_csv::Layout::Long, CSV_FILE);
+                        app_state.input_mode = InputMode::Normal;
+                    }
+                    KeyCode::Char('w') => {
+                        app_state.status =
+                            export_csv(habits, habit_csv::Layout::Wide, WIDE_CSV_FILE);
+                        app_state.input_mode = InputMode::Normal;
+                    }
+                    KeyCode::Char('i') if !app_state.read_only => {
+                        app_state.input_mode = InputMode::ImportingCsv;
+                        app_state.edit_buffer = CSV_FILE.to_string();
+                    }
+                    KeyCode::Esc => {
+                        app_state.input_mode = InputMode::Normal;
+                    }
+                    _ => {}
+                },
+                InputMode::ImportingCsv => match key.code {
+                    KeyCode::Enter => {
+                        app_state.status = match std::fs::read_to_string(&app_state.edit_buffer)
+                            .and_then(|contents| {
+                                habit_csv::import(habits, &contents, habit_csv::Conflict::Merge)
+                            }) {
+                            Ok(summary) => format!("Imported: {}", summary),
+                            Err(e) => format!("Import failed: {}", e),
+                        };
+                        app_state.input_mode = InputMode::Normal;
+                        app_state.edit_buffer.clear();
+                        app_state.update_list_items(habits, todos);
+                    }
+                    KeyCode::Esc => {
+                        app_state.input_mode = InputMode::Normal;
+                        app_state.edit_buffer.clear();
+                    }
+                    KeyCode::Char(c) => {
+                        app_state.edit_buffer.push(c);
+                    }
+                    KeyCode::Backspace => {
+                        app_state.edit_buffer.pop();
+                    }
                     _ => {}
                 },
                 InputMode::AddingCategory => match key.code {
@@ -213,12 +708,11 @@ fn run_app<B: tui::backend::Backend>(
                 },
                 InputMode::AddingHabit => match key.code {
                     KeyCode::Enter => {
-                        let new_habit = habit::Habit::new(
+                        habits.push(habit::Habit::new(
                             app_state.new_habit_name.clone(),
                             app_state.new_category.clone(),
                             app_state.new_habit_frequency,
-                        );
-                        habits.push(new_habit);
+                        ));
                         app_state.input_mode = InputMode::Normal;
                         app_state.new_habit_name.clear();
                         app_state.new_category.clear();
@@ -272,8 +766,19 @@ fn run_app<B: tui::backend::Backend>(
                             {
                                 let old_category = category.clone();
                                 *category = app_state.edit_buffer.clone();
+                                habit::rename_category_color(
+                                    category_colors,
+                                    &old_category,
+                                    &app_state.edit_buffer,
+                                );
                                 for habit in habits.iter_mut() {
                                     if habit.category == old_category {
+                                        storage.record_rename(
+                                            &habit.name,
+                                            &old_category,
+                                            &habit.name,
+                                            &app_state.edit_buffer,
+                                        )?;
                                         habit.category = app_state.edit_buffer.clone();
                                     }
                                 }
@@ -305,6 +810,12 @@ fn run_app<B: tui::backend::Backend>(
                                     .iter_mut()
                                     .find(|h| h.name == old_name && h.category == habit.category)
                                 {
+                                    storage.record_rename(
+                                        &old_name,
+                                        &h.category,
+                                        &app_state.edit_buffer,
+                                        &h.category,
+                                    )?;
                                     h.name = app_state.edit_buffer.clone();
                                 }
                             }
@@ -330,3 +841,118 @@ fn run_app<B: tui::backend::Backend>(
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::habit::{Frequency, Habit};
+    use crate::todo::Todo;
+
+    fn habit(name: &str, category: &str) -> Habit {
+        Habit::new(name.to_string(), category.to_string(), Frequency::Daily)
+    }
+
+    fn todo(description: &str) -> Todo {
+        Todo {
+            description: description.to_string(),
+            ..Todo::default()
+        }
+    }
+
+    /// Asks to delete the entry at `index` and answers with `key`.
+    fn delete(
+        key: KeyCode,
+        index: usize,
+        habits: &mut Vec<Habit>,
+        todos: &mut Vec<Todo>,
+        app_state: &mut ui::AppState,
+    ) {
+        app_state.update_list_items(habits, todos);
+        app_state.selected = Some(index);
+        app_state.input_mode = InputMode::ConfirmDelete;
+        confirm_delete(key, habits, todos, app_state);
+        assert!(matches!(app_state.input_mode, InputMode::Normal));
+    }
+
+    #[test]
+    fn y_deletes_the_selected_habit() {
+        let mut habits = vec![habit("Read", "Mind"), habit("Run", "Body")];
+        let mut todos = Vec::new();
+        let mut app_state = ui::AppState::default();
+        // Body, Run, Mind, Read
+        delete(
+            KeyCode::Char('y'),
+            1,
+            &mut habits,
+            &mut todos,
+            &mut app_state,
+        );
+        let names: Vec<&str> = habits.iter().map(|h| h.name.as_str()).collect();
+        assert_eq!(names, ["Read"]);
+        assert_eq!(app_state.total_items, 2);
+        assert_eq!(app_state.selected, Some(1));
+    }
+
+    #[test]
+    fn other_keys_keep_the_entry() {
+        let mut habits = vec![habit("Read", "Mind")];
+        let mut todos = Vec::new();
+        let mut app_state = ui::AppState::default();
+        for key in [KeyCode::Char('n'), KeyCode::Esc, KeyCode::Char('Y')] {
+            delete(key, 1, &mut habits, &mut todos, &mut app_state);
+        }
+        assert_eq!(habits.len(), 1);
+        assert_eq!(app_state.selected, Some(1));
+    }
+
+    #[test]
+    fn deleting_a_category_takes_its_habits() {
+        let mut habits = vec![
+            habit("Read", "Mind"),
+            habit("Run", "Body"),
+            habit("Swim", "Body"),
+        ];
+        let mut todos = Vec::new();
+        let mut app_state = ui::AppState::default();
+        delete(
+            KeyCode::Char('y'),
+            0,
+            &mut habits,
+            &mut todos,
+            &mut app_state,
+        );
+        let names: Vec<&str> = habits.iter().map(|h| h.name.as_str()).collect();
+        assert_eq!(names, ["Read"]);
+        assert_eq!(app_state.selected, Some(0));
+    }
+
+    #[test]
+    fn deleting_the_last_entry_moves_the_selection_up() {
+        let mut habits = Vec::new();
+        let mut todos = vec![todo("Buy milk"), todo("Call mum")];
+        let mut app_state = ui::AppState {
+            current_tab: 4,
+            ..ui::AppState::default()
+        };
+        // To-Do List, Buy milk, Call mum
+        delete(
+            KeyCode::Char('y'),
+            2,
+            &mut habits,
+            &mut todos,
+            &mut app_state,
+        );
+        assert_eq!(todos.len(), 1);
+        assert_eq!(todos[0].description, "Buy milk");
+        assert_eq!(app_state.selected, Some(1));
+
+        delete(
+            KeyCode::Char('y'),
+            1,
+            &mut habits,
+            &mut todos,
+            &mut app_state,
+        );
+        assert!(todos.is_empty());
+        assert_eq!(app_state.selected, Some(0));
+    }
+}
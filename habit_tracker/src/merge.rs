@@ -0,0 +1,279 @@
+use crate::habit::{CategoryColors, Habit};
+use crate::todo::Todo;
+
+/// Three-way merge of habit lists. `ours` is taken as the starting point and
+/// every change `theirs` made relative to `base` is applied on top: added and
+/// removed completion dates, changed settings, new habits and deletions.
+/// Habits are matched by name and category.
+pub fn merge_habits(base: &[Habit], ours: &[Habit], theirs: &[Habit]) -> Vec<Habit> {
+    let find = |list: &[Habit], habit: &Habit| {
+        list.iter()
+            .find(|h| h.name == habit.name && h.category == habit.category)
+            .cloned()
+    };
+
+    let mut merged = Vec::new();
+    for our in ours {
+        let base_habit = find(base, our);
+        match (find(theirs, our), &base_habit) {
+            (Some(their), _) => merged.push(merge_habit(base_habit.as_ref(), our, &their)),
+            // Deleted on their side; keep it only if we changed it since.
+            (None, Some(base_habit)) if base_habit == our => {}
+            (None, _) => merged.push(our.clone()),
+        }
+    }
+    for their in theirs {
+        if find(ours, their).is_some() {
+            continue;
+        }
+        match find(base, their) {
+            // Deleted on our side; keep it only if they changed it since.
+            Some(base_habit) if base_habit == *their => {}
+            _ => merged.push(their.clone()),
+        }
+    }
+    merged
+}
+
+fn merge_habit(base: Option<&Habit>, ours: &Habit, theirs: &Habit) -> Habit {
+    let mut merged = ours.clone();
+    if merged.id.is_empty() {
+        merged.id = theirs.id.clone();
+    }
+    let in_base = |date| base.is_some_and(|b| b.is_completed(date));
+    for &date in theirs.completed_dates() {
+        if !in_base(date) {
+            match theirs.check_in_on(date) {
+                Some(check_in) if merged.check_in_on(date).is_none() => {
+                    merged.check_in(date, check_in.clone())
+                }
+                _ => merged.mark_completed(date),
+            }
+        }
+    }
+    if let Some(base) = base {
+        for &date in base.completed_dates() {
+            if !theirs.is_completed(date) {
+                merged.unmark_completed(date);
+            }
+        }
+        if ours.frequency == base.frequency {
+            merged.frequency = theirs.frequency;
+        }
+        if ours.reminders == base.reminders {
+            merged.reminders = theirs.reminders.clone();
+        }
+        if ours.paused == base.paused {
+            merged.paused = theirs.paused;
+        }
+    }
+    merged
+}
+
+/// Three-way merge of category colors. Their side wins for categories whose
+/// color we left untouched, including colors they removed.
+pub fn merge_category_colors(
+    base: &CategoryColors,
+    ours: &CategoryColors,
+    theirs: &CategoryColors,
+) -> CategoryColors {
+    let mut merged = ours.clone();
+    for category in base.keys().chain(theirs.keys()) {
+        if ours.get(category) == base.get(category) {
+            match theirs.get(category) {
+                Some(color) => merged.insert(category.clone(), color.clone()),
+                None => merged.remove(category),
+            };
+        }
+    }
+    merged
+}
+
+/// Three-way merge of todo lists, matching todos by description. Their side
+/// wins for todos we left untouched.
+pub fn merge_todos(base: &[Todo], ours: &[Todo], theirs: &[Todo]) -> Vec<Todo> {
+    let find = |list: &[Todo], todo: &Todo| {
+        list.iter()
+            .find(|t| t.description == todo.description)
+            .cloned()
+    };
+
+    let mut merged = Vec::new();
+    for our in ours {
+        let base_todo = find(base, our);
+        match (find(theirs, our), base_todo) {
+            (Some(their), Some(base_todo)) if base_todo == *our => merged.push(their),
+            (Some(_), _) => merged.push(our.clone()),
+            (None, Some(base_todo)) if base_todo == *our => {}
+            (None, _) => merged.push(our.clone()),
+        }
+    }
+    for their in theirs {
+        if find(ours, their).is_some() {
+            continue;
+        }
+        match find(base, their) {
+            Some(base_todo) if base_todo == *their => {}
+            _ => merged.push(their.clone()),
+        }
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::habit::Frequency;
+    use chrono::NaiveDate;
+
+    fn day(d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 5, d).unwrap()
+    }
+
+    fn habit(name: &str, done: &[u32]) -> Habit {
+        let mut habit = Habit::new(name.into(), "Health".into(), Frequency::Daily);
+        for d in done {
+            habit.mark_completed(day(*d));
+        }
+        habit
+    }
+
+    fn todo(description: &str, completed: bool) -> Todo {
+        Todo {
+            description: description.into(),
+            completed,
+            ..Todo::default()
+        }
+    }
+
+    fn names(habits: &[Habit]) -> Vec<&str> {
+        habits.iter().map(|h| h.name.as_str()).collect()
+    }
+
+    #[test]
+    fn a_deletion_gives_way_to_a_concurrent_edit() {
+        let base = [habit("Run", &[1]), habit("Read", &[1])];
+        // We delete Run and edit Read; they edit Run and delete Read.
+        let ours = [habit("Read", &[1, 2])];
+        let theirs = [habit("Run", &[1, 2])];
+        let merged = merge_habits(&base, &ours, &theirs);
+        assert_eq!(merged, [habit("Read", &[1, 2]), habit("Run", &[1, 2])]);
+
+        // Unedited, the deletions stand.
+        assert!(merge_habits(&base, &[habit("Read", &[1])], &[habit("Run", &[1])]).is_empty());
+    }
+
+    #[test]
+    fn checks_and_unchecks_from_both_sides_are_kept() {
+        let base = [habit("Run", &[1, 2])];
+        // We check the 3rd and uncheck the 1st; they check the 4th and
+        // uncheck the 2nd.
+        let ours = [habit("Run", &[2, 3])];
+        let theirs = [habit("Run", &[1, 4])];
+        let merged = merge_habits(&base, &ours, &theirs);
+        assert_eq!(merged, [habit("Run", &[3, 4])]);
+    }
+
+    #[test]
+    fn a_check_on_one_side_and_an_uncheck_of_the_same_day_on_the_other() {
+        // Each side only changes the day relative to the base, so the side
+        // that changed it wins whichever way the merge is run.
+        let base = [habit("Run", &[1])];
+        let checked = [habit("Run", &[1, 2])];
+        let unchecked = [habit("Run", &[])];
+        assert_eq!(
+            merge_habits(&base, &checked, &unchecked),
+            [habit("Run", &[2])]
+        );
+        assert_eq!(
+            merge_habits(&base, &unchecked, &checked),
+            [habit("Run", &[2])]
+        );
+    }
+
+    #[test]
+    fn concurrent_renames_keep_both_names() {
+        // Habits are matched by name, so a rename is a deletion of the old
+        // name and a new habit; neither side's habit is lost.
+        let base = [habit("Run", &[1])];
+        let ours = [habit("Jog", &[1])];
+        let theirs = [habit("Sprint", &[1, 2])];
+        assert_eq!(
+            names(&merge_habits(&base, &ours, &theirs)),
+            ["Jog", "Sprint"]
+        );
+        // Renamed to the same name on both sides, it stays one habit.
+        let theirs = [habit("Jog", &[1, 2])];
+        assert_eq!(merge_habits(&base, &ours, &theirs), [habit("Jog", &[1, 2])]);
+    }
+
+    #[test]
+    fn settings_changed_on_one_side_are_taken() {
+        let base = [habit("Run", &[])];
+        let ours = [habit("Run", &[1])];
+        let mut theirs = [habit("Run", &[])];
+        theirs[0].frequency = Frequency::Weekly;
+        theirs[0].paused = true;
+        let merged = merge_habits(&base, &ours, &theirs);
+        assert_eq!(merged[0].frequency, Frequency::Weekly);
+        assert!(merged[0].paused && merged[0].is_completed(day(1)));
+    }
+
+    #[test]
+    fn category_colors_merge_per_category() {
+        let colors = |pairs: &[(&str, &str)]| -> CategoryColors {
+            pairs
+                .iter()
+                .map(|(c, color)| (c.to_string(), color.to_string()))
+                .collect()
+        };
+        let base = colors(&[("Health", "green"), ("Work", "blue")]);
+        let ours = colors(&[("Health", "red"), ("Work", "blue")]);
+        let theirs = colors(&[("Health", "yellow"), ("Home", "cyan")]);
+        // Our change to Health wins; their removal of Work and new Home stand.
+        assert_eq!(
+            merge_category_colors(&base, &ours, &theirs),
+            colors(&[("Health", "red"), ("Home", "cyan")])
+        );
+    }
+
+    #[test]
+    fn todos_completed_on_one_side_stay_completed() {
+        let base = [todo("Pay rent", false), todo("Buy milk", false)];
+        let ours = [todo("Pay rent", true), todo("Buy milk", false)];
+        let theirs = [todo("Pay rent", false), todo("Buy milk", true)];
+        assert_eq!(
+            merge_todos(&base, &ours, &theirs),
+            [todo("Pay rent", true), todo("Buy milk", true)]
+        );
+    }
+
+    #[test]
+    fn todos_added_on_both_sides_are_kept_once() {
+        let base = [todo("Pay rent", false)];
+        let ours = [todo("Pay rent", false), todo("Call mum", false)];
+        let theirs = [
+            todo("Pay rent", false),
+            todo("Call mum", true),
+            todo("Water plants", false),
+        ];
+        // The same todo added on both sides is a conflict our side wins.
+        assert_eq!(
+            merge_todos(&base, &ours, &theirs),
+            [
+                todo("Pay rent", false),
+                todo("Call mum", false),
+                todo("Water plants", false),
+            ]
+        );
+    }
+
+    #[test]
+    fn a_todo_deleted_on_one_side_survives_a_concurrent_change() {
+        let base = [todo("Pay rent", false), todo("Buy milk", false)];
+        // We complete Pay rent and delete Buy milk; they delete Pay rent.
+        let ours = [todo("Pay rent", true)];
+        let theirs = [todo("Buy milk", false)];
+        assert_eq!(merge_todos(&base, &ours, &theirs), [todo("Pay rent", true)]);
+    }
+}
@@ -0,0 +1,208 @@
+use crate::stats::Summary;
+use serde_json::json;
+use std::str::FromStr;
+
+pub const DEFAULT_FORMAT: &str = "{done}/{total} ✓ 🔥{streak}";
+/// The default with `icons = "ascii"`.
+pub const ASCII_FORMAT: &str = "{done}/{total} done, streak {streak}";
+
+const DONE_COLOR: &str = "#50c878";
+const OVERDUE_COLOR: &str = "#e0475b";
+
+/// How the summary is wrapped for the program showing it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Style {
+    Text,
+    /// The summary's fields plus the formatted text.
+    Json,
+    Waybar,
+    /// A block for i3bar, as printed by i3blocks or i3status-rust.
+    I3,
+    Polybar,
+    Tmux,
+}
+
+impl FromStr for Style {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Style::Text),
+            "json" => Ok(Style::Json),
+            "waybar" => Ok(Style::Waybar),
+            "i3" | "i3status" | "i3blocks" => Ok(Style::I3),
+            "polybar" => Ok(Style::Polybar),
+            "tmux" => Ok(Style::Tmux),
+            _ => Err(format!("unknown prompt style: {}", s)),
+        }
+    }
+}
+
+/// The day's state, for styling: everything done, something overdue, or
+/// neither.
+fn class(summary: &Summary) -> &'static str {
+    if summary.overdue_todos > 0 {
+        "overdue"
+    } else if summary.total > 0 && summary.done == summary.total {
+        "done"
+    } else {
+        "pending"
+    }
+}
+
+/// Fills in `{done}`, `{total}`, `{percent}`, `{streak}`, `{todos}` and
+/// `{overdue}`. `{{` and `}}` stand for literal braces.
+pub fn format(summary: &Summary, format: &str) -> Result<String, String> {
+    let mut out = String::new();
+    let mut chars = format.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                out.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                out.push('}');
+            }
+            '{' => {
+                let name: String = chars.by_ref().take_while(|c| *c != '}').collect();
+                let value = match name.as_str() {
+                    "done" => summary.done.to_string(),
+                    "total" => summary.total.to_string(),
+                    "percent" => summary.percent().to_string(),
+                    "streak" => summary.streak.to_string(),
+                    "todos" => summary.open_todos.to_string(),
+                    "overdue" => summary.overdue_todos.to_string(),
+                    _ => return Err(format!("unknown placeholder {{{}}} in prompt format", name)),
+                };
+                out.push_str(&value);
+            }
+            _ => out.push(c),
+        }
+    }
+    Ok(out)
+}
+
+/// The summary as `style` expects it, with `text` as the visible part.
+pub fn render(summary: &Summary, text: &str, style: Style) -> String {
+    let class = class(summary);
+    let color = match class {
+        "done" => Some(DONE_COLOR),
+        "overdue" => Some(OVERDUE_COLOR),
+        _ => None,
+    };
+    let tooltip = format!(
+        "Habits: {}/{} done\nLongest streak: {} days\nTodos: {} open, {} overdue",
+        summary.done, summary.total, summary.streak, summary.open_todos, summary.overdue_todos
+    );
+    match style {
+        Style::Text => text.to_string(),
+        Style::Json => {
+            let mut value = serde_json::to_value(summary).unwrap_or_default();
+            value["text"] = json!(text);
+            value["class"] = json!(class);
+            value.to_string()
+        }
+        Style::Waybar => json!({
+            "text": text,
+            "tooltip": tooltip,
+            "class": class,
+            "percentage": summary.percent(),
+        })
+        .to_string(),
+        Style::I3 => {
+            let mut block = json!({
+                "full_text": text,
+                "short_text": format!("{}/{}", summary.done, summary.total),
+            });
+            if let Some(color) = color {
+                block["color"] = json!(color);
+            }
+            block.to_string()
+        }
+        Style::Polybar => match color {
+            Some(color) => format!("%{{F{}}}{}%{{F-}}", color, text),
+            None => text.to_string(),
+        },
+        Style::Tmux => match color {
+            Some(color) => format!("#[fg={}]{}#[default]", color, text),
+            None => text.to_string(),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+    use serde_json::Value;
+
+    fn summary(done: usize, total: usize, overdue_todos: usize) -> Summary {
+        Summary {
+            date: NaiveDate::from_ymd_opt(2024, 5, 8).unwrap(),
+            done,
+            total,
+            streak: 4,
+            open_todos: 2,
+            overdue_todos,
+        }
+    }
+
+    #[test]
+    fn fills_in_placeholders_and_escaped_braces() {
+        let text = format(
+            &summary(1, 4, 1),
+            "{done}/{total} {percent}% 🔥{streak} {{todos}}: {todos} ({overdue})",
+        );
+        assert_eq!(text.unwrap(), "1/4 25% 🔥4 {todos}: 2 (1)");
+        assert_eq!(
+            format(&summary(2, 3, 0), DEFAULT_FORMAT).unwrap(),
+            "2/3 ✓ 🔥4"
+        );
+    }
+
+    #[test]
+    fn unknown_placeholders_are_an_error() {
+        let err = format(&summary(1, 4, 0), "{done} {streek}").unwrap_err();
+        assert!(err.contains("{streek}"), "{}", err);
+    }
+
+    #[test]
+    fn styles_wrap_the_text_and_color_it_by_state() {
+        let done = summary(3, 3, 0);
+        let overdue = summary(3, 3, 1);
+        let pending = summary(1, 3, 0);
+        assert_eq!(render(&pending, "1/3", Style::Text), "1/3");
+        assert_eq!(
+            render(&done, "3/3", Style::Polybar),
+            format!("%{{F{}}}3/3%{{F-}}", DONE_COLOR)
+        );
+        assert_eq!(
+            render(&overdue, "3/3", Style::Tmux),
+            format!("#[fg={}]3/3#[default]", OVERDUE_COLOR)
+        );
+        assert_eq!(render(&pending, "1/3", Style::Tmux), "1/3");
+
+        let waybar: Value = serde_json::from_str(&render(&pending, "1/3", Style::Waybar)).unwrap();
+        assert_eq!(waybar["text"], "1/3");
+        assert_eq!(waybar["class"], "pending");
+        assert_eq!(waybar["percentage"], 33);
+
+        let i3: Value = serde_json::from_str(&render(&overdue, "3/3", Style::I3)).unwrap();
+        assert_eq!(i3["short_text"], "3/3");
+        assert_eq!(i3["color"], OVERDUE_COLOR);
+
+        let json: Value = serde_json::from_str(&render(&done, "3/3", Style::Json)).unwrap();
+        assert_eq!(json["done"], 3);
+        assert_eq!(json["date"], "2024-05-08");
+        assert_eq!(json["class"], "done");
+    }
+
+    #[test]
+    fn parses_style_names() {
+        assert_eq!("i3blocks".parse(), Ok(Style::I3));
+        assert_eq!("waybar".parse(), Ok(Style::Waybar));
+        assert!("conky".parse::<Style>().is_err());
+    }
+}
@@ -0,0 +1,346 @@
+use crate::crdt::{AddWinsSet, Lww, Record, Records, Replica};
+use crate::habit::{CategoryColors, Habit};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+
+/// File in the data directory holding this device's replica and the changes
+/// not yet sent to the server.
+pub const STATE_FILE: &str = "sync_state.json";
+
+/// Syncs run while the UI starts and quits, so a server that stops
+/// answering must not hold it up for long.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// What a device remembers between syncs.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct State {
+    /// Everything this device has seen, from its own edits, the server and
+    /// merged replicas. Local edits are found by comparing against it.
+    replica: Replica,
+    /// Edits the server has not acknowledged yet, merged into one delta.
+    pending: Replica,
+}
+
+impl State {
+    fn load(path: &Path) -> io::Result<Self> {
+        if path.exists() {
+            Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+        } else {
+            Ok(State::default())
+        }
+    }
+
+    fn save(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, serde_json::to_string_pretty(self)?)
+    }
+
+    /// Folds edits made to `habits` and `colors` since the last sync into
+    /// the replica and the pending delta.
+    fn record_local_edits(&mut self, habits: &mut [Habit], colors: &CategoryColors) {
+        let now = Utc::now();
+        let changes = Replica {
+            habits: local_changes(habits, &self.replica.habits, now),
+            category_colors: color_changes(colors, &self.replica.colors(), now),
+        };
+        self.replica.merge(&changes);
+        self.pending.merge(&changes);
+    }
+}
+
+pub enum Outcome {
+    Synced,
+    /// The server could not be reached; changes to this many habits and
+    /// category colors wait for next time.
+    Offline(usize),
+}
+
+/// Sends local edits to the server at `url` and brings `habits` and `colors`
+/// up to date with everyone else's. Without a connection the edits are
+/// queued in `state_file` and sent on a later sync.
+pub fn sync(
+    habits: &mut Vec<Habit>,
+    colors: &mut CategoryColors,
+    state_file: &Path,
+    url: &str,
+    user: &str,
+) -> io::Result<Outcome> {
+    let mut state = State::load(state_file)?;
+    state.record_local_edits(habits, colors);
+    state.save(state_file)?;
+
+    let endpoint = format!("{}/users/{}/changes", url.trim_end_matches('/'), user);
+    let agent = ureq::AgentBuilder::new()
+        .timeout_connect(CONNECT_TIMEOUT)
+        .timeout_read(READ_TIMEOUT)
+        .timeout_write(READ_TIMEOUT)
+        .build();
+    let replica = match send_changes(&agent, &endpoint, &state.pending)? {
+        Some(replica) => replica,
+        None => {
+            let pending = state.pending.habits.len() + state.pending.category_colors.len();
+            return Ok(Outcome::Offline(pending));
+        }
+    };
+
+    state.replica.merge(&replica);
+    state.pending = Replica::default();
+    update_habits(habits, &state.replica.habits);
+    *colors = state.replica.colors();
+    state.save(state_file)?;
+    Ok(Outcome::Synced)
+}
+
+/// Posts `changes` and returns the server's replica, or `None` if the server
+/// could not be reached or stopped answering in time.
+fn send_changes(
+    agent: &ureq::Agent,
+    endpoint: &str,
+    changes: &Replica,
+) -> io::Result<Option<Replica>> {
+    match agent.post(endpoint).send_json(changes) {
+        Ok(response) => match response.into_json() {
+            Ok(replica) => Ok(Some(replica)),
+            // Timeouts while reading the body; the changes are sent again
+            // next time, which merging makes harmless.
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock
+                ) =>
+            {
+                Ok(None)
+            }
+            Err(e) => Err(e),
+        },
+        Err(ureq::Error::Transport(_)) => Ok(None),
+        Err(err) => Err(io::Error::other(err)),
+    }
+}
+
+/// This device's replica, including edits not synced yet, for merging into
+/// another copy of the data.
+pub fn export_replica(
+    habits: &mut [Habit],
+    colors: &CategoryColors,
+    state_file: &Path,
+) -> io::Result<Replica> {
+    let mut state = State::load(state_file)?;
+    state.record_local_edits(habits, colors);
+    state.save(state_file)?;
+    Ok(state.replica)
+}
+
+/// Merges a replica exported from another device or copy. The result is the
+/// same whichever side merges into which.
+pub fn merge_replica(
+    habits: &mut Vec<Habit>,
+    colors: &mut CategoryColors,
+    state_file: &Path,
+    other: &Replica,
+) -> io::Result<()> {
+    let mut state = State::load(state_file)?;
+    state.record_local_edits(habits, colors);
+    state.replica.merge(other);
+    // The other copy may never talk to the server itself.
+    state.pending.merge(other);
+    update_habits(habits, &state.replica.habits);
+    *colors = state.replica.colors();
+    state.save(state_file)
+}
+
+/// Compares habits with the replica. Habits that never synced get an id:
+/// that of a record with the same name and category if one is not claimed
+/// yet (the same habit set up on two devices), or a new one.
+fn local_changes(habits: &mut [Habit], replica: &Records, now: DateTime<Utc>) -> Records {
+    for index in 0..habits.len() {
+        if !habits[index].id.is_empty() {
+            continue;
+        }
+        let habit = &habits[index];
+        let existing = replica.iter().find(|(id, record)| {
+            !record.is_deleted()
+                && record.name() == habit.name
+                && record.category() == habit.category
+                && !habits.iter().any(|h| &h.id == *id)
+        });
+        habits[index].id = match existing {
+            Some((id, _)) => id.clone(),
+            None => uuid::Uuid::new_v4().to_string(),
+        };
+    }
+
+    let mut changes = Records::new();
+    for habit in habits.iter() {
+        let old = replica.get(&habit.id).cloned().unwrap_or_default();
+        let mut record = Record::default();
+        if old.name.as_ref().map(|n| &n.value) != Some(&habit.name) {
+            record.name = Some(Lww::new(habit.name.clone(), now));
+        }
+        if old.category.as_ref().map(|c| &c.value) != Some(&habit.category) {
+            record.category = Some(Lww::new(habit.category.clone(), now));
+        }
+        if old.frequency.as_ref().map(|f| f.value) != Some(habit.frequency) {
+            record.frequency = Some(Lww::new(habit.frequency, now));
+        }
+        // Unset in records from before these fields existed; that reads as
+        // the default, so only real edits are sent.
+        if old.reminders() != habit.reminders {
+            record.reminders = Some(Lww::new(habit.reminders.clone(), now));
+        }
+        if old.is_paused() != habit.paused {
+            record.paused = Some(Lww::new(habit.paused, now));
+        }
+        if old.is_deleted() {
+            // Edited here after being deleted elsewhere: bring it back.
+            record.deleted = Some(Lww::new(false, now));
+        }
+        for date in habit.completed_dates() {
+            if !old.completions.contains(date) {
+                record.completions.insert(*date);
+            }
+        }
+        for (date, check_in) in habit.check_ins() {
+            if old.check_ins.get(date).map(|c| &c.value) != Some(check_in) {
+                record
+                    .check_ins
+                    .insert(*date, Lww::new(check_in.clone(), check_in.at));
+            }
+        }
+        for date in old.completions.iter() {
+            if !habit.is_completed(*date) {
+                record
+                    .completions
+                    .merge(&AddWinsSet::remove_observed(&old.completions, date));
+            }
+        }
+        if record != Record::default() {
+            changes.insert(habit.id.clone(), record);
+        }
+    }
+    for (id, record) in replica {
+        if !record.is_deleted() && !habits.iter().any(|h| &h.id == id) {
+            let record = Record {
+                deleted: Some(Lww::new(true, now)),
+                ..Default::default()
+            };
+            changes.insert(id.clone(), record);
+        }
+    }
+    changes
+}
+
+/// Category colors set, changed or removed since `old`.
+fn color_changes(
+    colors: &CategoryColors,
+    old: &CategoryColors,
+    now: DateTime<Utc>,
+) -> BTreeMap<String, Lww<Option<String>>> {
+    let mut changes = BTreeMap::new();
+    for (category, color) in colors {
+        if old.get(category) != Some(color) {
+            changes.insert(category.clone(), Lww::new(Some(color.clone()), now));
+        }
+    }
+    for category in old.keys() {
+        if !colors.contains_key(category) {
+            changes.insert(category.clone(), Lww::new(None, now));
+        }
+    }
+    changes
+}
+
+/// Makes `habits` match the replica, keeping the local order and appending
+/// habits added elsewhere.
+fn update_habits(habits: &mut Vec<Habit>, records: &Records) {
+    habits.retain(|h| records.get(&h.id).is_some_and(|r| !r.is_deleted()));
+    for habit in habits.iter_mut() {
+        *habit = records[&habit.id].to_habit(&habit.id);
+    }
+    for (id, record) in records {
+        if !record.is_deleted() && !habits.iter().any(|h| &h.id == id) {
+            habits.push(record.to_habit(id));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::habit::Frequency;
+    use std::io::Write;
+    use std::net::TcpListener;
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::Instant;
+
+    /// A server that accepts connections, writes `reply` and then stalls
+    /// until the test is over.
+    fn stalling_server(reply: &'static str) -> (String, mpsc::Sender<()>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let (done, wait) = mpsc::channel();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            stream.write_all(reply.as_bytes()).unwrap();
+            let _ = wait.recv();
+        });
+        (url, done)
+    }
+
+    fn agent() -> ureq::Agent {
+        ureq::AgentBuilder::new()
+            .timeout_connect(Duration::from_millis(200))
+            .timeout_read(Duration::from_millis(200))
+            .build()
+    }
+
+    #[test]
+    fn a_server_that_stops_answering_counts_as_offline() {
+        for reply in [
+            "",
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: 100\r\n\r\n{",
+        ] {
+            let (url, done) = stalling_server(reply);
+            let started = Instant::now();
+            let result = send_changes(
+                &agent(),
+                &format!("{}/users/a/changes", url),
+                &Replica::default(),
+            );
+            assert_eq!(result.unwrap(), None, "{:?}", reply);
+            assert!(started.elapsed() < Duration::from_secs(5));
+            done.send(()).unwrap();
+        }
+    }
+
+    #[test]
+    fn edits_made_offline_wait_for_the_next_sync() {
+        let dir = std::env::temp_dir().join(format!("habit_tracker-remote-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let state_file = dir.join(STATE_FILE);
+        // Nothing listens on a port just released.
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let url = format!("http://127.0.0.1:{}", port);
+
+        let mut habits = vec![Habit::new("Run".into(), "Health".into(), Frequency::Daily)];
+        let mut colors = CategoryColors::new();
+        let outcome = sync(&mut habits, &mut colors, &state_file, &url, "a").unwrap();
+        assert!(matches!(outcome, Outcome::Offline(1)));
+        assert!(!habits[0].id.is_empty());
+        let state = State::load(&state_file).unwrap();
+        assert!(state.pending.habits.contains_key(&habits[0].id));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
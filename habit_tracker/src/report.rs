@@ -0,0 +1,582 @@
+use crate::habit::{week_bounds, Frequency, Habit};
+use crate::todo::Todo;
+use chrono::{Datelike, NaiveDate};
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+use std::str::FromStr;
+
+const LEADERBOARD_SIZE: usize = 10;
+const WEEKDAYS: [&str; 7] = ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Period {
+    Week,
+    Month,
+    Year,
+}
+
+impl FromStr for Period {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "week" | "weekly" => Ok(Period::Week),
+            "month" | "monthly" => Ok(Period::Month),
+            "year" | "yearly" => Ok(Period::Year),
+            _ => Err(format!("unknown report period: {}", s)),
+        }
+    }
+}
+
+impl Period {
+    /// First and last day of the period containing `date`. Weeks start on
+    /// the configured day, as in the frequency graph.
+    fn bounds(self, date: NaiveDate) -> (NaiveDate, NaiveDate) {
+        match self {
+            Period::Week => week_bounds(date),
+            Period::Month => {
+                let start = date.with_day(1).unwrap_or(date);
+                let next = start
+                    .checked_add_months(chrono::Months::new(1))
+                    .unwrap_or(start);
+                (start, next.pred_opt().unwrap_or(start))
+            }
+            Period::Year => (
+                NaiveDate::from_ymd_opt(date.year(), 1, 1).unwrap_or(date),
+                NaiveDate::from_ymd_opt(date.year(), 12, 31).unwrap_or(date),
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Markdown,
+    Html,
+}
+
+struct HabitStats<'a> {
+    habit: &'a Habit,
+    /// Days, weeks or months (per the habit's frequency) in the period so far.
+    expected: usize,
+    /// How many of those had a check-in.
+    done: usize,
+    streak: u32,
+}
+
+/// Everything a report shows, computed once and rendered in either format.
+struct Report<'a> {
+    title: String,
+    start: NaiveDate,
+    end: NaiveDate,
+    period: Period,
+    habits: Vec<HabitStats<'a>>,
+    todos: Vec<&'a Todo>,
+    notes: Vec<String>,
+}
+
+/// Builds a review of the period containing `date`. Days after `today` do
+/// not count against completion rates.
+pub fn render(
+    habits: &[Habit],
+    todos: &[Todo],
+    period: Period,
+    date: NaiveDate,
+    today: NaiveDate,
+    format: Format,
+) -> String {
+    let report = build(habits, todos, period, date, today);
+    match format {
+        Format::Markdown => markdown(&report),
+        Format::Html => html(&report),
+    }
+}
+
+fn build<'a>(
+    habits: &'a [Habit],
+    todos: &'a [Todo],
+    period: Period,
+    date: NaiveDate,
+    today: NaiveDate,
+) -> Report<'a> {
+    let (start, end) = period.bounds(date);
+    let counted_end = end.min(today);
+
+    let stats: Vec<HabitStats> = habits
+        .iter()
+        .map(|habit| {
+            let mut slots = BTreeMap::new();
+            for day in start.iter_days().take_while(|d| *d <= counted_end) {
+                let done = slots.entry(slot(habit.frequency, day)).or_insert(false);
+                *done |= habit.is_completed(day);
+            }
+            HabitStats {
+                habit,
+                expected: slots.len(),
+                done: slots.values().filter(|done| **done).count(),
+                streak: habit.get_current_streak(counted_end),
+            }
+        })
+        .collect();
+
+    let completed_todos: Vec<&Todo> = todos
+        .iter()
+        .filter(|t| t.completed && t.completed_on.is_some_and(|d| d >= start && d <= end))
+        .collect();
+
+    let title = match period {
+        Period::Week => format!("Week of {} to {}", start, end),
+        Period::Month => format!("{} {}", MONTHS[start.month0() as usize], start.year()),
+        Period::Year => format!("{}", start.year()),
+    };
+
+    let mut report = Report {
+        title,
+        start,
+        end,
+        period,
+        habits: stats,
+        todos: completed_todos,
+        notes: Vec::new(),
+    };
+    report.notes = notes(&report);
+    report
+}
+
+/// The day, week or month a check-in on `day` counts towards.
+fn slot(frequency: Frequency, day: NaiveDate) -> NaiveDate {
+    match frequency {
+        Frequency::Daily => day,
+        Frequency::Weekly => week_bounds(day).0,
+        Frequency::Monthly => day.with_day(1).unwrap_or(day),
+    }
+}
+
+fn rate(done: usize, expected: usize) -> String {
+    if expected == 0 {
+        "-".to_string()
+    } else {
+        format!("{:.0}%", done as f64 * 100.0 / expected as f64)
+    }
+}
+
+fn categories<'a>(report: &'a Report) -> BTreeMap<&'a str, (usize, usize)> {
+    let mut categories: BTreeMap<&str, (usize, usize)> = BTreeMap::new();
+    for stats in &report.habits {
+        let entry = categories.entry(&stats.habit.category).or_default();
+        entry.0 += stats.done;
+        entry.1 += stats.expected;
+    }
+    categories
+}
+
+fn leaderboard<'a>(report: &'a Report) -> Vec<&'a HabitStats<'a>> {
+    let mut board: Vec<&HabitStats> = report.habits.iter().filter(|s| s.streak > 0).collect();
+    board.sort_by_key(|s| Reverse(s.streak));
+    board.truncate(LEADERBOARD_SIZE);
+    board
+}
+
+fn days(report: &Report) -> impl Iterator<Item = NaiveDate> {
+    let end = report.end;
+    report.start.iter_days().take_while(move |d| *d <= end)
+}
+
+/// Short observations about the period.
+fn notes(report: &Report) -> Vec<String> {
+    let mut notes = Vec::new();
+    let counted: Vec<&HabitStats> = report.habits.iter().filter(|s| s.expected > 0).collect();
+
+    let perfect: Vec<&str> = counted
+        .iter()
+        .filter(|s| s.done == s.expected)
+        .map(|s| s.habit.name.as_str())
+        .collect();
+    if !perfect.is_empty() {
+        notes.push(format!("Never missed: {}", perfect.join(", ")));
+    }
+    let untouched: Vec<&str> = counted
+        .iter()
+        .filter(|s| s.done == 0)
+        .map(|s| s.habit.name.as_str())
+        .collect();
+    if !untouched.is_empty() {
+        notes.push(format!("No check-ins: {}", untouched.join(", ")));
+    }
+
+    let mut by_weekday = [0usize; 7];
+    for day in days(report) {
+        let count = report
+            .habits
+            .iter()
+            .filter(|s| s.habit.is_completed(day))
+            .count();
+        by_weekday[day.weekday().num_days_from_monday() as usize] += count;
+    }
+    if let Some((index, &count)) = by_weekday.iter().enumerate().max_by_key(|(_, c)| **c) {
+        if count > 0 {
+            notes.push(format!(
+                "Busiest weekday: {} ({} check-ins)",
+                WEEKDAYS[index], count
+            ));
+        }
+    }
+    if !report.todos.is_empty() {
+        notes.push(format!("{} todos completed", report.todos.len()));
+    }
+    notes
+}
+
+fn markdown(report: &Report) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "# Habit Report: {}\n", report.title);
+
+    out.push_str("## Completion by Category\n\n");
+    out.push_str("| Category | Done | Expected | Rate |\n|---|---:|---:|---:|\n");
+    for (category, (done, expected)) in categories(report) {
+        let _ = writeln!(
+            out,
+            "| {} | {} | {} | {} |",
+            md_escape(category),
+            done,
+            expected,
+            rate(done, expected)
+        );
+    }
+
+    out.push_str("\n## Streak Leaderboard\n\n");
+    let board = leaderboard(report);
+    if board.is_empty() {
+        out.push_str("No active streaks.\n");
+    } else {
+        out.push_str("| # | Habit | Category | Streak |\n|---:|---|---|---:|\n");
+        for (rank, stats) in board.iter().enumerate() {
+            let _ = writeln!(
+                out,
+                "| {} | {} | {} | {} |",
+                rank + 1,
+                md_escape(&stats.habit.name),
+                md_escape(&stats.habit.category),
+                stats.streak
+            );
+        }
+    }
+
+    out.push_str("\n## Heatmap\n\n");
+    if report.period == Period::Year {
+        // A column per day would be unreadable; count check-ins per month.
+        out.push_str("| Habit |");
+        for month in MONTHS {
+            let _ = write!(out, " {} |", month);
+        }
+        out.push_str("\n|---|");
+        out.push_str(&"---:|".repeat(12));
+        out.push('\n');
+        for stats in &report.habits {
+            let mut counts = [0; 12];
+            for day in days(report).filter(|d| stats.habit.is_completed(*d)) {
+                counts[day.month0() as usize] += 1;
+            }
+            let _ = write!(out, "| {} |", md_escape(&stats.habit.name));
+            for count in counts {
+                let _ = write!(out, " {} |", count);
+            }
+            out.push('\n');
+        }
+    } else {
+        out.push_str("| Habit |");
+        for day in days(report) {
+            let _ = write!(out, " {} |", day.day());
+        }
+        out.push_str("\n|---|");
+        out.push_str(&":-:|".repeat(days(report).count()));
+        out.push('\n');
+        for stats in &report.habits {
+            let _ = write!(out, "| {} |", md_escape(&stats.habit.name));
+            for day in days(report) {
+                let cell = if stats.habit.is_completed(day) {
+                    "■"
+                } else {
+                    "·"
+                };
+                let _ = write!(out, " {} |", cell);
+            }
+            out.push('\n');
+        }
+    }
+
+    out.push_str("\n## Completed Todos\n\n");
+    if report.todos.is_empty() {
+        out.push_str("None.\n");
+    }
+    for todo in &report.todos {
+        let _ = writeln!(
+            out,
+            "- [x] {} ({})",
+            md_escape(&todo.description),
+            todo.completed_on.map(|d| d.to_string()).unwrap_or_default()
+        );
+    }
+
+    out.push_str("\n## Notes\n\n");
+    for note in &report.notes {
+        let _ = writeln!(out, "- {}", md_escape(note));
+    }
+    out
+}
+
+fn html(report: &Report) -> String {
+    let mut out = String::new();
+    let _ = write!(
+        out,
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+         <title>Habit Report: {title}</title>\n<style>\n{css}</style>\n</head>\n<body>\n\
+         <h1>Habit Report: {title}</h1>\n",
+        title = html_escape(&report.title),
+        css = CSS
+    );
+
+    out.push_str("<h2>Completion by Category</h2>\n<table>\n");
+    out.push_str("<tr><th>Category</th><th>Done</th><th>Expected</th><th>Rate</th></tr>\n");
+    for (category, (done, expected)) in categories(report) {
+        let _ = writeln!(
+            out,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            html_escape(category),
+            done,
+            expected,
+            rate(done, expected)
+        );
+    }
+    out.push_str("</table>\n");
+
+    out.push_str("<h2>Streak Leaderboard</h2>\n");
+    let board = leaderboard(report);
+    if board.is_empty() {
+        out.push_str("<p>No active streaks.</p>\n");
+    } else {
+        out.push_str(
+            "<table>\n<tr><th>#</th><th>Habit</th><th>Category</th><th>Streak</th></tr>\n",
+        );
+        for (rank, stats) in board.iter().enumerate() {
+            let _ = writeln!(
+                out,
+                "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                rank + 1,
+                html_escape(&stats.habit.name),
+                html_escape(&stats.habit.category),
+                stats.streak
+            );
+        }
+        out.push_str("</table>\n");
+    }
+
+    out.push_str("<h2>Heatmap</h2>\n<table class=\"heatmap\">\n");
+    for stats in &report.habits {
+        let _ = writeln!(
+            out,
+            "<tr><td>{}</td><td>{}</td></tr>",
+            html_escape(&stats.habit.name),
+            heatmap_svg(report, stats.habit)
+        );
+    }
+    out.push_str("</table>\n");
+
+    out.push_str("<h2>Completed Todos</h2>\n");
+    if report.todos.is_empty() {
+        out.push_str("<p>None.</p>\n");
+    } else {
+        out.push_str("<ul>\n");
+        for todo in &report.todos {
+            let _ = writeln!(
+                out,
+                "<li>{} <small>{}</small></li>",
+                html_escape(&todo.description),
+                todo.completed_on.map(|d| d.to_string()).unwrap_or_default()
+            );
+        }
+        out.push_str("</ul>\n");
+    }
+
+    out.push_str("<h2>Notes</h2>\n<ul>\n");
+    for note in &report.notes {
+        let _ = writeln!(out, "<li>{}</li>", html_escape(note));
+    }
+    out.push_str("</ul>\n</body>\n</html>\n");
+    out
+}
+
+const CSS: &str = "\
+body { font-family: sans-serif; max-width: 60em; margin: 2em auto; color: #222; }
+table { border-collapse: collapse; margin-bottom: 1em; }
+th, td { padding: 0.25em 0.75em; border-bottom: 1px solid #ddd; text-align: left; }
+.heatmap td { border: none; vertical-align: middle; }
+";
+
+const CELL: i64 = 12;
+const GAP: i64 = 2;
+
+/// A calendar grid like GitHub's: one column per week, one row per weekday.
+fn heatmap_svg(report: &Report, habit: &Habit) -> String {
+    let first_week = week_bounds(report.start).0;
+    let weeks = (report.end - first_week).num_days() / 7 + 1;
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\">",
+        weeks * (CELL + GAP),
+        7 * (CELL + GAP)
+    );
+    let completed: BTreeSet<NaiveDate> = habit.completed_dates().iter().copied().collect();
+    for day in days(report) {
+        let offset = (day - first_week).num_days();
+        let color = if completed.contains(&day) {
+            "#40c463"
+        } else {
+            "#ebedf0"
+        };
+        let _ = write!(
+            svg,
+            "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" rx=\"2\" fill=\"{}\"><title>{}</title></rect>",
+            offset / 7 * (CELL + GAP),
+            offset % 7 * (CELL + GAP),
+            CELL,
+            CELL,
+            color,
+            day
+        );
+    }
+    svg.push_str("</svg>");
+    svg
+}
+
+fn html_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn md_escape(text: &str) -> String {
+    text.replace('|', "\\|")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn day(d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 5, d).unwrap()
+    }
+
+    fn habit(name: &str, category: &str, frequency: Frequency, done: &[NaiveDate]) -> Habit {
+        let mut habit = Habit::new(name.into(), category.into(), frequency);
+        for date in done {
+            habit.mark_completed(*date);
+        }
+        habit
+    }
+
+    /// Reviewed on Thursday 2024-05-09, in the week of 6 to 12 May.
+    fn habits() -> Vec<Habit> {
+        let april_29 = NaiveDate::from_ymd_opt(2024, 4, 29).unwrap();
+        vec![
+            habit("Run", "Health", Frequency::Daily, &[day(7), day(8), day(9)]),
+            habit("Swim", "Health", Frequency::Weekly, &[april_29, day(7)]),
+            habit("Walk", "Mind", Frequency::Daily, &[day(9)]),
+            habit("Read", "Mind", Frequency::Daily, &[day(6)]),
+        ]
+    }
+
+    fn weekly(habits: &[Habit], format: Format) -> String {
+        render(habits, &[], Period::Week, day(8), day(9), format)
+    }
+
+    #[test]
+    fn completion_rates_count_up_to_today() {
+        let habits = habits();
+        let report = build(&habits, &[], Period::Week, day(8), day(9));
+        let rates: Vec<(usize, usize)> =
+            report.habits.iter().map(|s| (s.done, s.expected)).collect();
+        // Daily habits expect Monday to Thursday; Swim expects one week.
+        assert_eq!(rates, [(3, 4), (1, 1), (1, 4), (1, 4)]);
+
+        let markdown = weekly(&habits, Format::Markdown);
+        assert!(
+            markdown.contains("| Health | 4 | 5 | 80% |"),
+            "{}",
+            markdown
+        );
+        assert!(markdown.contains("| Mind | 2 | 8 | 25% |"), "{}", markdown);
+        let html = weekly(&habits, Format::Html);
+        assert!(html.contains("<tr><td>Health</td><td>4</td><td>5</td><td>80%</td></tr>"));
+        // Nothing is expected of a period that has not started.
+        assert_eq!(rate(0, 0), "-");
+    }
+
+    #[test]
+    fn the_leaderboard_ranks_live_streaks_longest_first() {
+        let habits = habits();
+        let report = build(&habits, &[], Period::Week, day(8), day(9));
+        let board: Vec<(&str, u32)> = leaderboard(&report)
+            .iter()
+            .map(|s| (s.habit.name.as_str(), s.streak))
+            .collect();
+        // Read's streak ended on Monday, so it is left off.
+        assert_eq!(board, [("Run", 3), ("Swim", 2), ("Walk", 1)]);
+        assert!(weekly(&habits, Format::Markdown).contains(
+            "| 1 | Run | Health | 3 |\n| 2 | Swim | Health | 2 |\n| 3 | Walk | Mind | 1 |\n"
+        ));
+
+        let board = build(&habits[3..], &[], Period::Week, day(8), day(9));
+        assert!(leaderboard(&board).is_empty());
+        assert!(weekly(&habits[3..], Format::Html).contains("<p>No active streaks.</p>"));
+    }
+
+    #[test]
+    fn heatmaps_mark_the_days_checked() {
+        let habits = habits();
+        let markdown = weekly(&habits, Format::Markdown);
+        assert!(markdown.contains("| Habit | 6 | 7 | 8 | 9 | 10 | 11 | 12 |"));
+        assert!(
+            markdown.contains("| Run | · | ■ | ■ | ■ | · | · | · |"),
+            "{}",
+            markdown
+        );
+
+        let html = weekly(&habits, Format::Html);
+        // Seven cells per habit, green for the six check-ins in the week;
+        // Swim's check-in on 29 April falls outside it.
+        assert_eq!(html.matches("<rect ").count(), 28);
+        assert_eq!(html.matches("fill=\"#40c463\"").count(), 6);
+        // Tuesday sits in the first column, second row.
+        assert!(html.contains(
+            "<rect x=\"0\" y=\"14\" width=\"12\" height=\"12\" rx=\"2\" fill=\"#40c463\"><title>2024-05-07</title></rect>"
+        ));
+
+        // A year's heatmap counts check-ins per month.
+        let markdown = render(&habits, &[], Period::Year, day(8), day(9), Format::Markdown);
+        assert!(markdown.contains("| Swim | 0 | 0 | 0 | 1 | 1 | 0 | 0 | 0 | 0 | 0 | 0 | 0 |"));
+    }
+
+    #[test]
+    fn habit_names_are_escaped() {
+        let habits = vec![habit(
+            "<b>\"Tea\" & cake</b>",
+            "Food|Drink",
+            Frequency::Daily,
+            &[day(9)],
+        )];
+        let html = weekly(&habits, Format::Html);
+        assert!(html.contains("<td>&lt;b&gt;&quot;Tea&quot; &amp; cake&lt;/b&gt;</td>"));
+        assert!(!html.contains("<b>"));
+        let markdown = weekly(&habits, Format::Markdown);
+        assert!(
+            markdown.contains("| Food\\|Drink | 1 | 4 | 25% |"),
+            "{}",
+            markdown
+        );
+    }
+}
@@ -0,0 +1,272 @@
+use crate::crdt::Replica;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+pub const DEFAULT_ADDR: &str = "127.0.0.1:8765";
+
+/// Serves the sync protocol on `addr`, keeping each user's habits and
+/// category colors in `<dir>/<user>.json`.
+///
+/// - `GET /users/<user>/habits` returns the user's replica.
+/// - `POST /users/<user>/changes` merges the posted replica (usually just the
+///   edited fields) and returns the user's replica as it is afterwards.
+pub fn serve(addr: &str, dir: &Path) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+    let server = Server::http(addr).map_err(io::Error::other)?;
+    println!("Serving habits from {} on http://{}", dir.display(), addr);
+    for request in server.incoming_requests() {
+        if let Err(err) = handle(request, dir) {
+            eprintln!("Request failed: {}", err);
+        }
+    }
+    Ok(())
+}
+
+fn handle(mut request: Request, dir: &Path) -> io::Result<()> {
+    let url = request.url().to_string();
+    let parts: Vec<&str> = url.trim_matches('/').split('/').collect();
+    let (user, action) = match parts.as_slice() {
+        ["users", user, action] if valid_user(user) => (*user, *action),
+        _ => return request.respond(Response::from_string("not found").with_status_code(404)),
+    };
+    let path = user_file(dir, user);
+
+    let replica = match (request.method(), action) {
+        (Method::Get, "habits") => load(&path)?,
+        (Method::Post, "changes") => {
+            let mut body = String::new();
+            request.as_reader().read_to_string(&mut body)?;
+            let changes: Replica = match serde_json::from_str(&body) {
+                Ok(changes) => changes,
+                Err(err) => {
+                    let response = Response::from_string(err.to_string()).with_status_code(400);
+                    return request.respond(response);
+                }
+            };
+            let mut replica = load(&path)?;
+            replica.merge(&changes);
+            save(&path, &replica)?;
+            replica
+        }
+        _ => return request.respond(Response::from_string("not found").with_status_code(404)),
+    };
+
+    let header = Header::from_bytes("Content-Type", "application/json").expect("valid header");
+    request.respond(Response::from_string(serde_json::to_string(&replica)?).with_header(header))
+}
+
+/// User names become file names, so only plain ones are accepted.
+fn valid_user(user: &str) -> bool {
+    !user.is_empty()
+        && user
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+fn user_file(dir: &Path, user: &str) -> PathBuf {
+    dir.join(format!("{}.json", user))
+}
+
+fn load(path: &Path) -> io::Result<Replica> {
+    if path.exists() {
+        Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+    } else {
+        Ok(Replica::default())
+    }
+}
+
+fn save(path: &Path, replica: &Replica) -> io::Result<()> {
+    let tmp_path = path.with_extension("json.tmp");
+    fs::write(&tmp_path, serde_json::to_string_pretty(replica)?)?;
+    fs::rename(&tmp_path, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::habit::{CategoryColors, Frequency, Habit};
+    use crate::remote::{self, Outcome};
+    use chrono::NaiveDate;
+    use std::net::TcpListener;
+    use std::thread;
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "habit_tracker-server-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn day(d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 5, d).unwrap()
+    }
+
+    /// Serves from `dir` on `addr` in the background for the rest of the
+    /// test run.
+    fn start(addr: &str, dir: &Path) -> String {
+        fs::create_dir_all(dir).unwrap();
+        let server = Server::http(addr).unwrap();
+        let url = format!("http://{}", server.server_addr());
+        let dir = dir.to_path_buf();
+        thread::spawn(move || {
+            for request in server.incoming_requests() {
+                handle(request, &dir).unwrap();
+            }
+        });
+        url
+    }
+
+    /// One device: its habits, category colors and the file keeping its
+    /// sync state.
+    struct Client {
+        habits: Vec<Habit>,
+        colors: CategoryColors,
+        state_file: PathBuf,
+    }
+
+    impl Client {
+        fn new(dir: &Path, name: &str) -> Self {
+            Client {
+                habits: Vec::new(),
+                colors: CategoryColors::new(),
+                state_file: dir.join(format!("{}-{}", name, remote::STATE_FILE)),
+            }
+        }
+
+        fn sync(&mut self, url: &str) -> Outcome {
+            remote::sync(
+                &mut self.habits,
+                &mut self.colors,
+                &self.state_file,
+                url,
+                "me",
+            )
+            .unwrap()
+        }
+
+        fn synced(&mut self, url: &str) {
+            assert!(matches!(self.sync(url), Outcome::Synced));
+        }
+    }
+
+    #[test]
+    fn field_edits_and_completions_from_two_devices_meet() {
+        let dir = scratch_dir("two-devices");
+        let url = start("127.0.0.1:0", &dir.join("server"));
+        let (mut a, mut b) = (Client::new(&dir, "a"), Client::new(&dir, "b"));
+        a.habits
+            .push(Habit::new("Run".into(), "Health".into(), Frequency::Daily));
+        a.synced(&url);
+        b.synced(&url);
+        assert_eq!(b.habits, a.habits);
+
+        // A renames while B changes the frequency: both edits stand.
+        a.habits[0].name = "Jog".into();
+        b.habits[0].frequency = Frequency::Weekly;
+        // Each marks a different day done.
+        a.habits[0].mark_completed(day(1));
+        b.habits[0].mark_completed(day(2));
+        a.synced(&url);
+        b.synced(&url);
+        a.synced(&url);
+        assert_eq!(a.habits, b.habits);
+        let habit = &a.habits[0];
+        assert_eq!(habit.name, "Jog");
+        assert_eq!(habit.frequency, Frequency::Weekly);
+        assert_eq!(habit.completed_dates(), [day(1), day(2)]);
+
+        // The same field edited on both: the later edit wins everywhere.
+        a.habits[0].name = "Run".into();
+        a.synced(&url);
+        b.habits[0].name = "Sprint".into();
+        b.synced(&url);
+        a.synced(&url);
+        assert_eq!(a.habits[0].name, "Sprint");
+        assert_eq!(a.habits, b.habits);
+    }
+
+    #[test]
+    fn a_category_keeps_one_color_across_devices() {
+        let dir = scratch_dir("colors");
+        let url = start("127.0.0.1:0", &dir.join("server"));
+        let (mut a, mut b) = (Client::new(&dir, "a"), Client::new(&dir, "b"));
+        for name in ["Run", "Swim"] {
+            a.habits
+                .push(Habit::new(name.into(), "Health".into(), Frequency::Daily));
+        }
+        a.colors.insert("Health".into(), "green".into());
+        a.synced(&url);
+        b.synced(&url);
+        assert_eq!(b.colors, a.colors);
+
+        // Both recolor the category, B later: B's color is the category's
+        // on both devices, whichever habit either of them added meanwhile.
+        a.colors.insert("Health".into(), "blue".into());
+        a.habits
+            .push(Habit::new("Walk".into(), "Health".into(), Frequency::Daily));
+        a.synced(&url);
+        b.colors.insert("Health".into(), "red".into());
+        b.synced(&url);
+        a.synced(&url);
+        assert_eq!(b.habits.len(), 3);
+        assert_eq!(a.colors, b.colors);
+        assert_eq!(a.colors["Health"], "red");
+
+        // Removing the color syncs too.
+        a.colors.remove("Health");
+        a.synced(&url);
+        b.synced(&url);
+        assert!(b.colors.is_empty());
+    }
+
+    #[test]
+    fn edits_made_while_the_server_is_down_are_sent_later() {
+        let dir = scratch_dir("offline");
+        // A port nothing listens on any more.
+        let addr = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .to_string();
+        let mut a = Client::new(&dir, "a");
+        a.habits
+            .push(Habit::new("Run".into(), "Health".into(), Frequency::Daily));
+        a.habits[0].mark_completed(day(1));
+        assert!(matches!(
+            a.sync(&format!("http://{}", addr)),
+            Outcome::Offline(1)
+        ));
+        a.habits[0].mark_completed(day(2));
+        assert!(matches!(
+            a.sync(&format!("http://{}", addr)),
+            Outcome::Offline(1)
+        ));
+
+        let url = start(&addr, &dir.join("server"));
+        a.synced(&url);
+        let mut b = Client::new(&dir, "b");
+        b.synced(&url);
+        assert_eq!(b.habits[0].completed_dates(), [day(1), day(2)]);
+    }
+
+    #[test]
+    fn rejects_unknown_paths_and_odd_user_names() {
+        let dir = scratch_dir("paths");
+        let url = start("127.0.0.1:0", &dir);
+        let status = |path: &str| match ureq::get(&format!("{}{}", url, path)).call() {
+            Ok(response) => response.status(),
+            Err(ureq::Error::Status(status, _)) => status,
+            Err(err) => panic!("{}", err),
+        };
+        assert_eq!(status("/users/me/habits"), 200);
+        assert_eq!(status("/users/../habits"), 404);
+        assert_eq!(status("/users/me/other"), 404);
+        assert!(!dir.join("me.json").exists());
+    }
+}
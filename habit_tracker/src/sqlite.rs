@@ -0,0 +1,508 @@
+use crate::habit::{CategoryColors, CheckIn, Frequency, Habit};
+use crate::storage::{self, Storage};
+use crate::todo::Todo;
+use chrono::{DateTime, NaiveDate, NaiveTime, Utc};
+use rusqlite::{params, Connection, ErrorCode, OptionalExtension};
+use std::io;
+use std::path::{Path, PathBuf};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS habits (
+    id        INTEGER PRIMARY KEY,
+    name      TEXT NOT NULL,
+    category  TEXT NOT NULL,
+    frequency TEXT NOT NULL,
+    uid       TEXT NOT NULL DEFAULT '',
+    reminders TEXT NOT NULL DEFAULT '',
+    paused    INTEGER NOT NULL DEFAULT 0
+);
+CREATE TABLE IF NOT EXISTS check_ins (
+    habit_id INTEGER NOT NULL REFERENCES habits(id) ON DELETE CASCADE,
+    date     TEXT NOT NULL,
+    at       TEXT,
+    zone     TEXT,
+    PRIMARY KEY (habit_id, date)
+);
+CREATE TABLE IF NOT EXISTS todos (
+    id           INTEGER PRIMARY KEY,
+    description  TEXT NOT NULL,
+    completed    INTEGER NOT NULL,
+    priority     TEXT,
+    created      TEXT,
+    completed_on TEXT
+);
+CREATE TABLE IF NOT EXISTS categories (
+    name  TEXT PRIMARY KEY,
+    color TEXT NOT NULL
+);
+";
+
+/// Columns added to tables after their first release, which older
+/// databases lack.
+const ADDED_COLUMNS: [(&str, &str, &str); 5] = [
+    ("habits", "uid", "TEXT NOT NULL DEFAULT ''"),
+    ("habits", "reminders", "TEXT NOT NULL DEFAULT ''"),
+    ("habits", "paused", "INTEGER NOT NULL DEFAULT 0"),
+    ("check_ins", "at", "TEXT"),
+    ("check_ins", "zone", "TEXT"),
+];
+
+/// Stores habits, their check-ins, todos and category colors in normalized
+/// SQLite tables.
+/// Row ids keep the order the app displays things in.
+pub struct SqliteStorage {
+    conn: Connection,
+    path: PathBuf,
+}
+
+impl SqliteStorage {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let conn = Connection::open(path).map_err(sql_err)?;
+        check_integrity(&conn, path)?;
+        conn.execute_batch("PRAGMA foreign_keys = ON;")
+            .and_then(|_| conn.execute_batch(SCHEMA))
+            .map_err(sql_err)?;
+        for (table, column, definition) in ADDED_COLUMNS {
+            let exists: bool = conn
+                .query_row(
+                    "SELECT COUNT(*) FROM pragma_table_info(?1) WHERE name = ?2",
+                    [table, column],
+                    |row| row.get(0),
+                )
+                .map_err(sql_err)?;
+            if !exists {
+                conn.execute_batch(&format!(
+                    "ALTER TABLE {} ADD COLUMN {} {}",
+                    table, column, definition
+                ))
+                .map_err(sql_err)?;
+            }
+        }
+        Ok(SqliteStorage {
+            conn,
+            path: path.to_path_buf(),
+        })
+    }
+}
+
+impl Storage for SqliteStorage {
+    fn load_habits(&self) -> io::Result<Vec<Habit>> {
+        let mut habit_stmt = self
+            .conn
+            .prepare(
+                "SELECT id, name, category, frequency, uid, reminders, paused
+                 FROM habits ORDER BY id",
+            )
+            .map_err(sql_err)?;
+        let mut date_stmt = self
+            .conn
+            .prepare("SELECT date, at, zone FROM check_ins WHERE habit_id = ?1 ORDER BY date")
+            .map_err(sql_err)?;
+
+        let rows = habit_stmt
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, String>(3)?,
+                    row.get::<_, String>(4)?,
+                    row.get::<_, String>(5)?,
+                    row.get::<_, bool>(6)?,
+                ))
+            })
+            .map_err(sql_err)?;
+
+        let mut habits = Vec::new();
+        for row in rows {
+            let (id, name, category, frequency, uid, reminders, paused) = row.map_err(sql_err)?;
+            let frequency: Frequency = frequency
+                .parse()
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            let mut habit = Habit::new(name, category, frequency);
+            habit.id = uid;
+            // Times of day, as `HH:MM` separated by commas.
+            habit.reminders = reminders
+                .split(',')
+                .filter(|time| !time.is_empty())
+                .map(|time| NaiveTime::parse_from_str(time, "%H:%M"))
+                .collect::<Result<_, _>>()
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            habit.paused = paused;
+            let dates = date_stmt
+                .query_map([id], |row| {
+                    Ok((
+                        row.get::<_, NaiveDate>(0)?,
+                        row.get::<_, Option<DateTime<Utc>>>(1)?,
+                        row.get::<_, Option<String>>(2)?,
+                    ))
+                })
+                .map_err(sql_err)?;
+            for row in dates {
+                match row.map_err(sql_err)? {
+                    (date, Some(at), Some(zone)) => habit.check_in(date, CheckIn { at, zone }),
+                    (date, _, _) => habit.mark_completed(date),
+                }
+            }
+            habits.push(habit);
+        }
+        Ok(habits)
+    }
+
+    fn save_habits(&mut self, habits: &[Habit]) -> io::Result<()> {
+        let tx = self.conn.transaction().map_err(sql_err)?;
+        tx.execute_batch("DELETE FROM check_ins; DELETE FROM habits;")
+            .map_err(sql_err)?;
+        for habit in habits {
+            insert_habit(&tx, habit).map_err(sql_err)?;
+        }
+        tx.commit().map_err(sql_err)
+    }
+
+    fn load_todos(&self) -> io::Result<Vec<Todo>> {
+        let mut stmt = self
+            .conn
+            .prepare(
+                "SELECT description, completed, priority, created, completed_on
+                 FROM todos ORDER BY id",
+            )
+            .map_err(sql_err)?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok(Todo {
+                    description: row.get(0)?,
+                    completed: row.get(1)?,
+                    priority: row
+                        .get::<_, Option<String>>(2)?
+                        .and_then(|p| p.chars().next()),
+                    created: row.get(3)?,
+                    completed_on: row.get(4)?,
+                })
+            })
+            .map_err(sql_err)?;
+        rows.collect::<Result<_, _>>().map_err(sql_err)
+    }
+
+    fn save_todos(&mut self, todos: &[Todo]) -> io::Result<()> {
+        let tx = self.conn.transaction().map_err(sql_err)?;
+        tx.execute("DELETE FROM todos", []).map_err(sql_err)?;
+        {
+            let mut stmt = tx
+                .prepare(
+                    "INSERT INTO todos (description, completed, priority, created, completed_on)
+                     VALUES (?1, ?2, ?3, ?4, ?5)",
+                )
+                .map_err(sql_err)?;
+            for todo in todos {
+                stmt.execute(params![
+                    todo.description,
+                    todo.completed,
+                    todo.priority.map(String::from),
+                    todo.created,
+                    todo.completed_on,
+                ])
+                .map_err(sql_err)?;
+            }
+        }
+        tx.commit().map_err(sql_err)
+    }
+
+    fn load_category_colors(&self) -> io::Result<CategoryColors> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT name, color FROM categories")
+            .map_err(sql_err)?;
+        let rows = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .map_err(sql_err)?;
+        rows.collect::<Result<_, _>>().map_err(sql_err)
+    }
+
+    fn save_category_colors(&mut self, colors: &CategoryColors) -> io::Result<()> {
+        let tx = self.conn.transaction().map_err(sql_err)?;
+        tx.execute("DELETE FROM categories", []).map_err(sql_err)?;
+        {
+            let mut stmt = tx
+                .prepare("INSERT INTO categories (name, color) VALUES (?1, ?2)")
+                .map_err(sql_err)?;
+            for (name, color) in colors {
+                stmt.execute(params![name, color]).map_err(sql_err)?;
+            }
+        }
+        tx.commit().map_err(sql_err)
+    }
+
+    fn watched_files(&self) -> Vec<PathBuf> {
+        vec![self.path.clone()]
+    }
+
+    /// Updates the one row; a habit not saved yet is inserted whole.
+    fn record_check_in(&mut self, habit: &Habit, date: NaiveDate) -> io::Result<bool> {
+        let id: Option<i64> = self
+            .conn
+            .query_row(
+                "SELECT id FROM habits WHERE name = ?1 AND category = ?2 ORDER BY id LIMIT 1",
+                params![habit.name, habit.category],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(sql_err)?;
+        let result = match id {
+            None => insert_habit(&self.conn, habit).map(|_| ()),
+            Some(id) if habit.is_completed(date) => {
+                let check_in = habit.check_in_on(date);
+                self.conn
+                    .execute(
+                        "INSERT OR REPLACE INTO check_ins (habit_id, date, at, zone)
+                         VALUES (?1, ?2, ?3, ?4)",
+                        params![id, date, check_in.map(|c| c.at), check_in.map(|c| &c.zone)],
+                    )
+                    .map(|_| ())
+            }
+            Some(id) => self
+                .conn
+                .execute(
+                    "DELETE FROM check_ins WHERE habit_id = ?1 AND date = ?2",
+                    params![id, date],
+                )
+                .map(|_| ()),
+        };
+        result.map_err(sql_err)?;
+        Ok(true)
+    }
+}
+
+/// Adds `habit` and its check-ins after the existing habits, returning its
+/// row id.
+fn insert_habit(conn: &Connection, habit: &Habit) -> rusqlite::Result<i64> {
+    let id = conn
+        .prepare_cached(
+            "INSERT INTO habits
+                 (name, category, frequency, uid, reminders, paused)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        )?
+        .insert(params![
+            habit.name,
+            habit.category,
+            habit.frequency.to_string(),
+            habit.id,
+            habit
+                .reminders
+                .iter()
+                .map(|time| time.format("%H:%M").to_string())
+                .collect::<Vec<_>>()
+                .join(","),
+            habit.paused,
+        ])?;
+    let mut date_stmt = conn.prepare_cached(
+        "INSERT INTO check_ins (habit_id, date, at, zone) VALUES (?1, ?2, ?3, ?4)",
+    )?;
+    for date in habit.completed_dates() {
+        let check_in = habit.check_in_on(*date);
+        date_stmt.execute(params![
+            id,
+            date,
+            check_in.map(|c| c.at),
+            check_in.map(|c| &c.zone),
+        ])?;
+    }
+    Ok(id)
+}
+
+/// Fails with a `CorruptFile` error if the file is damaged or not a
+/// database at all, before anything is read from or written to it.
+fn check_integrity(conn: &Connection, path: &Path) -> io::Result<()> {
+    let reason = match conn.query_row("PRAGMA quick_check", [], |row| row.get::<_, String>(0)) {
+        Ok(result) if result == "ok" => return Ok(()),
+        Ok(result) => result,
+        Err(rusqlite::Error::SqliteFailure(e, message))
+            if matches!(e.code, ErrorCode::NotADatabase | ErrorCode::DatabaseCorrupt) =>
+        {
+            message.unwrap_or_else(|| e.to_string())
+        }
+        Err(e) => return Err(sql_err(e)),
+    };
+    Err(storage::corrupt(path, reason))
+}
+
+fn sql_err(e: rusqlite::Error) -> io::Error {
+    io::Error::other(e)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::JsonStorage;
+    use std::fs;
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "habit_tracker-sqlite-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn day(d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 5, d).unwrap()
+    }
+
+    fn sample_habits() -> Vec<Habit> {
+        let mut run = Habit::new("Run".into(), "Health".into(), Frequency::Daily);
+        run.id = "0b6f".into();
+        run.mark_completed(day(1));
+        run.check_in(
+            day(2),
+            CheckIn {
+                at: "2024-05-02T06:30:00Z".parse().unwrap(),
+                zone: "Europe/Berlin".into(),
+            },
+        );
+        run.reminders = vec![NaiveTime::from_hms_opt(7, 30, 0).unwrap()];
+        let mut swim = Habit::new("Swim".into(), "Health".into(), Frequency::Weekly);
+        swim.paused = true;
+        vec![
+            run,
+            swim,
+            Habit::new("Budget".into(), "Money".into(), Frequency::Monthly),
+        ]
+    }
+
+    fn sample_todos() -> Vec<Todo> {
+        vec![
+            Todo {
+                description: "Pay rent due:2024-05-31".into(),
+                priority: Some('A'),
+                created: Some(day(1)),
+                ..Todo::default()
+            },
+            Todo {
+                description: "Call mum".into(),
+                completed: true,
+                completed_on: Some(day(3)),
+                ..Todo::default()
+            },
+        ]
+    }
+
+    fn sample_colors() -> CategoryColors {
+        CategoryColors::from([
+            ("Health".to_string(), "#50c878".to_string()),
+            ("Money".to_string(), "yellow".to_string()),
+        ])
+    }
+
+    #[test]
+    fn saves_and_loads_everything_in_order() {
+        let dir = scratch_dir("round-trip");
+        let mut storage = SqliteStorage::open(&dir.join("habits.db")).unwrap();
+        assert!(storage.load_habits().unwrap().is_empty());
+        storage.save_habits(&sample_habits()).unwrap();
+        storage.save_todos(&sample_todos()).unwrap();
+        storage.save_category_colors(&sample_colors()).unwrap();
+        // Reopened, as by the next session.
+        let mut storage = SqliteStorage::open(&dir.join("habits.db")).unwrap();
+        assert_eq!(storage.load_habits().unwrap(), sample_habits());
+        assert_eq!(storage.load_todos().unwrap(), sample_todos());
+        assert_eq!(storage.load_category_colors().unwrap(), sample_colors());
+
+        storage
+            .save_category_colors(&CategoryColors::new())
+            .unwrap();
+        assert!(storage.load_category_colors().unwrap().is_empty());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn check_ins_update_single_rows() {
+        let dir = scratch_dir("check-in");
+        let mut storage = SqliteStorage::open(&dir.join("habits.db")).unwrap();
+        let mut habits = sample_habits();
+        storage.save_habits(&habits).unwrap();
+
+        habits[1].mark_completed(day(4));
+        assert!(storage.record_check_in(&habits[1], day(4)).unwrap());
+        habits[0].unmark_completed(day(2));
+        assert!(storage.record_check_in(&habits[0], day(2)).unwrap());
+        assert_eq!(storage.load_habits().unwrap(), habits);
+
+        // A habit added since the last save is stored along with it.
+        let mut read = Habit::new("Read".into(), "Mind".into(), Frequency::Daily);
+        read.mark_completed(day(1));
+        read.mark_completed(day(5));
+        habits.push(read);
+        assert!(storage.record_check_in(&habits[3], day(5)).unwrap());
+        assert_eq!(storage.load_habits().unwrap(), habits);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn adds_columns_missing_from_older_databases() {
+        let dir = scratch_dir("migrate");
+        let path = dir.join("habits.db");
+        let conn = Connection::open(&path).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE habits (id INTEGER PRIMARY KEY, name TEXT NOT NULL,
+                 category TEXT NOT NULL, frequency TEXT NOT NULL);
+             CREATE TABLE check_ins (habit_id INTEGER NOT NULL, date TEXT NOT NULL,
+                 PRIMARY KEY (habit_id, date));
+             INSERT INTO habits VALUES (1, 'Run', 'Health', 'Daily');
+             INSERT INTO check_ins VALUES (1, '2024-05-01');",
+        )
+        .unwrap();
+        drop(conn);
+
+        let mut storage = SqliteStorage::open(&path).unwrap();
+        let habits = storage.load_habits().unwrap();
+        let mut run = Habit::new("Run".into(), "Health".into(), Frequency::Daily);
+        run.mark_completed(day(1));
+        assert_eq!(habits, [run]);
+        storage.save_habits(&sample_habits()).unwrap();
+        assert_eq!(storage.load_habits().unwrap(), sample_habits());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn damaged_databases_are_reported_as_unreadable() {
+        let dir = scratch_dir("corrupt");
+        let path = dir.join("habits.db");
+        fs::write(&path, "not a database, though long enough to have a header").unwrap();
+        let err = SqliteStorage::open(&path).err().unwrap();
+        assert_eq!(storage::corrupt_file(&err).unwrap().path, path);
+
+        // Once moved aside, a new database starts in its place.
+        storage::quarantine(&path).unwrap();
+        assert!(SqliteStorage::open(&path)
+            .unwrap()
+            .load_habits()
+            .unwrap()
+            .is_empty());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn holds_the_same_data_as_the_json_files() {
+        let dir = scratch_dir("from-json");
+        let mut json = JsonStorage::new(dir.join("habits.json"), dir.join("todos.json"));
+        json.save_habits(&sample_habits()).unwrap();
+        json.save_todos(&sample_todos()).unwrap();
+        json.save_category_colors(&sample_colors()).unwrap();
+
+        let mut sqlite = SqliteStorage::open(&dir.join("habits.db")).unwrap();
+        sqlite.save_habits(&json.load_habits().unwrap()).unwrap();
+        sqlite.save_todos(&json.load_todos().unwrap()).unwrap();
+        sqlite
+            .save_category_colors(&json.load_category_colors().unwrap())
+            .unwrap();
+        assert_eq!(sqlite.load_habits().unwrap(), json.load_habits().unwrap());
+        assert_eq!(sqlite.load_todos().unwrap(), json.load_todos().unwrap());
+        assert_eq!(
+            sqlite.load_category_colors().unwrap(),
+            json.load_category_colors().unwrap()
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,130 @@
+use crate::habit::Habit;
+use crate::todo::Todo;
+use chrono::NaiveDate;
+use serde::Serialize;
+
+/// How the day is going, in the terms status bars and the API show it.
+#[derive(Debug, Serialize)]
+pub struct Summary {
+    pub date: NaiveDate,
+    /// Habits done in their day, week or month containing `date`. Paused
+    /// habits are left out of this, `total` and `streak`.
+    pub done: usize,
+    pub total: usize,
+    /// The longest streak still alive on `date`.
+    pub streak: u32,
+    pub open_todos: usize,
+    /// Open todos whose due date has passed.
+    pub overdue_todos: usize,
+}
+
+impl Summary {
+    /// Share of habits done, rounded down; 0 without habits.
+    pub fn percent(&self) -> usize {
+        (self.done * 100).checked_div(self.total).unwrap_or(0)
+    }
+}
+
+pub fn summarize(habits: &[Habit], todos: &[Todo], date: NaiveDate) -> Summary {
+    let active: Vec<&Habit> = habits.iter().filter(|h| !h.paused).collect();
+    let open: Vec<&Todo> = todos.iter().filter(|t| !t.completed).collect();
+    Summary {
+        date,
+        done: active.iter().filter(|h| h.is_done_in_period(date)).count(),
+        total: active.len(),
+        streak: active
+            .iter()
+            .map(|h| live_streak(h, date))
+            .max()
+            .unwrap_or(0),
+        open_todos: open.len(),
+        overdue_todos: open
+            .iter()
+            .filter(|t| t.due_date().is_some_and(|due| due < date))
+            .count(),
+    }
+}
+
+/// The current streak, counting one that ended yesterday as still alive
+/// while today has not been checked yet.
+pub fn live_streak(habit: &Habit, date: NaiveDate) -> u32 {
+    let yesterday = date.pred_opt().unwrap_or(date);
+    habit
+        .get_current_streak(date)
+        .max(habit.get_current_streak(yesterday))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::habit::Frequency;
+
+    fn day(d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 5, d).unwrap()
+    }
+
+    fn habit(name: &str, frequency: Frequency, done: &[u32]) -> Habit {
+        let mut habit = Habit::new(name.to_string(), "Health".to_string(), frequency);
+        for d in done {
+            habit.mark_completed(day(*d));
+        }
+        habit
+    }
+
+    fn todo(description: &str, completed: bool) -> Todo {
+        Todo {
+            description: description.to_string(),
+            completed,
+            ..Todo::default()
+        }
+    }
+
+    #[test]
+    fn weekly_and_monthly_habits_count_as_done_for_their_whole_period() {
+        // 2024-05-08 is a Wednesday; weeks start on Monday by default.
+        let habits = vec![
+            habit("Run", Frequency::Daily, &[7]),
+            habit("Swim", Frequency::Weekly, &[6]),
+            habit("Budget", Frequency::Monthly, &[1]),
+        ];
+        let summary = summarize(&habits, &[], day(8));
+        assert_eq!((summary.done, summary.total), (2, 3));
+        assert_eq!(summary.percent(), 66);
+    }
+
+    #[test]
+    fn a_streak_ending_yesterday_is_still_alive() {
+        let habits = vec![
+            habit("Run", Frequency::Daily, &[5, 6, 7]),
+            habit("Read", Frequency::Daily, &[1, 2, 3, 4]),
+        ];
+        assert_eq!(summarize(&habits, &[], day(8)).streak, 3);
+        assert_eq!(summarize(&habits, &[], day(9)).streak, 0);
+    }
+
+    #[test]
+    fn paused_habits_are_left_out() {
+        let mut habits = vec![
+            habit("Run", Frequency::Daily, &[8]),
+            habit("Read", Frequency::Daily, &[1, 2, 3, 4, 5, 6, 7]),
+        ];
+        habits[1].paused = true;
+        let summary = summarize(&habits, &[], day(8));
+        assert_eq!((summary.done, summary.total), (1, 1));
+        assert_eq!(summary.percent(), 100);
+        assert_eq!(summary.streak, 1);
+    }
+
+    #[test]
+    fn counts_open_and_overdue_todos() {
+        let todos = vec![
+            todo("Pay rent due:2024-05-01", false),
+            todo("Call mum due:2024-05-08", false),
+            todo("File taxes due:2024-04-30", true),
+            todo("Buy milk", false),
+        ];
+        let summary = summarize(&[], &todos, day(8));
+        assert_eq!((summary.open_todos, summary.overdue_todos), (3, 1));
+        assert_eq!(summary.percent(), 0);
+    }
+}
@@ -1,50 +1,715 @@
-use crate::habit::Habit;
-use crate::todo::Todo;
-use serde_json;
-use std::fs::{File, OpenOptions};
+use crate::config::{Backend, Config};
+use crate::crypto::{self, Cipher};
+use crate::eventlog::EventLogStorage;
+use crate::habit::{CategoryColors, Habit};
+use crate::sqlite::SqliteStorage;
+use crate::todo::{self, Todo};
+use chrono::NaiveDate;
+use serde::de::DeserializeOwned;
+use serde::ser::SerializeMap;
+use serde::{Serialize, Serializer};
+use serde_json::{Map, Value};
+use std::error::Error;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
 use std::io::{self, Read, Write};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
-const HABITS_FILE: &str = "habits.json";
-const TODOS_FILE: &str = "todos.json";
+pub const HABITS_FILE: &str = "habits.json";
+pub const TODOS_FILE: &str = "todos.json";
+pub const CATEGORIES_FILE: &str = "categories.json";
+const SQLITE_FILE: &str = "habits.db";
+const HABITS_KEY: &str = "habits";
+const TODOS_KEY: &str = "todos";
+const CATEGORIES_KEY: &str = "category_colors";
 
-pub fn load_habits() -> io::Result<Vec<Habit>> {
-    load_data(HABITS_FILE)
+/// A place habits, todos and category colors are persisted to.
+pub trait Storage {
+    fn load_habits(&self) -> io::Result<Vec<Habit>>;
+    fn save_habits(&mut self, habits: &[Habit]) -> io::Result<()>;
+    fn load_todos(&self) -> io::Result<Vec<Todo>>;
+    fn save_todos(&mut self, todos: &[Todo]) -> io::Result<()>;
+    fn load_category_colors(&self) -> io::Result<CategoryColors>;
+    fn save_category_colors(&mut self, colors: &CategoryColors) -> io::Result<()>;
+
+    /// Files whose modification means the stored data changed.
+    fn watched_files(&self) -> Vec<PathBuf>;
+
+    /// Renames a habit, identified by name and category. Backends storing
+    /// whole lists pick renames up in `save_habits`; the event log records
+    /// them here, as its diff cannot tell a rename from a removal and an
+    /// addition.
+    fn record_rename(
+        &mut self,
+        _name: &str,
+        _category: &str,
+        _new_name: &str,
+        _new_category: &str,
+    ) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Stores whether `habit` was done on `date`, and its check-in, without
+    /// writing anything else. Returns false if the backend leaves that to
+    /// the next `save_habits`, as backends storing whole lists do.
+    fn record_check_in(&mut self, _habit: &Habit, _date: NaiveDate) -> io::Result<bool> {
+        Ok(false)
+    }
+}
+
+/// Opens the backend selected in the config.
+pub fn open(config: &Config) -> io::Result<Box<dyn Storage>> {
+    if config.todotxt_file.is_some() && crypto::is_enabled(Path::new(".")) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "todos can't be kept in a todo.txt file while the data is encrypted",
+        ));
+    }
+    let storage = open_backend(config.backend)?;
+    Ok(match &config.todotxt_file {
+        Some(path) => Box::new(TodoTxtStorage {
+            inner: storage,
+            path: path.clone(),
+        }),
+        None => storage,
+    })
+}
+
+/// Opens a backend at its default location, ignoring the todo.txt setting.
+pub fn open_backend(backend: Backend) -> io::Result<Box<dyn Storage>> {
+    Ok(match backend {
+        Backend::Json => {
+            let mut storage = JsonStorage::new(HABITS_FILE, TODOS_FILE);
+            storage.cipher = crypto::unlock(Path::new("."))?;
+            Box::new(storage)
+        }
+        Backend::Sqlite => Box::new(SqliteStorage::open(Path::new(SQLITE_FILE))?),
+        Backend::Log => Box::new(EventLogStorage::new(Path::new("."))),
+    })
 }
 
-pub fn save_habits(habits: &[Habit]) -> io::Result<()> {
-    save_data(HABITS_FILE, habits)
+/// Keeps each kind of data in its own JSON file; category colors go in
+/// `categories.json` next to the habits file.
+pub struct JsonStorage {
+    habits_file: PathBuf,
+    todos_file: PathBuf,
+    categories_file: PathBuf,
+    // Each file is backed up once per session, before its first save.
+    habits_backed_up: bool,
+    todos_backed_up: bool,
+    categories_backed_up: bool,
+    /// Set when the data directory is encrypted.
+    cipher: Option<Cipher>,
 }
 
-pub fn load_todos() -> io::Result<Vec<Todo>> {
-    load_data(TODOS_FILE)
+impl JsonStorage {
+    pub fn new(habits_file: impl Into<PathBuf>, todos_file: impl Into<PathBuf>) -> Self {
+        let habits_file = habits_file.into();
+        JsonStorage {
+            categories_file: habits_file.with_file_name(CATEGORIES_FILE),
+            habits_file,
+            todos_file: todos_file.into(),
+            habits_backed_up: false,
+            todos_backed_up: false,
+            categories_backed_up: false,
+            cipher: None,
+        }
+    }
+}
+
+impl Storage for JsonStorage {
+    fn load_habits(&self) -> io::Result<Vec<Habit>> {
+        load_data(&self.habits_file, HABITS_KEY, self.cipher.as_ref())
+    }
+
+    fn save_habits(&mut self, habits: &[Habit]) -> io::Result<()> {
+        if !self.habits_backed_up {
+            backup(&self.habits_file)?;
+            self.habits_backed_up = true;
+        }
+        save_data(&self.habits_file, HABITS_KEY, habits, self.cipher.as_ref())
+    }
+
+    fn load_todos(&self) -> io::Result<Vec<Todo>> {
+        load_data(&self.todos_file, TODOS_KEY, self.cipher.as_ref())
+    }
+
+    fn save_todos(&mut self, todos: &[Todo]) -> io::Result<()> {
+        if !self.todos_backed_up {
+            backup(&self.todos_file)?;
+            self.todos_backed_up = true;
+        }
+        save_data(&self.todos_file, TODOS_KEY, todos, self.cipher.as_ref())
+    }
+
+    fn load_category_colors(&self) -> io::Result<CategoryColors> {
+        load_data(&self.categories_file, CATEGORIES_KEY, self.cipher.as_ref())
+    }
+
+    fn save_category_colors(&mut self, colors: &CategoryColors) -> io::Result<()> {
+        if !self.categories_backed_up {
+            backup(&self.categories_file)?;
+            self.categories_backed_up = true;
+        }
+        save_data(
+            &self.categories_file,
+            CATEGORIES_KEY,
+            colors,
+            self.cipher.as_ref(),
+        )
+    }
+
+    fn watched_files(&self) -> Vec<PathBuf> {
+        vec![
+            self.habits_file.clone(),
+            self.todos_file.clone(),
+            self.categories_file.clone(),
+        ]
+    }
 }
 
-pub fn save_todos(todos: &[Todo]) -> io::Result<()> {
-    save_data(TODOS_FILE, todos)
+/// Keeps todos in a todo.txt file while habits stay in `inner`.
+struct TodoTxtStorage {
+    inner: Box<dyn Storage>,
+    path: PathBuf,
 }
 
-fn load_data<T: serde::de::DeserializeOwned>(file_name: &str) -> io::Result<Vec<T>> {
-    let path = Path::new(file_name);
+impl Storage for TodoTxtStorage {
+    fn load_habits(&self) -> io::Result<Vec<Habit>> {
+        self.inner.load_habits()
+    }
+
+    fn save_habits(&mut self, habits: &[Habit]) -> io::Result<()> {
+        self.inner.save_habits(habits)
+    }
+
+    fn load_todos(&self) -> io::Result<Vec<Todo>> {
+        load_todotxt(&self.path)
+    }
+
+    fn save_todos(&mut self, todos: &[Todo]) -> io::Result<()> {
+        save_todotxt(&self.path, todos)
+    }
+
+    fn load_category_colors(&self) -> io::Result<CategoryColors> {
+        self.inner.load_category_colors()
+    }
+
+    fn save_category_colors(&mut self, colors: &CategoryColors) -> io::Result<()> {
+        self.inner.save_category_colors(colors)
+    }
+
+    fn watched_files(&self) -> Vec<PathBuf> {
+        let mut files = self.inner.watched_files();
+        files.push(self.path.clone());
+        files
+    }
+
+    fn record_rename(
+        &mut self,
+        name: &str,
+        category: &str,
+        new_name: &str,
+        new_category: &str,
+    ) -> io::Result<()> {
+        self.inner
+            .record_rename(name, category, new_name, new_category)
+    }
+
+    fn record_check_in(&mut self, habit: &Habit, date: NaiveDate) -> io::Result<bool> {
+        self.inner.record_check_in(habit, date)
+    }
+}
+
+/// Reads a habits file directly, without going through a backend.
+pub fn load_habits_file(path: &Path, cipher: Option<&Cipher>) -> io::Result<Vec<Habit>> {
+    load_data(path, HABITS_KEY, cipher)
+}
+
+pub fn save_habits_file(path: &Path, habits: &[Habit], cipher: Option<&Cipher>) -> io::Result<()> {
+    save_data(path, HABITS_KEY, habits, cipher)
+}
+
+/// Reads a todos file directly, without going through a backend.
+pub fn load_todos_file(path: &Path, cipher: Option<&Cipher>) -> io::Result<Vec<Todo>> {
+    load_data(path, TODOS_KEY, cipher)
+}
+
+pub fn save_todos_file(path: &Path, todos: &[Todo], cipher: Option<&Cipher>) -> io::Result<()> {
+    save_data(path, TODOS_KEY, todos, cipher)
+}
+
+/// Reads a category colors file directly, without going through a backend.
+pub fn load_category_colors_file(
+    path: &Path,
+    cipher: Option<&Cipher>,
+) -> io::Result<CategoryColors> {
+    load_data(path, CATEGORIES_KEY, cipher)
+}
+
+pub fn save_category_colors_file(
+    path: &Path,
+    colors: &CategoryColors,
+    cipher: Option<&Cipher>,
+) -> io::Result<()> {
+    save_data(path, CATEGORIES_KEY, colors, cipher)
+}
+
+/// Re-writes the JSON data files and their backups, decrypting them with
+/// `from` and encrypting them with `to`. Returns how many files were
+/// converted.
+pub fn convert_encryption(from: Option<&Cipher>, to: Option<&Cipher>) -> io::Result<usize> {
+    let mut files = Vec::new();
+    for name in [HABITS_FILE, TODOS_FILE, CATEGORIES_FILE] {
+        let path = PathBuf::from(name);
+        files.extend(list_backups(&path)?);
+        if path.exists() {
+            files.push(path);
+        }
+    }
+    for path in &files {
+        let data = fs::read(path)?;
+        let plain = match from {
+            Some(cipher) => cipher.decrypt_or_plain(&data)?,
+            None if crypto::is_encrypted(&data) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{} is already encrypted", path.display()),
+                ))
+            }
+            None => data,
+        };
+        let out = match to {
+            Some(cipher) => cipher.encrypt(&plain),
+            None => plain,
+        };
+        let tmp_path = with_suffix(path, ".tmp");
+        fs::write(&tmp_path, out)?;
+        fs::rename(&tmp_path, path)?;
+    }
+    Ok(files.len())
+}
+
+fn load_todotxt(path: &Path) -> io::Result<Vec<Todo>> {
     if path.exists() {
-        let mut file = File::open(path)?;
-        let mut contents = String::new();
-        file.read_to_string(&mut contents)?;
-        Ok(serde_json::from_str(&contents)?)
+        Ok(todo::parse_todotxt(&fs::read_to_string(path)?))
     } else {
         Ok(Vec::new())
     }
 }
 
-fn save_data<T: serde::Serialize>(file_name: &str, data: &[T]) -> io::Result<()> {
+fn save_todotxt(path: &Path, todos: &[Todo]) -> io::Result<()> {
+    fs::write(path, todo::to_todotxt(todos))
+}
+
+/// Version written into the `version` field of the JSON data files.
+/// Files without an envelope (a bare array) are version 0.
+pub const CURRENT_VERSION: u64 = 1;
+
+/// `MIGRATIONS[n]` upgrades a document from version `n` to `n + 1`.
+/// Each step receives the whole envelope and the key its records live under.
+const MIGRATIONS: &[fn(Value, &str) -> Value] = &[migrate_v0_to_v1];
+
+fn migrate_v0_to_v1(value: Value, key: &str) -> Value {
+    let mut envelope = Map::new();
+    envelope.insert("version".to_string(), Value::from(1));
+    envelope.insert(key.to_string(), value);
+    Value::Object(envelope)
+}
+
+/// Brings a parsed data file up to `CURRENT_VERSION`.
+fn migrate(mut value: Value, key: &str) -> io::Result<Value> {
+    let mut version = match &value {
+        Value::Array(_) => 0,
+        Value::Object(map) => map.get("version").and_then(Value::as_u64).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidData, "missing \"version\" field")
+        })?,
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "expected an array or an object",
+            ))
+        }
+    };
+    if version > CURRENT_VERSION {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!(
+                "data version {} is newer than this build supports ({})",
+                version, CURRENT_VERSION
+            ),
+        ));
+    }
+    while version < CURRENT_VERSION {
+        value = MIGRATIONS[version as usize](value, key);
+        version += 1;
+    }
+    Ok(value)
+}
+
+/// The on-disk shape of a data file: `{ "version": N, "<key>": [...] }`, or
+/// an object of category colors under the key instead of a list.
+struct Envelope<'a, T: ?Sized> {
+    key: &'a str,
+    data: &'a T,
+}
+
+impl<T: Serialize + ?Sized> Serialize for Envelope<'_, T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(2))?;
+        map.serialize_entry("version", &CURRENT_VERSION)?;
+        map.serialize_entry(self.key, self.data)?;
+        map.end()
+    }
+}
+
+fn parse_data<T: DeserializeOwned + Default>(contents: &str, key: &str) -> io::Result<T> {
+    let value = migrate(serde_json::from_str(contents)?, key)?;
+    match value.get(key) {
+        Some(records) => Ok(T::deserialize(records)?),
+        None => Ok(T::default()),
+    }
+}
+
+fn load_data<T: DeserializeOwned + Default>(
+    path: &Path,
+    key: &str,
+    cipher: Option<&Cipher>,
+) -> io::Result<T> {
+    if path.exists() {
+        let mut file = File::open(path)?;
+        let mut data = Vec::new();
+        file.read_to_end(&mut data)?;
+        decode(&data, cipher)
+            .and_then(|contents| parse_data(&contents, key))
+            .map_err(|e| match e.kind() {
+                // A file cut short mid-write parses as an unexpected end of input.
+                io::ErrorKind::InvalidData | io::ErrorKind::UnexpectedEof => corrupt(path, e),
+                _ => io::Error::new(e.kind(), format!("{}: {}", path.display(), e)),
+            })
+    } else {
+        Ok(T::default())
+    }
+}
+
+/// Writes to a temporary file first so a crash never leaves a half-written
+/// data file behind.
+fn save_data<T: Serialize + ?Sized>(
+    path: &Path,
+    key: &str,
+    data: &T,
+    cipher: Option<&Cipher>,
+) -> io::Result<()> {
+    let tmp_path = with_suffix(path, ".tmp");
     let mut file = OpenOptions::new()
         .write(true)
         .create(true)
         .truncate(true)
-        .open(file_name)?;
+        .open(&tmp_path)?;
 
-    let json = serde_json::to_string_pretty(data)?;
-    file.write_all(json.as_bytes())?;
+    let json = serde_json::to_string_pretty(&Envelope { key, data })?;
+    match cipher {
+        Some(cipher) => file.write_all(&cipher.encrypt(json.as_bytes()))?,
+        None => file.write_all(json.as_bytes())?,
+    }
+    file.sync_all()?;
+    fs::rename(&tmp_path, path)
+}
+
+/// The text of a data file, decrypted if it is encrypted.
+fn decode(data: &[u8], cipher: Option<&Cipher>) -> io::Result<String> {
+    let data = match cipher {
+        Some(cipher) => cipher.decrypt(data)?,
+        None if crypto::is_encrypted(data) => {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!("encrypted, but {} is missing", crypto::KEY_FILE),
+            ))
+        }
+        None => data.to_vec(),
+    };
+    String::from_utf8(data).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// A data file that exists but could not be parsed.
+#[derive(Debug)]
+pub struct CorruptFile {
+    pub path: PathBuf,
+    pub reason: String,
+}
+
+impl fmt::Display for CorruptFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path.display(), self.reason)
+    }
+}
+
+impl Error for CorruptFile {}
+
+/// The error for a data file that cannot be parsed, which the UI offers to
+/// recover from.
+pub fn corrupt(path: &Path, reason: impl fmt::Display) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        CorruptFile {
+            path: path.to_path_buf(),
+            reason: reason.to_string(),
+        },
+    )
+}
+
+/// Returns the unreadable file behind a load error, if that was the cause.
+pub fn corrupt_file(err: &io::Error) -> Option<&CorruptFile> {
+    err.get_ref()?.downcast_ref()
+}
+
+/// Moves an unreadable data file out of the way as
+/// `<name>.corrupt-<timestamp>` and returns its new path.
+pub fn quarantine(path: &Path) -> io::Result<PathBuf> {
+    let target = with_suffix(path, &format!(".corrupt-{}", timestamp()));
+    fs::rename(path, &target)?;
+    Ok(target)
+}
+
+const BACKUP_DIR: &str = "backups";
+const MAX_BACKUPS: usize = 10;
+
+fn backup_dir(path: &Path) -> PathBuf {
+    path.parent().unwrap_or(Path::new("")).join(BACKUP_DIR)
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// Copies `path` to `backups/<name>.<timestamp>`, keeping the newest
+/// `MAX_BACKUPS` copies.
+fn backup(path: &Path) -> io::Result<()> {
+    if !path.exists() {
+        return Ok(());
+    }
+    let dir = backup_dir(path);
+    fs::create_dir_all(&dir)?;
+    fs::copy(
+        path,
+        dir.join(format!("{}.{}", file_name(path), timestamp())),
+    )?;
+
+    let backups = list_backups(path)?;
+    for old in backups.iter().skip(MAX_BACKUPS) {
+        fs::remove_file(old)?;
+    }
     Ok(())
 }
 
+/// Backups of `path`, newest first.
+fn list_backups(path: &Path) -> io::Result<Vec<PathBuf>> {
+    let dir = backup_dir(path);
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    let prefix = format!("{}.", file_name(path));
+    let mut backups: Vec<PathBuf> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|p| file_name(p).starts_with(&prefix))
+        .collect();
+    // Timestamps sort lexicographically.
+    backups.sort();
+    backups.reverse();
+    Ok(backups)
+}
+
+pub fn latest_backup(path: &Path) -> io::Result<Option<PathBuf>> {
+    Ok(list_backups(path)?.into_iter().next())
+}
+
+pub fn restore_backup(path: &Path, backup: &Path) -> io::Result<()> {
+    fs::copy(backup, path).map(|_| ())
+}
+
+fn timestamp() -> String {
+    chrono::Local::now().format("%Y%m%d-%H%M%S").to_string()
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eventlog;
+    use crate::habit::Frequency;
+
+    #[test]
+    fn loads_unversioned_habits() {
+        let habits: Vec<Habit> =
+            parse_data(include_str!("../tests/fixtures/v0_habits.json"), HABITS_KEY).unwrap();
+        assert_eq!(habits.len(), 8);
+        assert_eq!(habits[0].name, "gym");
+        assert_eq!(habits[5].frequency, Frequency::Weekly);
+        assert_eq!(habits[6].frequency, Frequency::Monthly);
+    }
+
+    #[test]
+    fn loads_unversioned_todos() {
+        let todos: Vec<Todo> =
+            parse_data(include_str!("../tests/fixtures/v0_todos.json"), TODOS_KEY).unwrap();
+        assert_eq!(todos.len(), 2);
+        assert_eq!(todos[0].description, "take out the trash");
+        assert!(todos[0].created.is_none());
+    }
+
+    #[test]
+    fn fills_in_missing_fields() {
+        let habits: Vec<Habit> =
+            parse_data(include_str!("../tests/fixtures/v1_habits.json"), HABITS_KEY).unwrap();
+        assert_eq!(habits.len(), 2);
+        assert_eq!(habits[0].completed_dates().len(), 2);
+        assert_eq!(habits[1].name, "stretch");
+        assert_eq!(habits[1].category, "");
+        assert_eq!(habits[1].frequency, Frequency::Daily);
+        assert!(habits[1].completed_dates().is_empty());
+    }
+
+    #[test]
+    fn rejects_newer_versions() {
+        let result: io::Result<Vec<Habit>> = parse_data(
+            include_str!("../tests/fixtures/future_habits.json"),
+            HABITS_KEY,
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn migrated_files_are_current() {
+        let value = migrate(
+            serde_json::from_str(include_str!("../tests/fixtures/v0_habits.json")).unwrap(),
+            HABITS_KEY,
+        )
+        .unwrap();
+        assert_eq!(value["version"], CURRENT_VERSION);
+        assert!(value[HABITS_KEY].is_array());
+    }
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "habit_tracker-storage-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn habit(name: &str) -> Habit {
+        Habit::new(name.into(), "Health".into(), Frequency::Daily)
+    }
+
+    #[test]
+    fn renames_reach_the_event_log_behind_a_todotxt_file() {
+        let dir = scratch_dir("todotxt-rename");
+        let mut storage = TodoTxtStorage {
+            inner: Box::new(EventLogStorage::new(&dir)),
+            path: dir.join("todo.txt"),
+        };
+        let mut run = habit("Run");
+        run.mark_completed(NaiveDate::from_ymd_opt(2024, 5, 1).unwrap());
+        storage.save_habits(&[run.clone()]).unwrap();
+
+        storage
+            .record_rename("Run", "Health", "Jog", "Health")
+            .unwrap();
+        run.name = "Jog".into();
+        storage.save_habits(&[run.clone()]).unwrap();
+        let log = EventLogStorage::new(&dir);
+        assert_eq!(log.load_habits().unwrap(), [run]);
+        // Logged as a rename, the check-in made under the old name stays
+        // part of the habit's history.
+        let entries = log.entries().unwrap();
+        let events: Vec<String> = eventlog::history(&entries, "Jog", Some("Health"))
+            .iter()
+            .map(|entry| entry.event.to_string())
+            .collect();
+        assert_eq!(events.len(), 3, "{:?}", events);
+        assert_eq!(events[2], "renamed to Jog (Health)");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn unreadable_files_are_reported_and_quarantined() {
+        let dir = scratch_dir("quarantine");
+        let path = dir.join(HABITS_FILE);
+        fs::write(&path, "{ \"version\": 1, \"habits\": [").unwrap();
+        let storage = JsonStorage::new(&path, dir.join(TODOS_FILE));
+        let err = storage.load_habits().unwrap_err();
+        let corrupt = corrupt_file(&err).unwrap();
+        assert_eq!(corrupt.path, path);
+
+        let moved = quarantine(&corrupt.path).unwrap();
+        assert!(file_name(&moved).starts_with("habits.json.corrupt-"));
+        assert!(moved.exists() && !path.exists());
+        assert!(storage.load_habits().unwrap().is_empty());
+        // Other errors are not mistaken for unreadable files.
+        let denied = io::Error::new(io::ErrorKind::PermissionDenied, "no");
+        assert!(corrupt_file(&denied).is_none());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn the_latest_backup_can_be_restored() {
+        let dir = scratch_dir("restore");
+        let path = dir.join(HABITS_FILE);
+        assert_eq!(latest_backup(&path).unwrap(), None);
+        save_habits_file(&path, &[habit("Run")], None).unwrap();
+        fs::create_dir_all(dir.join(BACKUP_DIR)).unwrap();
+        fs::write(
+            dir.join(BACKUP_DIR).join("habits.json.20000101-000000"),
+            "old",
+        )
+        .unwrap();
+        // Backups of other files do not count.
+        fs::write(
+            dir.join(BACKUP_DIR).join("todos.json.29990101-000000"),
+            "[]",
+        )
+        .unwrap();
+
+        // The first save of a session backs up what was there.
+        let mut storage = JsonStorage::new(&path, dir.join(TODOS_FILE));
+        storage.save_habits(&[habit("Run"), habit("Swim")]).unwrap();
+        storage.save_habits(&[habit("Swim")]).unwrap();
+        assert_eq!(list_backups(&path).unwrap().len(), 2);
+
+        let backup = latest_backup(&path).unwrap().unwrap();
+        fs::write(&path, "garbage").unwrap();
+        quarantine(&path).unwrap();
+        restore_backup(&path, &backup).unwrap();
+        assert_eq!(storage.load_habits().unwrap(), [habit("Run")]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn only_the_newest_backups_are_kept() {
+        let dir = scratch_dir("prune");
+        let path = dir.join(TODOS_FILE);
+        save_todos_file(&path, &[], None).unwrap();
+        fs::create_dir_all(dir.join(BACKUP_DIR)).unwrap();
+        for day in 1..=12 {
+            let name = format!("todos.json.200001{:02}-000000", day);
+            fs::write(dir.join(BACKUP_DIR).join(name), "[]").unwrap();
+        }
+        backup(&path).unwrap();
+        let backups = list_backups(&path).unwrap();
+        assert_eq!(backups.len(), MAX_BACKUPS);
+        // The new one is the latest; the three oldest went.
+        assert_eq!(latest_backup(&path).unwrap().as_ref(), backups.first());
+        assert_eq!(file_name(&backups[9]), "todos.json.20000104-000000");
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,372 @@
+use crate::crypto;
+use crate::merge;
+use crate::storage::{self, CATEGORIES_FILE, HABITS_FILE, TODOS_FILE};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+const REMOTE: &str = "origin";
+const DRIVER: &str = "habit-tracker";
+const ATTRIBUTES_FILE: &str = ".gitattributes";
+const IGNORE_FILE: &str = ".gitignore";
+const IGNORED: &str = "\
+.habit_tracker.lock
+*.tmp
+*.corrupt-*
+backups/
+";
+
+/// Makes `dir` a git repository set up for syncing: the data files use the
+/// semantic merge driver, and `url` (if given) becomes the `origin` remote.
+/// Safe to run again on an existing repository.
+pub fn init(dir: &Path, url: Option<&str>) -> io::Result<()> {
+    if !dir.join(".git").exists() {
+        git(dir, &["init", "--quiet"])?;
+    }
+    write_if_missing(
+        &dir.join(ATTRIBUTES_FILE),
+        &format!(
+            "{} merge={}\n{} merge={}\n{} merge={}\n",
+            HABITS_FILE, DRIVER, TODOS_FILE, DRIVER, CATEGORIES_FILE, DRIVER
+        ),
+    )?;
+    write_if_missing(&dir.join(IGNORE_FILE), IGNORED)?;
+
+    // The driver lives in the repository config, so every clone has to be
+    // initialised too; %O %A %B are the base, ours (the output) and theirs.
+    let exe = std::env::current_exe()?;
+    let driver = format!("'{}' merge-driver %O %A %B %P", exe.display());
+    git(
+        dir,
+        &["config", &format!("merge.{}.name", DRIVER), "habit data"],
+    )?;
+    git(
+        dir,
+        &["config", &format!("merge.{}.driver", DRIVER), &driver],
+    )?;
+    if git(dir, &["config", "user.name"]).is_err() {
+        git(dir, &["config", "user.name", "habit_tracker"])?;
+        git(dir, &["config", "user.email", "habit_tracker@localhost"])?;
+    }
+
+    if let Some(url) = url {
+        if has_remote(dir) {
+            git(dir, &["remote", "set-url", REMOTE, url])?;
+        } else {
+            git(dir, &["remote", "add", REMOTE, url])?;
+        }
+    }
+    Ok(())
+}
+
+/// Commits the given data files if they changed. Files outside `dir`
+/// (such as a todo.txt elsewhere) are left alone. Returns whether a commit
+/// was made.
+pub fn commit(dir: &Path, files: &[PathBuf], message: &str) -> io::Result<bool> {
+    let mut args = vec!["add", "--", ATTRIBUTES_FILE, IGNORE_FILE];
+    let files: Vec<String> = files
+        .iter()
+        .filter(|f| f.is_relative() && dir.join(f).exists())
+        .map(|f| f.display().to_string())
+        .collect();
+    args.extend(files.iter().map(String::as_str));
+    // Other clones need the salt to decrypt the data files.
+    if crypto::is_enabled(dir) {
+        args.push(crypto::KEY_FILE);
+    }
+    git(dir, &args)?;
+
+    let unchanged = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(["diff", "--cached", "--quiet"])
+        .status()?
+        .success();
+    if unchanged {
+        return Ok(false);
+    }
+    git(dir, &["commit", "--quiet", "-m", message])?;
+    Ok(true)
+}
+
+/// Fetches from `origin` and merges its copy of the current branch.
+/// Returns false when there is no remote or nothing was pushed there yet.
+pub fn pull(dir: &Path) -> io::Result<bool> {
+    if !has_remote(dir) {
+        return Ok(false);
+    }
+    git(dir, &["fetch", "--quiet", REMOTE])?;
+    let upstream = format!("{}/{}", REMOTE, branch(dir)?);
+    if git(dir, &["rev-parse", "--verify", "--quiet", &upstream]).is_err() {
+        return Ok(false);
+    }
+    // Both machines may have started their own history before the first sync.
+    let merged = git(
+        dir,
+        &[
+            "merge",
+            "--quiet",
+            "--no-edit",
+            "--allow-unrelated-histories",
+            &upstream,
+        ],
+    );
+    if let Err(err) = merged {
+        let _ = git(dir, &["merge", "--abort"]);
+        return Err(err);
+    }
+    Ok(true)
+}
+
+/// Pushes the current branch to `origin`. Returns false when there is no
+/// remote.
+pub fn push(dir: &Path) -> io::Result<bool> {
+    if !has_remote(dir) {
+        return Ok(false);
+    }
+    git(dir, &["push", "--quiet", "-u", REMOTE, &branch(dir)?])?;
+    Ok(true)
+}
+
+/// Commits local changes, merges the remote's and pushes the result.
+pub fn sync(dir: &Path, files: &[PathBuf], message: &str) -> io::Result<()> {
+    init(dir, None)?;
+    commit(dir, files, message)?;
+    pull(dir)?;
+    push(dir)?;
+    Ok(())
+}
+
+/// Git merge driver for the JSON data files: merges `theirs` into `ours`
+/// habit by habit (or todo by todo, or category by category), so completion
+/// dates added on either side are kept instead of ending up in a textual
+/// conflict. `path` is the
+/// file's name in the repository and tells which kind of file it is.
+pub fn merge_driver(base: &Path, ours: &Path, theirs: &Path, path: &Path) -> io::Result<()> {
+    // Git runs the driver in the top of the work tree, the data directory.
+    let cipher = crypto::unlock(Path::new("."))?;
+    let cipher = cipher.as_ref();
+    if path.file_name() == Path::new(CATEGORIES_FILE).file_name() {
+        let load = |path| {
+            load_or_empty(path, |path| {
+                storage::load_category_colors_file(path, cipher)
+            })
+        };
+        let merged = merge::merge_category_colors(&load(base)?, &load(ours)?, &load(theirs)?);
+        storage::save_category_colors_file(ours, &merged, cipher)
+    } else if path.file_name() == Path::new(TODOS_FILE).file_name() {
+        let load = |path| load_or_empty(path, |path| storage::load_todos_file(path, cipher));
+        let merged = merge::merge_todos(&load(base)?, &load(ours)?, &load(theirs)?);
+        storage::save_todos_file(ours, &merged, cipher)
+    } else {
+        let load = |path| load_or_empty(path, |path| storage::load_habits_file(path, cipher));
+        let merged = merge::merge_habits(&load(base)?, &load(ours)?, &load(theirs)?);
+        storage::save_habits_file(ours, &merged, cipher)
+    }
+}
+
+/// Git passes an empty base when both sides added the file independently.
+fn load_or_empty<T: Default>(path: &Path, load: impl Fn(&Path) -> io::Result<T>) -> io::Result<T> {
+    if fs::metadata(path)?.len() == 0 {
+        Ok(T::default())
+    } else {
+        load(path)
+    }
+}
+
+fn has_remote(dir: &Path) -> bool {
+    git(dir, &["remote", "get-url", REMOTE]).is_ok()
+}
+
+fn branch(dir: &Path) -> io::Result<String> {
+    git(dir, &["symbolic-ref", "--short", "HEAD"])
+}
+
+fn write_if_missing(path: &Path, contents: &str) -> io::Result<()> {
+    if path.exists() {
+        Ok(())
+    } else {
+        fs::write(path, contents)
+    }
+}
+
+/// Runs git in `dir`, returning its trimmed stdout or an error carrying
+/// its stderr.
+fn git(dir: &Path, args: &[&str]) -> io::Result<String> {
+    let output = Command::new("git").arg("-C").arg(dir).args(args).output()?;
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    } else {
+        Err(io::Error::other(format!(
+            "git {}: {}",
+            args.first().unwrap_or(&""),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::habit::{CategoryColors, Frequency, Habit};
+    use crate::todo::Todo;
+    use chrono::NaiveDate;
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "habit_tracker-sync-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn day(d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 5, d).unwrap()
+    }
+
+    fn run(done: &[u32]) -> Habit {
+        let mut habit = Habit::new("Run".into(), "Health".into(), Frequency::Daily);
+        for d in done {
+            habit.mark_completed(day(*d));
+        }
+        habit
+    }
+
+    fn data_files() -> Vec<PathBuf> {
+        vec![
+            PathBuf::from(HABITS_FILE),
+            PathBuf::from(TODOS_FILE),
+            PathBuf::from(CATEGORIES_FILE),
+        ]
+    }
+
+    /// A bare remote and two copies of the data set up to sync with it.
+    fn remote_and_clones(name: &str) -> (PathBuf, PathBuf) {
+        let dir = scratch_dir(name);
+        let remote = dir.join("remote.git");
+        fs::create_dir_all(&remote).unwrap();
+        git(&remote, &["init", "--quiet", "--bare"]).unwrap();
+        let url = remote.display().to_string();
+        let (a, b) = (dir.join("a"), dir.join("b"));
+        for clone in [&a, &b] {
+            fs::create_dir_all(clone).unwrap();
+            init(clone, Some(&url)).unwrap();
+        }
+        (a, b)
+    }
+
+    #[test]
+    fn changes_travel_between_clones_through_the_remote() {
+        let (a, b) = remote_and_clones("travel");
+        storage::save_habits_file(&a.join(HABITS_FILE), &[run(&[1])], None).unwrap();
+        sync(&a, &data_files(), "from a").unwrap();
+
+        sync(&b, &data_files(), "first sync of b").unwrap();
+        let habits = storage::load_habits_file(&b.join(HABITS_FILE), None).unwrap();
+        assert_eq!(habits, [run(&[1])]);
+
+        // Edits to different files on both sides meanwhile merge cleanly.
+        let todos = [Todo {
+            description: "Pay rent".into(),
+            ..Todo::default()
+        }];
+        storage::save_todos_file(&b.join(TODOS_FILE), &todos, None).unwrap();
+        sync(&b, &data_files(), "from b").unwrap();
+        storage::save_habits_file(&a.join(HABITS_FILE), &[run(&[1, 2])], None).unwrap();
+        sync(&a, &data_files(), "from a again").unwrap();
+
+        assert_eq!(
+            storage::load_todos_file(&a.join(TODOS_FILE), None).unwrap(),
+            todos
+        );
+        assert!(pull(&b).unwrap());
+        let habits = storage::load_habits_file(&b.join(HABITS_FILE), None).unwrap();
+        assert_eq!(habits, [run(&[1, 2])]);
+    }
+
+    #[test]
+    fn nothing_to_pull_before_the_first_push() {
+        let (a, _) = remote_and_clones("empty");
+        assert!(!pull(&a).unwrap());
+        // The git config files are committed even without data.
+        assert!(commit(&a, &data_files(), "start").unwrap());
+        assert!(!commit(&a, &data_files(), "unchanged").unwrap());
+        assert!(push(&a).unwrap());
+
+        let alone = scratch_dir("no-remote");
+        init(&alone, None).unwrap();
+        assert!(!pull(&alone).unwrap());
+        assert!(!push(&alone).unwrap());
+    }
+
+    #[test]
+    fn the_merge_driver_keeps_completions_from_both_sides() {
+        let dir = scratch_dir("driver");
+        // Git hands the driver temporary copies of the three versions.
+        let (base, ours, theirs) = (
+            dir.join(".merge_file_base"),
+            dir.join(".merge_file_ours"),
+            dir.join(".merge_file_theirs"),
+        );
+        let swim = Habit::new("Swim".into(), "Health".into(), Frequency::Weekly);
+        storage::save_habits_file(&base, &[run(&[1])], None).unwrap();
+        storage::save_habits_file(&ours, &[run(&[1, 2])], None).unwrap();
+        storage::save_habits_file(&theirs, &[run(&[1, 3]), swim.clone()], None).unwrap();
+
+        merge_driver(&base, &ours, &theirs, Path::new(HABITS_FILE)).unwrap();
+        let merged = storage::load_habits_file(&ours, None).unwrap();
+        assert_eq!(merged, [run(&[1, 2, 3]), swim]);
+
+        // Both sides added the file, so there is no base.
+        fs::write(&base, "").unwrap();
+        storage::save_habits_file(&ours, &[run(&[4])], None).unwrap();
+        storage::save_habits_file(&theirs, &[run(&[5])], None).unwrap();
+        merge_driver(&base, &ours, &theirs, Path::new(HABITS_FILE)).unwrap();
+        let merged = storage::load_habits_file(&ours, None).unwrap();
+        assert_eq!(merged, [run(&[4, 5])]);
+    }
+
+    #[test]
+    fn the_merge_driver_merges_category_colors_by_category() {
+        let dir = scratch_dir("driver-colors");
+        let (base, ours, theirs) = (
+            dir.join(".merge_file_base"),
+            dir.join(".merge_file_ours"),
+            dir.join(".merge_file_theirs"),
+        );
+        let colors = |pairs: &[(&str, &str)]| -> CategoryColors {
+            pairs
+                .iter()
+                .map(|(category, color)| (category.to_string(), color.to_string()))
+                .collect()
+        };
+        let save = |path: &Path, pairs: &[(&str, &str)]| {
+            storage::save_category_colors_file(path, &colors(pairs), None).unwrap()
+        };
+        save(&base, &[("Health", "green"), ("Work", "blue")]);
+        // We recolor Health; they recolor Work, remove Health's color (which
+        // we changed, so ours stays) and color a new category.
+        save(&ours, &[("Health", "red"), ("Work", "blue")]);
+        save(&theirs, &[("Mind", "cyan"), ("Work", "yellow")]);
+
+        merge_driver(&base, &ours, &theirs, Path::new(CATEGORIES_FILE)).unwrap();
+        let merged = storage::load_category_colors_file(&ours, None).unwrap();
+        assert_eq!(
+            merged,
+            colors(&[("Health", "red"), ("Mind", "cyan"), ("Work", "yellow")])
+        );
+
+        // A color we left alone and they removed goes.
+        save(&base, &[("Health", "green")]);
+        save(&ours, &[("Health", "green")]);
+        save(&theirs, &[]);
+        merge_driver(&base, &ours, &theirs, Path::new(CATEGORIES_FILE)).unwrap();
+        assert!(storage::load_category_colors_file(&ours, None)
+            .unwrap()
+            .is_empty());
+    }
+}
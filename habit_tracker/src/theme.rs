@@ -0,0 +1,252 @@
+use serde::{Deserialize, Deserializer};
+use tui::style::Color;
+
+/// The colors of the terminal UI.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Theme {
+    /// Tab names, todos, the title and the help line.
+    pub text: Color,
+    /// The current tab, input, priorities and habit names in the charts.
+    pub accent: Color,
+    /// List titles and weekday names.
+    pub heading: Color,
+    /// The selected list entry.
+    pub selection: Color,
+    /// Behind the selected list entry.
+    pub selection_background: Color,
+    /// Streak bars and days done.
+    pub done: Color,
+    /// Days missed and overdue todos.
+    pub missed: Color,
+    /// Paused habits and due dates.
+    pub muted: Color,
+    /// Given in turn to categories without a color of their own.
+    pub categories: Vec<Color>,
+}
+
+/// The shipped themes, chosen with `theme` in the config.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Preset {
+    #[default]
+    Dark,
+    Light,
+    Solarized,
+    HighContrast,
+}
+
+/// Colors from `[colors]`, each replacing the preset's.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Colors {
+    #[serde(deserialize_with = "color")]
+    pub text: Option<Color>,
+    #[serde(deserialize_with = "color")]
+    pub accent: Option<Color>,
+    #[serde(deserialize_with = "color")]
+    pub heading: Option<Color>,
+    #[serde(deserialize_with = "color")]
+    pub selection: Option<Color>,
+    #[serde(deserialize_with = "color")]
+    pub selection_background: Option<Color>,
+    #[serde(deserialize_with = "color")]
+    pub done: Option<Color>,
+    #[serde(deserialize_with = "color")]
+    pub missed: Option<Color>,
+    #[serde(deserialize_with = "color")]
+    pub muted: Option<Color>,
+    #[serde(deserialize_with = "colors")]
+    pub categories: Option<Vec<Color>>,
+}
+
+// The Solarized palette, see https://ethanschoonover.com/solarized/
+const BASE01: Color = Color::Rgb(88, 110, 117);
+const BASE0: Color = Color::Rgb(131, 148, 150);
+const BASE02: Color = Color::Rgb(7, 54, 66);
+const YELLOW: Color = Color::Rgb(181, 137, 0);
+const ORANGE: Color = Color::Rgb(203, 75, 22);
+const RED: Color = Color::Rgb(220, 50, 47);
+const MAGENTA: Color = Color::Rgb(211, 54, 130);
+const VIOLET: Color = Color::Rgb(108, 113, 196);
+const BLUE: Color = Color::Rgb(38, 139, 210);
+const CYAN: Color = Color::Rgb(42, 161, 152);
+const GREEN: Color = Color::Rgb(133, 153, 0);
+
+impl Theme {
+    /// `preset` with the colors set in `colors` replaced.
+    pub fn new(preset: Preset, colors: &Colors) -> Self {
+        let theme = Theme::preset(preset);
+        Theme {
+            text: colors.text.unwrap_or(theme.text),
+            accent: colors.accent.unwrap_or(theme.accent),
+            heading: colors.heading.unwrap_or(theme.heading),
+            selection: colors.selection.unwrap_or(theme.selection),
+            selection_background: colors
+                .selection_background
+                .unwrap_or(theme.selection_background),
+            done: colors.done.unwrap_or(theme.done),
+            missed: colors.missed.unwrap_or(theme.missed),
+            muted: colors.muted.unwrap_or(theme.muted),
+            categories: colors.categories.clone().unwrap_or(theme.categories),
+        }
+    }
+
+    pub fn preset(preset: Preset) -> Self {
+        match preset {
+            Preset::Dark => Theme {
+                text: Color::White,
+                accent: Color::Yellow,
+                heading: Color::Cyan,
+                selection: Color::LightCyan,
+                selection_background: Color::Reset,
+                done: Color::Green,
+                missed: Color::Red,
+                muted: Color::DarkGray,
+                categories: vec![
+                    Color::Red,
+                    Color::Green,
+                    Color::Yellow,
+                    Color::Blue,
+                    Color::Magenta,
+                    Color::Cyan,
+                ],
+            },
+            // Yellow and the light colors wash out on a white background.
+            Preset::Light => Theme {
+                text: Color::Black,
+                accent: Color::Blue,
+                heading: Color::Magenta,
+                selection: Color::Black,
+                selection_background: Color::Gray,
+                done: Color::Green,
+                missed: Color::Red,
+                muted: Color::DarkGray,
+                categories: vec![
+                    Color::Red,
+                    Color::Green,
+                    Color::Blue,
+                    Color::Magenta,
+                    Color::Cyan,
+                    Color::Black,
+                ],
+            },
+            Preset::Solarized => Theme {
+                text: BASE0,
+                accent: YELLOW,
+                heading: BLUE,
+                selection: CYAN,
+                selection_background: BASE02,
+                done: GREEN,
+                missed: RED,
+                muted: BASE01,
+                categories: vec![RED, ORANGE, YELLOW, GREEN, CYAN, BLUE, VIOLET, MAGENTA],
+            },
+            Preset::HighContrast => Theme {
+                text: Color::White,
+                accent: Color::LightYellow,
+                heading: Color::White,
+                selection: Color::Black,
+                selection_background: Color::White,
+                done: Color::LightGreen,
+                missed: Color::LightRed,
+                muted: Color::Gray,
+                categories: vec![
+                    Color::LightYellow,
+                    Color::LightCyan,
+                    Color::LightGreen,
+                    Color::LightMagenta,
+                    Color::White,
+                ],
+            },
+        }
+    }
+
+    /// The color of the `index`th category shown.
+    pub fn category(&self, index: usize) -> Color {
+        match self.categories.len() {
+            0 => self.text,
+            len => self.categories[index % len],
+        }
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme::preset(Preset::Dark)
+    }
+}
+
+const NAMED_COLORS: [(&str, Color); 17] = [
+    ("reset", Color::Reset),
+    ("black", Color::Black),
+    ("red", Color::Red),
+    ("green", Color::Green),
+    ("yellow", Color::Yellow),
+    ("blue", Color::Blue),
+    ("magenta", Color::Magenta),
+    ("cyan", Color::Cyan),
+    ("gray", Color::Gray),
+    ("dark_gray", Color::DarkGray),
+    ("light_red", Color::LightRed),
+    ("light_green", Color::LightGreen),
+    ("light_yellow", Color::LightYellow),
+    ("light_blue", Color::LightBlue),
+    ("light_magenta", Color::LightMagenta),
+    ("light_cyan", Color::LightCyan),
+    ("white", Color::White),
+];
+
+/// Reads a color name such as `light_cyan`, a hex `#rrggbb` or a palette
+/// index from 0 to 255.
+pub fn parse_color(s: &str) -> Result<Color, String> {
+    let name = s.to_ascii_lowercase().replace('-', "_");
+    if let Some((_, color)) = NAMED_COLORS.iter().find(|(n, _)| *n == name) {
+        return Ok(*color);
+    }
+    if let Some(hex) = name.strip_prefix('#').filter(|hex| hex.len() == 6) {
+        if let Ok(rgb) = u32::from_str_radix(hex, 16) {
+            return Ok(Color::Rgb((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8));
+        }
+    }
+    if let Ok(index) = name.parse::<u8>() {
+        return Ok(Color::Indexed(index));
+    }
+    Err(format!(
+        "unknown color `{}`; use a name like light_cyan, #rrggbb or 0-255",
+        s
+    ))
+}
+
+fn color<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Color>, D::Error> {
+    parse_color(&String::deserialize(deserializer)?)
+        .map(Some)
+        .map_err(serde::de::Error::custom)
+}
+
+fn colors<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Vec<Color>>, D::Error> {
+    Vec::<String>::deserialize(deserializer)?
+        .iter()
+        .map(|name| parse_color(name).map_err(serde::de::Error::custom))
+        .collect::<Result<_, _>>()
+        .map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn colors_override_the_preset() {
+        let colors: Colors = toml::from_str("accent = \"#268bd2\"\nmuted = \"244\"").unwrap();
+        let theme = Theme::new(Preset::Light, &colors);
+        assert_eq!(theme.accent, Color::Rgb(38, 139, 210));
+        assert_eq!(theme.muted, Color::Indexed(244));
+        assert_eq!(theme.text, Theme::preset(Preset::Light).text);
+    }
+
+    #[test]
+    fn unknown_colors_are_rejected() {
+        let err = toml::from_str::<Colors>("text = \"light_orange\"").unwrap_err();
+        assert!(err.to_string().contains("unknown color `light_orange`"));
+    }
+}
@@ -1,9 +1,19 @@
+use chrono::NaiveDate;
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+const DATE_FORMAT: &str = "%Y-%m-%d";
+
+#[derive(Debug, Default, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
 pub struct Todo {
     pub description: String,
     pub completed: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub priority: Option<char>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub created: Option<NaiveDate>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub completed_on: Option<NaiveDate>,
 }
 
 impl Todo {
@@ -11,10 +21,281 @@ impl Todo {
         Todo {
             description,
             completed: false,
+            priority: None,
+            created: Some(crate::clock::today()),
+            completed_on: None,
         }
     }
 
     pub fn toggle_completion(&mut self) {
         self.completed = !self.completed;
+        self.completed_on = if self.completed {
+            Some(crate::clock::today())
+        } else {
+            None
+        };
+    }
+
+    /// `+project` tags in the description.
+    pub fn projects(&self) -> Vec<&str> {
+        self.description
+            .split_whitespace()
+            .filter(|word| word.len() > 1 && word.starts_with('+'))
+            .map(|word| &word[1..])
+            .collect()
+    }
+
+    /// `@context` tags in the description.
+    pub fn contexts(&self) -> Vec<&str> {
+        self.description
+            .split_whitespace()
+            .filter(|word| word.len() > 1 && word.starts_with('@'))
+            .map(|word| &word[1..])
+            .collect()
+    }
+
+    /// `key:value` pairs in the description, such as `due:2024-05-01`.
+    pub fn extras(&self) -> Vec<(&str, &str)> {
+        self.description
+            .split_whitespace()
+            .filter_map(parse_extra)
+            .collect()
+    }
+
+    pub fn due_date(&self) -> Option<NaiveDate> {
+        self.extras()
+            .into_iter()
+            .find(|(key, _)| *key == "due")
+            .and_then(|(_, value)| NaiveDate::parse_from_str(value, DATE_FORMAT).ok())
+    }
+
+    /// Parses a single line in todo.txt format. Blank lines yield `None`.
+    /// The description is kept as written, spacing included, so a file
+    /// read and written back is unchanged.
+    ///
+    /// Completed tasks carry their priority as a `pri:X` extra, as the
+    /// format has no slot for it once the line starts with `x`.
+    pub fn from_todotxt(line: &str) -> Option<Self> {
+        if line.trim().is_empty() {
+            return None;
+        }
+
+        let mut rest = line;
+        let mut completed = false;
+        let mut priority = None;
+        let mut completed_on = None;
+        let mut created = None;
+
+        if let Some(stripped) = rest.strip_prefix("x ") {
+            completed = true;
+            rest = stripped.trim_start();
+            if let Some((date, stripped)) = take_date(rest) {
+                completed_on = Some(date);
+                rest = stripped;
+                if let Some((date, stripped)) = take_date(rest) {
+                    created = Some(date);
+                    rest = stripped;
+                }
+            }
+        } else {
+            if let Some((p, stripped)) = take_priority(rest) {
+                priority = Some(p);
+                rest = stripped;
+            }
+            if let Some((date, stripped)) = take_date(rest) {
+                created = Some(date);
+                rest = stripped;
+            }
+        }
+
+        let mut description = rest.to_string();
+        if completed {
+            // Split on single spaces so the rest of the spacing survives
+            // taking the extra out.
+            let mut words: Vec<&str> = rest.split(' ').collect();
+            if let Some(index) = words.iter().position(|w| parse_priority_extra(w).is_some()) {
+                priority = parse_priority_extra(words.remove(index));
+                description = words.join(" ");
+            }
+        }
+
+        Some(Todo {
+            description,
+            completed,
+            priority,
+            created,
+            completed_on,
+        })
+    }
+
+    pub fn to_todotxt(&self) -> String {
+        let mut parts = Vec::new();
+        if self.completed {
+            parts.push("x".to_string());
+            if let Some(date) = self.completed_on {
+                parts.push(date.format(DATE_FORMAT).to_string());
+            }
+        } else if let Some(p) = self.priority {
+            parts.push(format!("({})", p));
+        }
+        // The creation date is only unambiguous after a completion date.
+        if let Some(date) = self.created {
+            if !self.completed || self.completed_on.is_some() {
+                parts.push(date.format(DATE_FORMAT).to_string());
+            }
+        }
+        parts.push(self.description.clone());
+        if self.completed {
+            if let Some(p) = self.priority {
+                parts.push(format!("pri:{}", p));
+            }
+        }
+        parts.join(" ")
+    }
+}
+
+pub fn parse_todotxt(contents: &str) -> Vec<Todo> {
+    contents.lines().filter_map(Todo::from_todotxt).collect()
+}
+
+pub fn to_todotxt(todos: &[Todo]) -> String {
+    todos
+        .iter()
+        .map(|todo| format!("{}\n", todo.to_todotxt()))
+        .collect()
+}
+
+fn take_priority(s: &str) -> Option<(char, &str)> {
+    let bytes = s.as_bytes();
+    if bytes.len() >= 4
+        && bytes[0] == b'('
+        && bytes[1].is_ascii_uppercase()
+        && bytes[2] == b')'
+        && bytes[3] == b' '
+    {
+        Some((bytes[1] as char, s[4..].trim_start()))
+    } else {
+        None
+    }
+}
+
+fn take_date(s: &str) -> Option<(NaiveDate, &str)> {
+    let (word, rest) = s.split_once(' ').unwrap_or((s, ""));
+    NaiveDate::parse_from_str(word, DATE_FORMAT)
+        .ok()
+        .map(|date| (date, rest.trim_start()))
+}
+
+fn parse_extra(word: &str) -> Option<(&str, &str)> {
+    let (key, value) = word.split_once(':')?;
+    let valid_key = !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '-' || c == '_');
+    // Leave URLs such as `https://...` alone.
+    if valid_key && !value.is_empty() && !value.starts_with('/') {
+        Some((key, value))
+    } else {
+        None
+    }
+}
+
+fn parse_priority_extra(word: &str) -> Option<char> {
+    let value = word.strip_prefix("pri:")?;
+    let mut chars = value.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) if c.is_ascii_uppercase() => Some(c),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn day(d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 5, d).unwrap()
+    }
+
+    fn parse(line: &str) -> Todo {
+        Todo::from_todotxt(line).unwrap()
+    }
+
+    #[test]
+    fn reads_priority_creation_date_and_tags() {
+        let todo = parse("(A) 2024-05-01 Call mum +family @phone due:2024-05-10");
+        assert_eq!(todo.priority, Some('A'));
+        assert_eq!(todo.created, Some(day(1)));
+        assert!(!todo.completed);
+        assert_eq!(todo.description, "Call mum +family @phone due:2024-05-10");
+        assert_eq!(todo.projects(), ["family"]);
+        assert_eq!(todo.contexts(), ["phone"]);
+        assert_eq!(todo.extras(), [("due", "2024-05-10")]);
+        assert_eq!(todo.due_date(), Some(day(10)));
+    }
+
+    #[test]
+    fn reads_completed_tasks() {
+        let todo = parse("x 2024-05-03 2024-05-01 Pay rent pri:B");
+        assert!(todo.completed);
+        assert_eq!(todo.completed_on, Some(day(3)));
+        assert_eq!(todo.created, Some(day(1)));
+        assert_eq!(todo.priority, Some('B'));
+        assert_eq!(todo.description, "Pay rent");
+        let todo = parse("x 2024-05-03 Pay  rent pri:B  now");
+        assert_eq!(todo.description, "Pay  rent  now");
+
+        let todo = parse("x Pay rent");
+        assert!(todo.completed);
+        assert_eq!((todo.completed_on, todo.created), (None, None));
+        // Only a lower-case x followed by a space marks a task done.
+        assert!(!parse("X-ray appointment").completed);
+        assert!(!parse("xylophone lesson").completed);
+    }
+
+    #[test]
+    fn leaves_look_alikes_in_the_description() {
+        let todo = parse("Read https://example.com/a:b (B) later 2024-05-01");
+        assert_eq!(todo.priority, None);
+        assert_eq!(todo.created, None);
+        assert!(todo.extras().is_empty());
+        assert_eq!(parse("(a) lower case").priority, None);
+        assert_eq!(parse("no due:someday").due_date(), None);
+    }
+
+    #[test]
+    fn writes_what_it_reads() {
+        let lines = [
+            "(A) 2024-05-01 Call mum +family @phone due:2024-05-10",
+            "(C) Water plants",
+            "2024-05-02 Book flights",
+            "x 2024-05-03 2024-05-01 Pay rent pri:B",
+            "x 2024-05-04 Walk dog",
+            "x Tidy desk",
+            "Plain task key:value",
+            "(B) Spaced  out   task @home @errands",
+            "x 2024-05-05 Two  spaces pri:A",
+            "Trailing space ",
+        ];
+        for line in lines {
+            assert_eq!(parse(line).to_todotxt(), line);
+        }
+        let contents = lines.join("\n") + "\n";
+        let todos = parse_todotxt(&format!("\n{}\n  \n", contents));
+        assert_eq!(todos.len(), lines.len());
+        assert_eq!(to_todotxt(&todos), contents);
+    }
+
+    #[test]
+    fn a_creation_date_without_completion_date_is_dropped() {
+        // `x 2024-05-01 Task` would read the date back as the completion date.
+        let todo = Todo {
+            description: "Task".into(),
+            completed: true,
+            created: Some(day(1)),
+            ..Todo::default()
+        };
+        assert_eq!(todo.to_todotxt(), "x Task");
+        assert_eq!(Todo::from_todotxt("   "), None);
     }
 }
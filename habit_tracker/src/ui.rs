@@ -1,15 +1,79 @@
-use crate::habit::{Frequency, Habit};
+use crate::habit::{CategoryColors, Frequency, Habit};
+use crate::keymap::{Action, Keymap, Pending};
+use crate::theme::{self, Theme};
 use crate::todo::Todo;
-use chrono::{Datelike, NaiveDate};
+use chrono::NaiveDate;
+use serde::Deserialize;
 use std::collections::BTreeMap;
 use tui::{
     backend::Backend,
     layout::{Constraint, Direction, Layout, Rect},
-    style::{Color, Modifier, Style},
+    style::{Modifier, Style},
     text::{Span, Spans},
     widgets::{Block, Borders, List, ListItem, ListState, Paragraph, Tabs},
     Frame,
 };
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+/// The tabs, in the order they are shown.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Tab {
+    #[default]
+    Daily,
+    Weekly,
+    Monthly,
+    Frequency,
+    Todo,
+}
+
+impl Tab {
+    pub fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// Emoji check boxes, or plain ASCII for terminals and fonts without them.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Icons {
+    #[default]
+    Emoji,
+    Ascii,
+}
+
+impl Icons {
+    fn check_box(self, done: bool) -> &'static str {
+        match (self, done) {
+            (Icons::Emoji, true) => "✅",
+            (Icons::Emoji, false) => "⬜",
+            (Icons::Ascii, true) => "[x]",
+            (Icons::Ascii, false) => "[ ]",
+        }
+    }
+
+    fn bar(self) -> &'static str {
+        match self {
+            Icons::Emoji => "█",
+            Icons::Ascii => "#",
+        }
+    }
+
+    fn rule(self) -> &'static str {
+        match self {
+            Icons::Emoji => "─",
+            Icons::Ascii => "-",
+        }
+    }
+}
+
+/// How the UI looks and which keys do what, from the config.
+pub struct Settings {
+    pub theme: Theme,
+    pub icons: Icons,
+    pub date_format: String,
+    pub keymap: Keymap,
+}
 
 pub enum InputMode {
     Normal,
@@ -18,6 +82,13 @@ pub enum InputMode {
     AddingTodo,
     EditingCategory,
     EditingHabit,
+    ExportMenu,
+    ImportingCsv,
+    /// Waiting for `y` to delete the selected entry.
+    ConfirmDelete,
+    Searching,
+    /// Typing a color for the category in `edit_buffer`.
+    EditingColor,
 }
 
 pub struct AppState {
@@ -32,6 +103,16 @@ pub struct AppState {
     pub list_items: Vec<ListEntry>,
     pub current_week: NaiveDate,
     pub edit_buffer: String,
+    /// Set when another instance holds the data directory lock.
+    pub read_only: bool,
+    /// Result of the last import or export, shown in the input box.
+    pub status: String,
+    /// Keys typed towards a binding of several keys.
+    pub pending: Pending,
+    /// The text last searched for.
+    pub search: String,
+    /// The selection when the search started, restored if it is cancelled.
+    pub search_origin: Option<usize>,
 }
 
 pub enum ListEntry {
@@ -40,6 +121,17 @@ pub enum ListEntry {
     Todo(Todo),
 }
 
+impl ListEntry {
+    /// The name shown for the entry.
+    pub fn label(&self) -> &str {
+        match self {
+            ListEntry::Category(category) => category,
+            ListEntry::Habit(habit) => &habit.name,
+            ListEntry::Todo(todo) => &todo.description,
+        }
+    }
+}
+
 impl Default for AppState {
     fn default() -> Self {
         AppState {
@@ -52,8 +144,13 @@ impl Default for AppState {
             current_tab: 0,
             total_items: 0,
             list_items: Vec::new(),
-            current_week: chrono::Local::now().date_naive(),
+            current_week: crate::clock::today(),
             edit_buffer: String::new(),
+            read_only: false,
+            status: String::new(),
+            pending: Pending::default(),
+            search: String::new(),
+            search_origin: None,
         }
     }
 }
@@ -79,6 +176,54 @@ impl AppState {
         });
     }
 
+    pub fn first(&mut self) {
+        self.selected = (self.total_items > 0).then_some(0);
+    }
+
+    pub fn last(&mut self) {
+        self.selected = self.total_items.checked_sub(1);
+    }
+
+    /// Selects the next entry after the selected one (or before, when
+    /// `backwards`) whose name contains the search text, ignoring case and
+    /// wrapping around. Returns false if there is none.
+    pub fn search(&mut self, backwards: bool) -> bool {
+        let len = self.total_items;
+        if self.search.is_empty() || len == 0 {
+            return false;
+        }
+        let needle = self.search.to_lowercase();
+        // With nothing selected the first entry is a candidate too.
+        let (start, steps) = match self.selected {
+            Some(i) => (i, 1..=len),
+            None => (0, 0..=len - 1),
+        };
+        for step in steps {
+            let index = match backwards {
+                true => (start + len - step % len) % len,
+                false => (start + step) % len,
+            };
+            if self.list_items[index]
+                .label()
+                .to_lowercase()
+                .contains(&needle)
+            {
+                self.selected = Some(index);
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Keeps the selection within the list after it shrank.
+    pub fn clamp_selection(&mut self) {
+        self.selected = match self.selected {
+            Some(_) if self.total_items == 0 => None,
+            Some(i) => Some(i.min(self.total_items - 1)),
+            None => None,
+        };
+    }
+
     pub fn update_list_items(&mut self, habits: &[Habit], todos: &[Todo]) {
         self.list_items.clear();
         let filtered_habits: Vec<&Habit> = habits
@@ -96,7 +241,7 @@ impl AppState {
         for habit in &filtered_habits {
             grouped_habits
                 .entry(&habit.category)
-                .or_insert_with(Vec::new)
+                .or_default()
                 .push(habit);
         }
 
@@ -121,11 +266,11 @@ impl AppState {
     }
 
     pub fn next_week(&mut self) {
-        self.current_week = self.current_week + chrono::Duration::days(7);
+        self.current_week += chrono::Duration::days(7);
     }
 
     pub fn previous_week(&mut self) {
-        self.current_week = self.current_week - chrono::Duration::days(7);
+        self.current_week -= chrono::Duration::days(7);
     }
 }
 
@@ -133,8 +278,10 @@ pub fn draw<B: Backend>(
     f: &mut Frame<B>,
     habits: &[Habit],
     todos: &[Todo],
+    category_colors: &CategoryColors,
     current_date: &NaiveDate,
     app_state: &mut AppState,
+    settings: &Settings,
 ) {
     let chunks = Layout::default()
         .direction(Direction::Vertical)
@@ -147,44 +294,73 @@ pub fn draw<B: Backend>(
         ])
         .split(f.size());
 
-    draw_title(f, chunks[0], current_date);
-    draw_tabs(f, chunks[1], app_state);
-    draw_main_content(f, chunks[2], habits, todos, current_date, app_state);
-    draw_help(f, chunks[3]);
+    draw_title(f, chunks[0], current_date, app_state.read_only, settings);
+    draw_tabs(f, chunks[1], app_state, &settings.theme);
+    draw_main_content(
+        f,
+        chunks[2],
+        habits,
+        todos,
+        category_colors,
+        current_date,
+        app_state,
+        settings,
+    );
+    draw_help(f, chunks[3], settings);
 }
 
-fn draw_title<B: Backend>(f: &mut Frame<B>, area: Rect, current_date: &NaiveDate) {
+fn draw_title<B: Backend>(
+    f: &mut Frame<B>,
+    area: Rect,
+    current_date: &NaiveDate,
+    read_only: bool,
+    settings: &Settings,
+) {
+    let read_only_note = if read_only {
+        " (read-only: another instance is running)"
+    } else {
+        ""
+    };
     let title = Paragraph::new(Span::styled(
-        format!("Habit Tracker - {}", current_date),
-        Style::default().add_modifier(Modifier::BOLD),
+        format!(
+            "Habit Tracker - {}{}",
+            current_date.format(&settings.date_format),
+            read_only_note
+        ),
+        Style::default()
+            .fg(settings.theme.text)
+            .add_modifier(Modifier::BOLD),
     ))
     .alignment(tui::layout::Alignment::Center)
     .block(Block::default().borders(Borders::ALL));
     f.render_widget(title, area);
 }
 
-fn draw_tabs<B: Backend>(f: &mut Frame<B>, area: Rect, app_state: &AppState) {
+fn draw_tabs<B: Backend>(f: &mut Frame<B>, area: Rect, app_state: &AppState, theme: &Theme) {
     let titles = vec!["Daily", "Weekly", "Monthly", "Frequency", "Todo"];
     let tabs = Tabs::new(titles.into_iter().map(Spans::from).collect())
         .select(app_state.current_tab)
         .block(Block::default().borders(Borders::ALL))
-        .style(Style::default().fg(Color::White))
+        .style(Style::default().fg(theme.text))
         .highlight_style(
             Style::default()
-                .fg(Color::Yellow)
+                .fg(theme.accent)
                 .add_modifier(Modifier::BOLD),
         )
         .divider(Span::raw("|"));
     f.render_widget(tabs, area);
 }
 
+#[allow(clippy::too_many_arguments)]
 fn draw_main_content<B: Backend>(
     f: &mut Frame<B>,
     area: Rect,
     habits: &[Habit],
     _todos: &[Todo],
+    category_colors: &CategoryColors,
     current_date: &NaiveDate,
     app_state: &mut AppState,
+    settings: &Settings,
 ) {
     let chunks = Layout::default()
         .direction(Direction::Horizontal)
@@ -196,38 +372,45 @@ fn draw_main_content<B: Backend>(
         .constraints([Constraint::Length(3), Constraint::Min(0)])
         .split(chunks[0]);
 
-    draw_input(f, input_chunks[0], app_state);
-    draw_list(f, input_chunks[1], current_date, app_state);
+    draw_input(f, input_chunks[0], app_state, &settings.theme);
+    draw_list(
+        f,
+        input_chunks[1],
+        category_colors,
+        current_date,
+        app_state,
+        settings,
+    );
 
     if app_state.current_tab == 3 {
         // Frequency tab
-        draw_frequency_graph(f, chunks[1], habits, app_state);
+        draw_frequency_graph(f, chunks[1], habits, app_state, settings);
     } else {
-        draw_streak_chart(f, chunks[1], habits, current_date);
+        draw_streak_chart(f, chunks[1], habits, current_date, settings);
     }
 }
 
 fn draw_list<B: Backend>(
     f: &mut Frame<B>,
     area: Rect,
+    category_colors: &CategoryColors,
     current_date: &NaiveDate,
     app_state: &mut AppState,
+    settings: &Settings,
 ) {
+    let theme = &settings.theme;
     let mut items = Vec::new();
-    let category_colors = [
-        Color::Red,
-        Color::Green,
-        Color::Yellow,
-        Color::Blue,
-        Color::Magenta,
-        Color::Cyan,
-    ];
     let mut color_index = 0;
+    let mut category_color = theme.text;
 
-    for (_index, entry) in app_state.list_items.iter().enumerate() {
+    for entry in app_state.list_items.iter() {
         match entry {
             ListEntry::Category(category) => {
-                let category_color = category_colors[color_index % category_colors.len()];
+                // A color set for the category, else the theme's next one.
+                category_color = category_colors
+                    .get(category)
+                    .and_then(|color| theme::parse_color(color).ok())
+                    .unwrap_or_else(|| theme.category(color_index));
                 color_index += 1;
                 items.push(ListItem::new(Spans::from(vec![Span::styled(
                     format!("{}:", category),
@@ -237,26 +420,42 @@ fn draw_list<B: Backend>(
                 )])));
             }
             ListEntry::Habit(habit) => {
-                let completed = habit.is_completed(*current_date);
-                let icon = if completed { "✅" } else { "⬜" };
-                let content = Spans::from(vec![
+                let icon = settings.icons.check_box(habit.is_completed(*current_date));
+                let mut content = Spans::from(vec![
                     Span::raw("  "), // Indent habit
                     Span::raw(format!("{} ", icon)),
-                    Span::styled(
-                        &habit.name,
-                        Style::default()
-                            .fg(category_colors[(color_index - 1) % category_colors.len()]),
-                    ),
+                    Span::styled(&habit.name, Style::default().fg(category_color)),
                 ]);
+                if habit.paused {
+                    content
+                        .0
+                        .push(Span::styled(" (paused)", Style::default().fg(theme.muted)));
+                }
                 items.push(ListItem::new(content));
             }
             ListEntry::Todo(todo) => {
-                let icon = if todo.completed { "✅" } else { "⬜" };
-                let content = Spans::from(vec![
+                let icon = settings.icons.check_box(todo.completed);
+                let priority = todo
+                    .priority
+                    .map(|p| format!("({}) ", p))
+                    .unwrap_or_default();
+                let mut content = Spans::from(vec![
                     Span::raw("  "), // Indent todo
                     Span::raw(format!("{} ", icon)),
-                    Span::styled(&todo.description, Style::default().fg(Color::White)),
+                    Span::styled(priority, Style::default().fg(theme.accent)),
+                    Span::styled(&todo.description, Style::default().fg(theme.text)),
                 ]);
+                if let Some(due) = todo.due_date().filter(|_| !todo.completed) {
+                    let color = if due < *current_date {
+                        theme.missed
+                    } else {
+                        theme.muted
+                    };
+                    content.0.push(Span::styled(
+                        format!(" (due {})", due.format(&settings.date_format)),
+                        Style::default().fg(color),
+                    ));
+                }
                 items.push(ListItem::new(content));
             }
         }
@@ -275,11 +474,12 @@ fn draw_list<B: Backend>(
         .block(
             Block::default()
                 .borders(Borders::ALL)
-                .title(Span::styled(list_title, Style::default().fg(Color::Cyan))),
+                .title(Span::styled(list_title, Style::default().fg(theme.heading))),
         )
         .highlight_style(
             Style::default()
-                .fg(Color::LightCyan)
+                .fg(theme.selection)
+                .bg(theme.selection_background)
                 .add_modifier(Modifier::BOLD),
         );
 
@@ -288,23 +488,40 @@ fn draw_list<B: Backend>(
     f.render_stateful_widget(list, area, &mut list_state);
 }
 
-fn draw_input<B: Backend>(f: &mut Frame<B>, area: Rect, app_state: &AppState) {
+fn draw_input<B: Backend>(f: &mut Frame<B>, area: Rect, app_state: &AppState, theme: &Theme) {
     let (input_text, input_prompt) = match app_state.input_mode {
-        InputMode::Normal => ("", ""),
+        InputMode::Normal => (app_state.status.as_str(), ""),
         InputMode::AddingCategory => (app_state.new_category.as_str(), "Enter category: "),
         InputMode::AddingHabit => (app_state.new_habit_name.as_str(), "Enter habit name: "),
         InputMode::AddingTodo => (app_state.new_todo.as_str(), "Enter todo: "),
         InputMode::EditingCategory => (app_state.edit_buffer.as_str(), "Edit category: "),
         InputMode::EditingHabit => (app_state.edit_buffer.as_str(), "Edit habit name: "),
+        InputMode::ExportMenu => (
+            "",
+            "[l] Export CSV  [w] Export wide CSV  [i] Import CSV  [Esc] Cancel",
+        ),
+        InputMode::ImportingCsv => (app_state.edit_buffer.as_str(), "Import CSV from: "),
+        InputMode::ConfirmDelete => (
+            app_state
+                .selected
+                .map_or("", |index| app_state.list_items[index].label()),
+            "Delete ",
+        ),
+        InputMode::Searching => (app_state.search.as_str(), "/"),
+        InputMode::EditingColor => (
+            app_state.edit_buffer.as_str(),
+            "Color (name, #rrggbb or 0-255; empty for the theme's): ",
+        ),
     };
 
-    let frequency_text = match app_state.input_mode {
+    let suffix = match app_state.input_mode {
         InputMode::AddingHabit => format!(" ({})", app_state.new_habit_frequency),
+        InputMode::ConfirmDelete => "? [y/n]".to_string(),
         _ => String::new(),
     };
 
-    let input = Paragraph::new(format!("{}{}{}", input_prompt, input_text, frequency_text))
-        .style(Style::default().fg(Color::Yellow))
+    let input = Paragraph::new(format!("{}{}{}", input_prompt, input_text, suffix))
+        .style(Style::default().fg(theme.accent))
         .block(Block::default().borders(Borders::ALL).title("Input"));
 
     f.render_widget(input, area);
@@ -315,16 +532,18 @@ fn draw_streak_chart<B: Backend>(
     area: Rect,
     habits: &[Habit],
     current_date: &NaiveDate,
+    settings: &Settings,
 ) {
+    let theme = &settings.theme;
     let mut content = Vec::new();
 
     for habit in habits {
-        let streak = habit.get_streak(*current_date);
-        let bar = "█".repeat(streak.min(20) as usize);
+        let streak = habit.get_current_streak(*current_date);
+        let bar = settings.icons.bar().repeat(streak.min(20) as usize);
         content.push(Spans::from(vec![
-            Span::styled(&habit.name, Style::default().fg(Color::Yellow)),
+            Span::styled(&habit.name, Style::default().fg(theme.accent)),
             Span::raw(": "),
-            Span::styled(bar, Style::default().fg(Color::Green)),
+            Span::styled(bar, Style::default().fg(theme.done)),
             Span::raw(format!(" {}", streak)),
         ]));
     }
@@ -341,58 +560,67 @@ fn draw_frequency_graph<B: Backend>(
     area: Rect,
     habits: &[Habit],
     app_state: &AppState,
+    settings: &Settings,
 ) {
+    let theme = &settings.theme;
     let mut content = Vec::new();
 
     // Calculate the start and end of the week
-    let week_start = app_state.current_week
-        - chrono::Duration::days(app_state.current_week.weekday().num_days_from_monday() as i64);
-    let week_end = week_start + chrono::Duration::days(6);
+    let (week_start, week_end) = crate::habit::week_bounds(app_state.current_week);
 
     // Add centered week navigation, shifted one space to the left
     let week_nav = format!(
-        "'p' Prev Week    {0} - {1}    Next Week 'n'",
+        "'{}' Prev Week    {} - {}    Next Week '{}'",
+        settings.keymap.describe(Action::PreviousWeek),
         week_start.format("%m-%d"),
-        week_end.format("%m-%d")
+        week_end.format("%m-%d"),
+        settings.keymap.describe(Action::NextWeek)
     );
-    let left_padding = (area.width as usize - week_nav.len()) / 2 - 1; // Subtract 1 to shift left
+    let left_padding = (area.width as usize).saturating_sub(week_nav.width()) / 2;
     content.push(Spans::from(vec![
-        Span::raw(" ".repeat(left_padding)),
-        Span::styled(week_nav, Style::default().fg(Color::Yellow)),
+        // Subtract 1 to shift left
+        Span::raw(" ".repeat(left_padding.saturating_sub(1))),
+        Span::styled(week_nav, Style::default().fg(theme.accent)),
     ]));
     content.push(Spans::from(vec![Span::raw("")])); // Empty line
 
     // Add weekday headers, shifted one space to the right
-    let weekdays = ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
-    let habit_name_width = 15; // Increased by 1 to shift calendar right
+    let weekdays: Vec<String> = week_start
+        .iter_days()
+        .take(7)
+        .map(|day| day.format("%a").to_string())
+        .collect();
+    let habit_name_width = name_column_width(habits, area.width);
     let weekday_spans: Vec<Span> = vec![Span::raw(" ".repeat(habit_name_width - 1))]
         .into_iter()
         .chain(
             weekdays
                 .iter()
-                .map(|&day| Span::styled(format!("{:^5}", day), Style::default().fg(Color::Cyan))),
+                .map(|day| Span::styled(format!("{:^5}", day), Style::default().fg(theme.heading))),
         )
         .collect();
     content.push(Spans::from(weekday_spans));
 
     // Add horizontal line, extended by one character
     content.push(Spans::from(vec![Span::raw(
-        "─".repeat(habit_name_width + weekdays.len() * 5),
+        settings
+            .icons
+            .rule()
+            .repeat(habit_name_width + weekdays.len() * 5),
     )]));
 
     for habit in habits {
         let mut habit_line = Vec::new();
         habit_line.push(Span::styled(
-            format!("{:<width$}", habit.name, width = habit_name_width),
-            Style::default().fg(Color::Yellow),
+            fit(&habit.name, habit_name_width),
+            Style::default().fg(theme.accent),
         ));
 
-        for day_offset in 0..7 {
-            let date = week_start + chrono::Duration::days(day_offset);
-            let symbol = if habit.is_completed(date) {
-                Span::styled("[X]", Style::default().fg(Color::Green))
+        for completed in habit.get_completion_status(week_start, week_end) {
+            let symbol = if completed {
+                Span::styled("[X]", Style::default().fg(theme.done))
             } else {
-                Span::styled("[ ]", Style::default().fg(Color::Red))
+                Span::styled("[ ]", Style::default().fg(theme.missed))
             };
             habit_line.push(symbol);
             habit_line.push(Span::raw("  ")); // Add space between boxes
@@ -413,29 +641,214 @@ fn draw_frequency_graph<B: Backend>(
     f.render_widget(frequency_graph, area);
 }
 
-fn draw_help<B: Backend>(f: &mut Frame<B>, area: Rect) {
-    let help_text = vec![Spans::from(vec![
-        Span::styled("q", Style::default().add_modifier(Modifier::BOLD)),
-        Span::raw(": Quit | "),
-        Span::styled("a", Style::default().add_modifier(Modifier::BOLD)),
-        Span::raw(": Add | "),
-        Span::styled("Enter", Style::default().add_modifier(Modifier::BOLD)),
-        Span::raw(": Toggle | "),
-        Span::styled("d", Style::default().add_modifier(Modifier::BOLD)),
-        Span::raw(": Delete | "),
-        Span::styled("e", Style::default().add_modifier(Modifier::BOLD)),
-        Span::raw(": Edit | "),
-        Span::styled("←/→", Style::default().add_modifier(Modifier::BOLD)),
-        Span::raw(": Date | "),
-        Span::styled("↑/↓", Style::default().add_modifier(Modifier::BOLD)),
-        Span::raw(": Nav | "),
-        Span::styled("Tab", Style::default().add_modifier(Modifier::BOLD)),
-        Span::raw(": Switch tab  "),
-    ])];
+/// Width of the frequency graph's name column in terminal cells: the
+/// longest name and a space, at least 15 and at most what leaves room for
+/// the week's boxes.
+fn name_column_width(habits: &[Habit], area_width: u16) -> usize {
+    let longest = habits.iter().map(|h| h.name.width()).max().unwrap_or(0);
+    let available = (area_width as usize).saturating_sub(2 + 7 * 5);
+    (longest + 1).min(available).max(15)
+}
+
+/// `name` padded to `width` cells, or cut short with an ellipsis to leave a
+/// space. Wide characters such as CJK and emoji take two cells.
+fn fit(name: &str, width: usize) -> String {
+    let name_width = name.width();
+    if name_width < width {
+        return format!("{}{}", name, " ".repeat(width - name_width));
+    }
+    let mut cut = String::new();
+    let mut cut_width = 0;
+    for c in name.chars() {
+        let c_width = c.width().unwrap_or(0);
+        if cut_width + c_width > width.saturating_sub(2) {
+            break;
+        }
+        cut.push(c);
+        cut_width += c_width;
+    }
+    // A wide character that did not fit leaves a cell to pad.
+    format!(
+        "{}…{}",
+        cut,
+        " ".repeat(width.saturating_sub(cut_width + 1))
+    )
+}
+
+fn draw_help<B: Backend>(f: &mut Frame<B>, area: Rect, settings: &Settings) {
+    let keymap = &settings.keymap;
+    let entries = [
+        (keymap.describe(Action::Quit), "Quit"),
+        (keymap.describe(Action::Add), "Add"),
+        (keymap.describe(Action::ToggleDone), "Toggle"),
+        (keymap.describe(Action::Delete), "Delete"),
+        (keymap.describe(Action::Edit), "Edit"),
+        (
+            format!(
+                "{}/{}",
+                keymap.describe(Action::PreviousDay),
+                keymap.describe(Action::NextDay)
+            ),
+            "Date",
+        ),
+        (
+            format!(
+                "{}/{}",
+                keymap.describe(Action::Up),
+                keymap.describe(Action::Down)
+            ),
+            "Nav",
+        ),
+        (keymap.describe(Action::NextTab), "Switch tab"),
+        (keymap.describe(Action::Search), "Search"),
+        (keymap.describe(Action::ImportExport), "Import/Export"),
+    ];
+    let mut spans = Vec::new();
+    for (index, (keys, label)) in entries.into_iter().enumerate() {
+        if index > 0 {
+            spans.push(Span::raw(" | "));
+        }
+        spans.push(Span::styled(
+            keys,
+            Style::default().add_modifier(Modifier::BOLD),
+        ));
+        spans.push(Span::raw(format!(": {}", label)));
+    }
+    let help_text = vec![Spans::from(spans)];
 
     let help_paragraph = Paragraph::new(help_text)
+        .style(Style::default().fg(settings.theme.text))
         .alignment(tui::layout::Alignment::Center)
         .block(Block::default().borders(Borders::ALL));
 
     f.render_widget(help_paragraph, area);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn habit(name: &str) -> Habit {
+        Habit::new(name.to_string(), "Health".to_string(), Frequency::Daily)
+    }
+
+    /// An app state listing `labels` as todos, with `selected` selected.
+    fn listing(labels: &[&str], selected: Option<usize>) -> AppState {
+        let list_items: Vec<ListEntry> = labels
+            .iter()
+            .map(|label| {
+                ListEntry::Todo(Todo {
+                    description: label.to_string(),
+                    ..Todo::default()
+                })
+            })
+            .collect();
+        AppState {
+            selected,
+            total_items: list_items.len(),
+            list_items,
+            ..AppState::default()
+        }
+    }
+
+    const LABELS: [&str; 5] = ["Read", "Run", "Stretch", "Write", "Rest"];
+
+    #[test]
+    fn search_finds_the_next_match_ignoring_case() {
+        let mut app_state = listing(&LABELS, Some(0));
+        app_state.search = "RE".to_string();
+        assert!(app_state.search(false));
+        assert_eq!(app_state.selected, Some(2));
+        assert!(app_state.search(false));
+        assert_eq!(app_state.selected, Some(4));
+    }
+
+    #[test]
+    fn search_wraps_around() {
+        let mut app_state = listing(&LABELS, Some(4));
+        app_state.search = "re".to_string();
+        assert!(app_state.search(false));
+        assert_eq!(app_state.selected, Some(0));
+
+        // The only match is found again from itself.
+        app_state.search = "write".to_string();
+        app_state.selected = Some(3);
+        assert!(app_state.search(false));
+        assert_eq!(app_state.selected, Some(3));
+    }
+
+    #[test]
+    fn search_backwards_wraps_around() {
+        let mut app_state = listing(&LABELS, Some(2));
+        app_state.search = "re".to_string();
+        assert!(app_state.search(true));
+        assert_eq!(app_state.selected, Some(0));
+        assert!(app_state.search(true));
+        assert_eq!(app_state.selected, Some(4));
+        assert!(app_state.search(true));
+        assert_eq!(app_state.selected, Some(2));
+    }
+
+    #[test]
+    fn search_without_a_selection_starts_at_the_first_entry() {
+        let mut app_state = listing(&LABELS, None);
+        app_state.search = "read".to_string();
+        assert!(app_state.search(false));
+        assert_eq!(app_state.selected, Some(0));
+
+        let mut app_state = listing(&LABELS, None);
+        app_state.search = "rest".to_string();
+        assert!(app_state.search(true));
+        assert_eq!(app_state.selected, Some(4));
+    }
+
+    #[test]
+    fn search_without_a_match_keeps_the_selection() {
+        let mut app_state = listing(&LABELS, Some(1));
+        app_state.search = "swim".to_string();
+        assert!(!app_state.search(false));
+        assert!(!app_state.search(true));
+        assert_eq!(app_state.selected, Some(1));
+
+        app_state.search.clear();
+        assert!(!app_state.search(false));
+        let mut empty = listing(&[], None);
+        empty.search = "read".to_string();
+        assert!(!empty.search(false));
+        assert_eq!(empty.selected, None);
+    }
+
+    #[test]
+    fn name_column_fits_the_longest_name() {
+        let habits = vec![habit("Read"), habit("Practise the cello scales")];
+        assert_eq!(name_column_width(&habits, 120), 26);
+        assert_eq!(name_column_width(&[habit("Read")], 120), 15);
+        assert_eq!(name_column_width(&[], 120), 15);
+    }
+
+    #[test]
+    fn name_column_leaves_room_for_the_week() {
+        let habits = vec![habit(&"x".repeat(80))];
+        assert_eq!(name_column_width(&habits, 60), 60 - 2 - 35);
+        assert_eq!(name_column_width(&habits, 40), 15);
+    }
+
+    #[test]
+    fn long_names_are_cut_with_an_ellipsis() {
+        assert_eq!(fit("Read", 6), "Read  ");
+        assert_eq!(fit("Meditate", 6), "Medi… ");
+        assert_eq!(fit("Laufen über", 12).chars().count(), 12);
+        assert_eq!(fit("Laufen über ß", 12), "Laufen übe… ");
+    }
+
+    #[test]
+    fn wide_characters_are_measured_in_cells() {
+        let habits = vec![habit("早起きして散歩する習慣")];
+        assert_eq!(name_column_width(&habits, 120), 23);
+        assert_eq!(fit("読書", 6), "読書  ");
+        assert_eq!(fit("早起きして散歩", 8), "早起き… ");
+        // Three cells left for text fit one wide character and a space.
+        assert_eq!(fit("散歩する", 5), "散…  ");
+        assert_eq!(fit("🏃 Run every morning", 10).width(), 10);
+    }
+}
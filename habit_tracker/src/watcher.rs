@@ -0,0 +1,134 @@
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// What a file looked like at the last poll; `None` if it did not exist.
+/// The contents are hashed too, as a write within the file system's
+/// timestamp granularity, or a restore that keeps the old modification time
+/// (`cp -p`, `git checkout`), leaves the time and often the size as they were.
+#[derive(Debug, PartialEq)]
+struct FileState {
+    modified: Option<SystemTime>,
+    len: u64,
+    hash: u64,
+}
+
+/// Notices when any of a set of files is modified, created or removed by
+/// comparing them between polls.
+pub struct Watcher {
+    files: Vec<(PathBuf, Option<FileState>)>,
+}
+
+impl Watcher {
+    pub fn new(paths: Vec<PathBuf>) -> Self {
+        let files = paths
+            .into_iter()
+            .map(|path| {
+                let state = state(&path);
+                (path, state)
+            })
+            .collect();
+        Watcher { files }
+    }
+
+    /// Returns true if any file changed since the previous call.
+    pub fn poll(&mut self) -> bool {
+        let mut changed = false;
+        for (path, last_state) in &mut self.files {
+            let state = state(path);
+            if state != *last_state {
+                *last_state = state;
+                changed = true;
+            }
+        }
+        changed
+    }
+}
+
+fn state(path: &Path) -> Option<FileState> {
+    let metadata = fs::metadata(path).ok()?;
+    let contents = fs::read(path).ok()?;
+    let mut hasher = DefaultHasher::new();
+    contents.hash(&mut hasher);
+    Some(FileState {
+        modified: metadata.modified().ok(),
+        len: metadata.len(),
+        hash: hasher.finish(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "habit_tracker-watcher-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn unchanged_files_are_not_reported() {
+        let dir = scratch_dir("unchanged");
+        let path = dir.join("habits.json");
+        fs::write(&path, "[]").unwrap();
+        let mut watcher = Watcher::new(vec![path.clone(), dir.join("missing.json")]);
+        assert!(!watcher.poll());
+        fs::read(&path).unwrap();
+        assert!(!watcher.poll());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn a_modification_is_reported_once() {
+        let dir = scratch_dir("modified");
+        let path = dir.join("habits.json");
+        fs::write(&path, "[1]").unwrap();
+        let mut watcher = Watcher::new(vec![path.clone()]);
+        fs::write(&path, "[1, 2]").unwrap();
+        assert!(watcher.poll());
+        assert!(!watcher.poll());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn a_rewrite_keeping_the_time_and_size_is_reported() {
+        let dir = scratch_dir("same-time");
+        let path = dir.join("habits.json");
+        fs::write(&path, "[1]").unwrap();
+        let modified = fs::metadata(&path).unwrap().modified().unwrap();
+        let mut watcher = Watcher::new(vec![path.clone()]);
+        // As `cp -p` would leave it, or a write within the same tick.
+        fs::write(&path, "[2]").unwrap();
+        File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+        assert!(watcher.poll());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn deleting_and_recreating_a_file_are_reported() {
+        let dir = scratch_dir("recreated");
+        let path = dir.join("habits.json");
+        fs::write(&path, "[]").unwrap();
+        let mut watcher = Watcher::new(vec![path.clone()]);
+        fs::remove_file(&path).unwrap();
+        assert!(watcher.poll());
+        assert!(!watcher.poll());
+        fs::write(&path, "[]").unwrap();
+        assert!(watcher.poll());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,376 @@
+use crate::config::Webhook;
+use crate::hooks::Event;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+/// Webhooks that could not be delivered, kept in the data directory until
+/// the next session or event.
+pub const QUEUE_FILE: &str = "webhook_queue.json";
+
+const ATTEMPTS: u32 = 3;
+const FIRST_RETRY_DELAY: Duration = Duration::from_millis(200);
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// One request to make, with its body already filled in.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Delivery {
+    pub url: String,
+    pub body: String,
+}
+
+impl Delivery {
+    pub fn new(webhook: &Webhook, event: &Event) -> Self {
+        let body = match &webhook.body {
+            Some(template) => render(template, event),
+            None => {
+                let mut value = serde_json::to_value(event).unwrap_or_default();
+                value["text"] = Value::String(event.message());
+                value.to_string()
+            }
+        };
+        Delivery {
+            url: webhook.url.clone(),
+            body,
+        }
+    }
+}
+
+/// Fills `{field}` placeholders with the event's fields, escaped to sit
+/// inside a JSON string, and `{message}` with its description. Other braces
+/// are left alone, as the template is JSON itself.
+pub fn render(template: &str, event: &Event) -> String {
+    let mut fields = match serde_json::to_value(event) {
+        Ok(Value::Object(fields)) => fields,
+        _ => serde_json::Map::new(),
+    };
+    fields.insert("message".to_string(), Value::String(event.message()));
+    let mut body = template.to_string();
+    for (name, value) in fields {
+        let text = match value {
+            Value::String(s) => s,
+            other => other.to_string(),
+        };
+        let escaped = Value::String(text).to_string();
+        body = body.replace(&format!("{{{}}}", name), &escaped[1..escaped.len() - 1]);
+    }
+    body
+}
+
+enum Failure {
+    /// Worth trying again later: no connection, or a server error.
+    Temporary(String),
+    Permanent(String),
+}
+
+/// Guards the queue file within a process, and tells whether a thread is
+/// already sending it.
+pub type QueueLock = Mutex<bool>;
+
+/// Adds `deliveries` to the queue file, so they survive the process being
+/// stopped before they are sent. Returns whether the caller should start
+/// [`send_queued`], as no other thread is sending already.
+pub fn enqueue(deliveries: Vec<Delivery>, queue_file: &Path, lock: &QueueLock) -> io::Result<bool> {
+    let mut sending = lock.lock().unwrap_or_else(|e| e.into_inner());
+    if !deliveries.is_empty() {
+        let mut queue = load_queue(queue_file)?;
+        queue.extend(deliveries);
+        save_queue(queue_file, &queue)?;
+    }
+    let start = !*sending && queue_file.exists();
+    *sending |= start;
+    Ok(start)
+}
+
+/// Sends the queued webhooks, oldest first, trying each a few times. Each
+/// leaves the queue once delivered, or once it fails in a way retrying will
+/// not fix; those failing for want of a connection or a working server stay
+/// for later. Returns what went wrong, for the log.
+pub fn send_queued(queue_file: &Path, lock: &QueueLock) -> io::Result<Vec<String>> {
+    send_each(queue_file, lock).inspect_err(|_| {
+        *lock.lock().unwrap_or_else(|e| e.into_inner()) = false;
+    })
+}
+
+fn send_each(queue_file: &Path, lock: &QueueLock) -> io::Result<Vec<String>> {
+    let mut problems = Vec::new();
+    // Deliveries kept for later sit before the rest, as only this thread
+    // removes any and others only append.
+    let mut kept = 0;
+    loop {
+        let delivery = {
+            let mut sending = lock.lock().unwrap_or_else(|e| e.into_inner());
+            match load_queue(queue_file)?.into_iter().nth(kept) {
+                Some(delivery) => delivery,
+                None => {
+                    // Cleared under the lock, so a delivery queued from now
+                    // on starts a new sender.
+                    *sending = false;
+                    return Ok(problems);
+                }
+            }
+        };
+        match deliver(&delivery) {
+            Ok(()) => {}
+            Err(Failure::Temporary(reason)) => {
+                problems.push(format!("webhook to {} queued: {}", delivery.url, reason));
+                kept += 1;
+                continue;
+            }
+            Err(Failure::Permanent(reason)) => {
+                problems.push(format!("webhook to {} failed: {}", delivery.url, reason));
+            }
+        }
+        let _guard = lock.lock().unwrap_or_else(|e| e.into_inner());
+        let mut queue = load_queue(queue_file)?;
+        queue.remove(kept);
+        save_queue(queue_file, &queue)?;
+    }
+}
+
+fn load_queue(path: &Path) -> io::Result<Vec<Delivery>> {
+    match fs::read_to_string(path) {
+        Ok(contents) => Ok(serde_json::from_str(&contents)?),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(err) => Err(err),
+    }
+}
+
+/// Replaces the queue file, or removes it once empty.
+fn save_queue(path: &Path, queue: &[Delivery]) -> io::Result<()> {
+    if queue.is_empty() {
+        return match fs::remove_file(path) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+            _ => Ok(()),
+        };
+    }
+    let tmp_path = path.with_extension("json.tmp");
+    fs::write(&tmp_path, serde_json::to_string_pretty(queue)?)?;
+    fs::rename(tmp_path, path)
+}
+
+fn deliver(delivery: &Delivery) -> Result<(), Failure> {
+    let mut delay = FIRST_RETRY_DELAY;
+    let mut attempt = 1;
+    loop {
+        let result = ureq::post(&delivery.url)
+            .timeout(TIMEOUT)
+            .set("Content-Type", "application/json")
+            .send_string(&delivery.body);
+        let failure = match result {
+            Ok(_) => return Ok(()),
+            Err(ureq::Error::Status(code, _)) if code >= 500 || code == 429 => {
+                Failure::Temporary(format!("status {}", code))
+            }
+            Err(ureq::Error::Status(code, _)) => {
+                return Err(Failure::Permanent(format!("status {}", code)))
+            }
+            Err(ureq::Error::Transport(transport)) => {
+                Failure::Temporary(match std::error::Error::source(&transport) {
+                    Some(source) => format!("{}: {}", transport.kind(), source),
+                    None => transport.kind().to_string(),
+                })
+            }
+        };
+        if attempt == ATTEMPTS {
+            return Err(failure);
+        }
+        thread::sleep(delay);
+        delay *= 2;
+        attempt += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::habit::Frequency;
+    use chrono::NaiveDate;
+    use std::net::TcpListener;
+    use std::path::PathBuf;
+
+    fn milestone() -> Event {
+        Event::StreakMilestone {
+            habit: "Say \"hi\"".to_string(),
+            category: "Social".to_string(),
+            date: NaiveDate::from_ymd_opt(2024, 5, 1).unwrap(),
+            streak: 30,
+            frequency: Frequency::Daily,
+        }
+    }
+
+    fn webhook(url: &str, body: Option<&str>) -> Webhook {
+        Webhook {
+            url: url.to_string(),
+            events: Vec::new(),
+            milestones: Vec::new(),
+            body: body.map(str::to_string),
+        }
+    }
+
+    fn queue_file(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "habit_tracker-{}-{}.json",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    fn send(deliveries: Vec<Delivery>, queue: &Path, lock: &QueueLock) -> io::Result<Vec<String>> {
+        assert!(enqueue(deliveries, queue, lock)?);
+        send_queued(queue, lock)
+    }
+
+    /// Answers `count` requests on `server` with `status` and returns their
+    /// bodies.
+    fn stand_in(
+        server: tiny_http::Server,
+        count: usize,
+        status: u16,
+    ) -> thread::JoinHandle<Vec<String>> {
+        thread::spawn(move || {
+            let mut bodies = Vec::new();
+            for mut request in server.incoming_requests().take(count) {
+                let mut body = String::new();
+                request.as_reader().read_to_string(&mut body).unwrap();
+                bodies.push(body);
+                let _ = request.respond(tiny_http::Response::empty(status));
+            }
+            bodies
+        })
+    }
+
+    #[test]
+    fn template_placeholders_are_escaped_for_json() {
+        let body = render(
+            r#"{"text": "{habit}: {streak} days", "tags": {"on": "{date}"}}"#,
+            &milestone(),
+        );
+        let value: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(value["text"], "Say \"hi\": 30 days");
+        assert_eq!(value["tags"]["on"], "2024-05-01");
+    }
+
+    #[test]
+    fn default_body_is_the_event_with_a_message() {
+        let delivery = Delivery::new(&webhook("http://localhost", None), &milestone());
+        let value: Value = serde_json::from_str(&delivery.body).unwrap();
+        assert_eq!(value["event"], "streak_milestone");
+        assert_eq!(value["streak"], 30);
+        assert_eq!(value["text"], "Say \"hi\" reached a 30-day streak!");
+    }
+
+    #[test]
+    fn delivers_to_the_endpoint() {
+        let server = tiny_http::Server::http("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", server.server_addr());
+        let received = stand_in(server, 1, 200);
+        let queue = queue_file("deliver");
+        let delivery = Delivery::new(&webhook(&url, Some(r#"{"n": {streak}}"#)), &milestone());
+
+        send(vec![delivery], &queue, &Mutex::new(false)).unwrap();
+
+        assert_eq!(received.join().unwrap(), vec![r#"{"n": 30}"#]);
+        assert!(!queue.exists());
+    }
+
+    #[test]
+    fn retries_server_errors_then_queues() {
+        let server = tiny_http::Server::http("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", server.server_addr());
+        let received = stand_in(server, ATTEMPTS as usize, 503);
+        let queue = queue_file("retry");
+        let delivery = Delivery::new(&webhook(&url, None), &milestone());
+
+        let problems = send(vec![delivery.clone()], &queue, &Mutex::new(false)).unwrap();
+
+        assert_eq!(received.join().unwrap().len(), ATTEMPTS as usize);
+        assert_eq!(problems.len(), 1);
+        assert_eq!(load_queue(&queue).unwrap(), vec![delivery]);
+        fs::remove_file(queue).unwrap();
+    }
+
+    #[test]
+    fn queued_while_offline_and_sent_later() {
+        // A port nothing listens on until the stand-in starts.
+        let addr = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let url = format!("http://{}/hook", addr);
+        let queue = queue_file("offline");
+        let lock = Mutex::new(false);
+        let delivery = Delivery::new(&webhook(&url, None), &milestone());
+
+        send(vec![delivery.clone()], &queue, &lock).unwrap();
+        assert_eq!(load_queue(&queue).unwrap(), vec![delivery.clone()]);
+
+        let received = stand_in(tiny_http::Server::http(addr).unwrap(), 1, 204);
+        send(Vec::new(), &queue, &lock).unwrap();
+
+        assert_eq!(received.join().unwrap(), vec![delivery.body]);
+        assert!(!queue.exists());
+    }
+
+    #[test]
+    fn client_errors_are_dropped() {
+        let server = tiny_http::Server::http("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", server.server_addr());
+        let received = stand_in(server, 1, 400);
+        let queue = queue_file("client-error");
+
+        send(
+            vec![Delivery::new(&webhook(&url, None), &milestone())],
+            &queue,
+            &Mutex::new(false),
+        )
+        .unwrap();
+
+        assert_eq!(received.join().unwrap().len(), 1);
+        assert!(!queue.exists());
+    }
+
+    #[test]
+    fn deliveries_are_queued_before_sending() {
+        let queue = queue_file("durable");
+        let lock = Mutex::new(false);
+        let delivery = Delivery::new(&webhook("http://127.0.0.1:9/hook", None), &milestone());
+
+        assert!(enqueue(vec![delivery.clone()], &queue, &lock).unwrap());
+        assert_eq!(load_queue(&queue).unwrap(), vec![delivery.clone()]);
+        // Another thread is to send them, so this one should not.
+        assert!(!enqueue(vec![delivery.clone()], &queue, &lock).unwrap());
+        assert_eq!(load_queue(&queue).unwrap().len(), 2);
+        fs::remove_file(queue).unwrap();
+    }
+
+    #[test]
+    fn only_undelivered_entries_stay_queued() {
+        let offline = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let server = tiny_http::Server::http("127.0.0.1:0").unwrap();
+        let online = format!("http://{}/hook", server.server_addr());
+        let received = stand_in(server, 1, 200);
+        let queue = queue_file("partial");
+        let kept = Delivery::new(
+            &webhook(&format!("http://{}/", offline), None),
+            &milestone(),
+        );
+        let sent = Delivery::new(&webhook(&online, None), &milestone());
+
+        send(vec![kept.clone(), sent], &queue, &Mutex::new(false)).unwrap();
+
+        assert_eq!(received.join().unwrap().len(), 1);
+        assert_eq!(load_queue(&queue).unwrap(), vec![kept]);
+        fs::remove_file(queue).unwrap();
+    }
+}